@@ -0,0 +1,316 @@
+use chrono::{DateTime, Duration, Utc};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::legend::{draw_generated_legend, LegendCaption, LegendConfig, LegendRegion, LegendScale};
+use crate::precipitation::RainRateGrid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccumulationLevel {
+    pub color: [u8; 3],
+    pub mm: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccumulationConfig {
+    // Longest time a frame stands for on either side, longer gaps between frames count as missing data
+    pub max_gap_minutes: f64,
+    // Color scale of the accumulation image, a pixel takes the color of the highest level it reaches
+    pub levels: Vec<AccumulationLevel>,
+}
+
+impl Default for AccumulationConfig {
+    fn default() -> Self {
+        let level = |mm: f64, color: [u8; 3]| AccumulationLevel { color, mm };
+        AccumulationConfig {
+            max_gap_minutes: 15.0,
+            levels: vec![
+                level(0.1, [200, 255, 200]),
+                level(1.0, [120, 230, 120]),
+                level(2.0, [40, 180, 40]),
+                level(5.0, [100, 190, 255]),
+                level(10.0, [0, 90, 255]),
+                level(25.0, [255, 0, 255]),
+                level(50.0, [200, 0, 0]),
+                level(100.0, [110, 0, 0]),
+                level(250.0, [60, 0, 60]),
+            ],
+        }
+    }
+}
+
+impl AccumulationConfig {
+    pub fn color_of(&self, mm: f32) -> Option<Rgba<u8>> {
+        self.levels
+            .iter()
+            .rev()
+            .find(|level| mm as f64 >= level.mm)
+            .map(|level| Rgba([level.color[0], level.color[1], level.color[2], 255]))
+    }
+}
+
+// Rain depth in mm of every pixel of the radar area over one time window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulation {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub frame_count: usize,
+    // Part of the window covered by the added frames, the rest was missing
+    pub covered: Duration,
+    pub width: u32,
+    pub height: u32,
+    data: Vec<f32>,
+}
+
+impl Accumulation {
+    pub fn window(&self) -> Duration {
+        self.end - self.start
+    }
+
+    pub fn coverage(&self) -> f64 {
+        let window = self.window().num_seconds();
+        if window > 0 {
+            self.covered.num_seconds() as f64 / window as f64
+        } else {
+            0.0
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> f32 {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            self.data[(y as u32 * self.width + x as u32) as usize]
+        } else {
+            0.0
+        }
+    }
+
+    // Row-major depths, row 0 is the top of the image
+    pub fn values(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn max(&self) -> f32 {
+        self.data.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+impl Accumulation {
+    pub fn new(end: DateTime<Utc>, window: Duration, size: (u32, u32)) -> Self {
+        Accumulation {
+            start: end - window,
+            end,
+            frame_count: 0,
+            covered: Duration::zero(),
+            width: size.0,
+            height: size.1,
+            data: vec![0.0; (size.0 * size.1) as usize],
+        }
+    }
+
+    // Add the rain of one frame held for the given time, see frame_durations
+    pub fn add_frame(&mut self, rain_rate: &RainRateGrid, held: Duration) {
+        if held <= Duration::zero() {
+            return;
+        }
+        self.frame_count += 1;
+        self.covered += held;
+        let hours = held.num_milliseconds() as f32 / 3_600_000.0;
+        for y in 0..self.height.min(rain_rate.height) {
+            for x in 0..self.width.min(rain_rate.width) {
+                self.data[(y * self.width + x) as usize] += rain_rate.get(x as i32, y as i32) * hours;
+            }
+        }
+    }
+}
+
+// Time every frame stands for within the window ending at end, zero for frames outside of it. A frame holds its
+// rate for half the time to each neighbouring frame, at most max_gap_minutes / 2 per side and never past the window
+// bounds; the first and last frame of the window extend towards the bounds under the same cap. Gaps from missing
+// frames thus stay uncovered instead of being filled with a stale rate. Times must be sorted.
+pub fn frame_durations(times: &[DateTime<Utc>], end: DateTime<Utc>, window: Duration, config: &AccumulationConfig) -> Vec<Duration> {
+    let start = end - window;
+    let half_gap = Duration::milliseconds((config.max_gap_minutes * 30_000.0) as i64);
+    let inside = |time: &DateTime<Utc>| *time > start && *time <= end;
+    times
+        .iter()
+        .enumerate()
+        .map(|(index, time)| {
+            if !inside(time) {
+                return Duration::zero();
+            }
+            let previous = index.checked_sub(1).map(|previous| times[previous]).filter(inside);
+            let next = times.get(index + 1).copied().filter(inside);
+            let before = match previous {
+                Some(previous) => (*time - previous) / 2,
+                None => *time - start,
+            };
+            let after = match next {
+                Some(next) => (next - *time) / 2,
+                None => end - *time,
+            };
+            before.min(half_gap) + after.min(half_gap)
+        })
+        .collect()
+}
+
+// Accumulation of frames already in memory, sorted by time
+pub fn accumulate(
+    frames: &[(DateTime<Utc>, &RainRateGrid)],
+    end: DateTime<Utc>,
+    window: Duration,
+    config: &AccumulationConfig,
+    size: (u32, u32),
+) -> Accumulation {
+    let times: Vec<DateTime<Utc>> = frames.iter().map(|(time, _)| *time).collect();
+    let mut accumulation = Accumulation::new(end, window, size);
+    for ((_, rain_rate), held) in frames.iter().zip(frame_durations(&times, end, window, config)) {
+        accumulation.add_frame(rain_rate, held);
+    }
+    accumulation
+}
+
+// Color the radar area of the background by depth, with a scale and the window in the legend region
+pub fn draw_accumulation(
+    img: &mut RgbaImage,
+    accumulation: &Accumulation,
+    config: &AccumulationConfig,
+    legend: Option<(&LegendRegion, &LegendConfig)>,
+) {
+    for y in 0..accumulation.height.min(img.height()) {
+        for x in 0..accumulation.width.min(img.width()) {
+            if let Some(color) = config.color_of(accumulation.get(x as i32, y as i32)) {
+                img.put_pixel(x, y, color);
+            }
+        }
+    }
+    let Some((region, legend_config)) = legend else {
+        return;
+    };
+
+    let caption = LegendCaption {
+        lines: vec![
+            format!("Rainfall {}h", accumulation.window().num_minutes() as f64 / 60.0),
+            String::from("ending"),
+            accumulation.end.format("%Y-%m-%d %H:%M UTC").to_string(),
+            format!("{} frames, {:.0}% covered", accumulation.frame_count, accumulation.coverage() * 100.0),
+            format!("max {:.1} mm", accumulation.max()),
+        ],
+    };
+    let scale = LegendScale {
+        unit: String::from("mm"),
+        swatches: config
+            .levels
+            .iter()
+            .rev()
+            .map(|level| (Rgba([level.color[0], level.color[1], level.color[2], 255]), level.mm.to_string()))
+            .collect(),
+    };
+    draw_generated_legend(img, region, &scale, legend_config, &caption);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridFormat {
+    // Comma separated rows, top row first
+    Csv,
+    // Numpy array of little endian f32 with shape (height, width)
+    Npy,
+}
+
+pub fn write_grid(values: &[f32], size: (u32, u32), format: GridFormat, output_path: &str) -> Result<(), Box<dyn Error>> {
+    let (width, height) = size;
+    if values.len() != (width * height) as usize {
+        return Err(format!("Grid of {} values does not match the size {}x{}", values.len(), width, height).into());
+    }
+    let file = File::create(output_path).map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+    let mut writer = BufWriter::new(file);
+    match format {
+        GridFormat::Csv => {
+            for row in values.chunks(width.max(1) as usize) {
+                let line: Vec<String> = row.iter().map(|value| format!("{:.3}", value)).collect();
+                writeln!(writer, "{}", line.join(","))?;
+            }
+        }
+        GridFormat::Npy => {
+            // Format version 1.0: magic, version, header length, then the header dict padded to a multiple of 64
+            let dict = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}", height, width);
+            let unpadded = 10 + dict.len() + 1;
+            let header = format!("{}{}\n", dict, " ".repeat((64 - unpadded % 64) % 64));
+            writer.write_all(b"\x93NUMPY\x01\x00")?;
+            writer.write_all(&(header.len() as u16).to_le_bytes())?;
+            writer.write_all(header.as_bytes())?;
+            for value in values {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+impl GridFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GridFormat::Csv => "csv",
+            GridFormat::Npy => "npy",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 4, 24, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn frame_durations_cap_gaps_and_stop_at_the_window() {
+        let config = AccumulationConfig::default();
+        let times = [at(10, 50), at(11, 10), at(11, 16), at(11, 40), at(11, 46), at(12, 10)];
+        let durations = frame_durations(&times, at(12, 0), Duration::hours(1), &config);
+        // 7.5 minutes at most per side, frames outside of 11:00 to 12:00 hold nothing
+        let expected = [0.0, 10.5, 10.5, 10.5, 10.5, 0.0];
+        let minutes: Vec<f64> = durations.iter().map(|held| held.num_seconds() as f64 / 60.0).collect();
+        assert_eq!(minutes, expected);
+
+        // Without gaps the frames cover the whole window
+        let config = AccumulationConfig { max_gap_minutes: 60.0, ..config };
+        let total: Duration = frame_durations(&times, at(12, 0), Duration::hours(1), &config).into_iter().sum();
+        assert_eq!(total, Duration::hours(1));
+    }
+
+    #[test]
+    fn npy_grid_has_an_aligned_version_1_header() {
+        let path = std::env::temp_dir().join(format!("storm_detect_grid_{}.npy", std::process::id()));
+        let path = path.to_str().unwrap();
+        let values = [0.0, 1.5, 2.0, 3.25, 4.0, 5.0];
+        write_grid(&values, (3, 2), GridFormat::Npy, path).unwrap();
+        let bytes = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+
+        let data: Vec<f32> = bytes[10 + header_len..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        assert_eq!(data, values);
+    }
+
+    #[test]
+    fn grid_of_the_wrong_size_is_rejected() {
+        assert!(write_grid(&[0.0; 5], (3, 2), GridFormat::Csv, "unused.csv").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+use rusttype::Scale;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use crate::draw::{draw_filled_circle, draw_polyline, fill_rect, Stroke};
+use crate::render::{rgba, RenderStyle, FONT};
+
+const CAPTION_MARGIN: i32 = 6;
+const CAPTION_PADDING: i32 = 3;
+// Gif quantization speed from 1 (best) to 30 (fastest), 10 is the usual trade-off
+const GIF_SPEED: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    // Format by file extension, animated png may use either .png or .apng
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationConfig {
+    pub frame_delay_ms: u32,
+    // The last frame is held longer so the loop restart is easy to spot
+    pub last_frame_delay_ms: u32,
+    // Number of plays, 0 loops forever
+    pub loop_count: u32,
+    pub caption: bool,
+    pub caption_font_size: f32,
+    // Lines through the previous centers of every tracked storm, up to trail_length positions back
+    pub trails: bool,
+    pub trail_length: usize,
+    pub trail_color: [u8; 3],
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        AnimationConfig {
+            frame_delay_ms: 500,
+            last_frame_delay_ms: 1500,
+            loop_count: 0,
+            caption: true,
+            caption_font_size: 16.0,
+            trails: false,
+            trail_length: 10,
+            trail_color: [255, 255, 255],
+        }
+    }
+}
+
+// Caption in the top left corner on a translucent box in the label halo color
+pub fn draw_caption(img: &mut RgbaImage, text: &str, config: &AnimationConfig, style: &RenderStyle) {
+    let scale = Scale::uniform(config.caption_font_size);
+    let (width, height) = text_size(scale, &FONT, text);
+    fill_rect(
+        img,
+        CAPTION_MARGIN - CAPTION_PADDING,
+        CAPTION_MARGIN - CAPTION_PADDING,
+        width + 2 * CAPTION_PADDING,
+        height + 2 * CAPTION_PADDING,
+        rgba(&style.label_halo_color),
+        style.label_background_opacity,
+    );
+    draw_text_mut(img, rgba(&style.label_color), CAPTION_MARGIN, CAPTION_MARGIN, scale, &FONT, text);
+}
+
+// Trails given as the centers of a track up to its position in the current frame
+pub fn draw_trails(img: &mut RgbaImage, trails: &[&[(f32, f32)]], config: &AnimationConfig, style: &RenderStyle) {
+    let color = rgba(&config.trail_color);
+    let stroke = Stroke {
+        width: style.line_width,
+        color,
+        antialias: style.antialias,
+    };
+    for centers in trails {
+        let trail = &centers[centers.len().saturating_sub(config.trail_length + 1)..];
+        if trail.len() < 2 {
+            continue;
+        }
+        draw_polyline(img, trail, false, &stroke);
+        for &position in &trail[..trail.len() - 1] {
+            draw_filled_circle(img, position, style.center_radius * 0.75, color, style.antialias);
+        }
+    }
+}
+
+// Encode the frames into one animation, all frames need the size of the first
+pub fn write_animation(frames: &[RgbaImage], format: AnimationFormat, config: &AnimationConfig, output_path: &str) -> Result<(), Box<dyn Error>> {
+    let first = frames.first().ok_or("No frames to animate")?;
+    if frames.iter().any(|frame| frame.dimensions() != first.dimensions()) {
+        return Err("All animation frames must have the same size".into());
+    }
+    let delays: Vec<u32> = (0..frames.len())
+        .map(|index| if index + 1 == frames.len() { config.last_frame_delay_ms } else { config.frame_delay_ms })
+        .collect();
+    let file = File::create(output_path).map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+    let writer = BufWriter::new(file);
+
+    match format {
+        AnimationFormat::Gif => {
+            let mut encoder = GifEncoder::new_with_speed(writer, GIF_SPEED);
+            let repeat = match config.loop_count {
+                0 => Repeat::Infinite,
+                count => Repeat::Finite(count.min(u16::MAX as u32) as u16),
+            };
+            encoder.set_repeat(repeat)?;
+            for (frame, &delay) in frames.iter().zip(&delays) {
+                encoder.encode_frame(Frame::from_parts(frame.clone(), 0, 0, Delay::from_numer_denom_ms(delay, 1)))?;
+            }
+        }
+        AnimationFormat::Apng => {
+            let mut encoder = png::Encoder::new(writer, first.width(), first.height());
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frames.len() as u32, config.loop_count)?;
+            let mut writer = encoder.write_header()?;
+            for (frame, &delay) in frames.iter().zip(&delays) {
+                writer.set_frame_delay(delay.min(u16::MAX as u32) as u16, 1000)?;
+                writer.write_image_data(frame.as_raw())?;
+            }
+            writer.finish()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::{AnimationDecoder, Rgba};
+
+    fn frames() -> Vec<RgbaImage> {
+        [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]].into_iter().map(|color| RgbaImage::from_pixel(8, 6, Rgba(color))).collect()
+    }
+
+    fn config() -> AnimationConfig {
+        AnimationConfig { frame_delay_ms: 200, last_frame_delay_ms: 900, loop_count: 3, ..AnimationConfig::default() }
+    }
+
+    fn write(name: &str, format: AnimationFormat, config: &AnimationConfig) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("storm_detect_animation_{}_{}", std::process::id(), name));
+        write_animation(&frames(), format, config, &path.to_string_lossy()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bytes
+    }
+
+    // Loop count of the NETSCAPE2.0 application extension, 0 loops forever
+    fn gif_loop_count(bytes: &[u8]) -> Option<u16> {
+        let start = bytes.windows(11).position(|window| window == b"NETSCAPE2.0")? + 11;
+        Some(u16::from_le_bytes([bytes[start + 2], bytes[start + 3]]))
+    }
+
+    #[test]
+    fn gif_holds_the_last_frame_and_loops_as_configured() {
+        let bytes = write("frames.gif", AnimationFormat::Gif, &config());
+        let decoded = GifDecoder::new(bytes.as_slice()).unwrap().into_frames().collect_frames().unwrap();
+        let delays: Vec<(u32, u32)> = decoded.iter().map(|frame| frame.delay().numer_denom_ms()).collect();
+        assert_eq!(delays, [(200, 1), (200, 1), (900, 1)]);
+        assert_eq!(*decoded[1].buffer().get_pixel(0, 0), Rgba([0, 255, 0, 255]));
+        assert_eq!(gif_loop_count(&bytes), Some(3));
+
+        let forever = write("forever.gif", AnimationFormat::Gif, &AnimationConfig { loop_count: 0, ..config() });
+        assert_eq!(gif_loop_count(&forever), Some(0));
+    }
+
+    #[test]
+    fn apng_holds_the_last_frame_and_loops_as_configured() {
+        let bytes = write("frames.png", AnimationFormat::Apng, &config());
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (3, 3));
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let mut delays = Vec::new();
+        for _ in 0..3 {
+            reader.next_frame(&mut buffer).unwrap();
+            let frame = reader.info().frame_control.unwrap();
+            delays.push((frame.delay_num, frame.delay_den));
+        }
+        assert_eq!(delays, [(200, 1000), (200, 1000), (900, 1000)]);
+        assert_eq!(&buffer[..4], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(AnimationFormat::from_path("out/storms.GIF"), Some(AnimationFormat::Gif));
+        assert_eq!(AnimationFormat::from_path("storms.png"), Some(AnimationFormat::Apng));
+        assert_eq!(AnimationFormat::from_path("storms.apng"), Some(AnimationFormat::Apng));
+        assert_eq!(AnimationFormat::from_path("storms.mp4"), None);
+        assert_eq!(AnimationFormat::from_path("storms"), None);
+    }
+
+    #[test]
+    fn frames_of_different_sizes_are_rejected() {
+        let frames = [RgbaImage::new(8, 6), RgbaImage::new(6, 8)];
+        let error = write_animation(&frames, AnimationFormat::Gif, &config(), "unused.gif").unwrap_err();
+        assert_eq!(error.to_string(), "All animation frames must have the same size");
+    }
+}
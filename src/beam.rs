@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+// Standard refraction bends the beam as if the earth radius were 4/3 of its actual value
+const EFFECTIVE_RADIUS_FACTOR: f64 = 4.0 / 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageRange {
+    // Distance from the image center is the slant range along the beam, as on a PPI display
+    #[default]
+    Slant,
+    // Distance from the image center is already the ground range
+    Ground,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BeamConfig {
+    pub enabled: bool,
+    pub image_range: ImageRange,
+    // Elevation of the scan by product number found in the image name, e.g. "19" for ..._PPI_02_19.png
+    pub product_elevations: BTreeMap<String, f64>,
+    // Used when the image name yields no elevation
+    pub default_elevation_deg: f64,
+    // Storms further out or with the beam center above this height are flagged as beyond reliable range
+    pub max_reliable_range_km: f64,
+    pub max_beam_height_km: f64,
+}
+
+impl Default for BeamConfig {
+    fn default() -> Self {
+        BeamConfig {
+            enabled: false,
+            image_range: ImageRange::default(),
+            product_elevations: BTreeMap::from([(String::from("19"), 1.5)]),
+            default_elevation_deg: 0.5,
+            max_reliable_range_km: 200.0,
+            max_beam_height_km: 6.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BeamGeometry {
+    pub elevation_deg: f64,
+    pub slant_range_km: f64,
+    pub ground_range_km: f64,
+    // Height of the beam center above sea level
+    pub beam_height_km: f64,
+    pub beyond_reliable_range: bool,
+}
+
+// Elevation of the scan from the image name: an explicit angle like "0.5°" or "0.5deg", a product number of
+// product_elevations, or the tenths of a degree ending a CINRAD name like ..._R_10_230_15.200.png
+pub fn elevation_from_name(image_path: &str, config: &BeamConfig) -> Option<f64> {
+    let stem = Path::new(image_path).file_stem()?.to_str()?;
+    let tokens: Vec<&str> = stem.split(['_', '-', ' ']).collect();
+
+    let explicit = tokens.iter().find_map(|token| {
+        let token = token.to_ascii_lowercase();
+        let angle = token.strip_suffix('°').or_else(|| token.strip_suffix("deg"))?;
+        angle.parse::<f64>().ok()
+    });
+    if explicit.is_some() {
+        return explicit;
+    }
+
+    // Numbers may carry a suffix like the .200 of 15.200
+    let stem_tokens = tokens.iter().map(|token| token.split('.').next().unwrap_or(token));
+    if let Some(elevation) = stem_tokens.clone().rev().find_map(|token| config.product_elevations.get(token)) {
+        return Some(*elevation);
+    }
+
+    // Resolution, range and elevation follow the R product letter, each in the units of the name
+    let product = tokens.iter().rposition(|token| *token == "R")?;
+    let elevation_tenths: f64 = stem_tokens.clone().nth(product + 3)?.parse().ok()?;
+    Some(elevation_tenths / 10.0)
+}
+
+// Beam geometry at the given image distance from the radar, station altitude in metres above sea level
+pub fn beam_geometry(image_distance_km: f64, elevation_deg: f64, station_altitude_m: f64, config: &BeamConfig) -> BeamGeometry {
+    let effective_radius = EARTH_RADIUS_KM * EFFECTIVE_RADIUS_FACTOR;
+    let elevation = elevation_deg.to_radians();
+    let (slant_range_km, ground_range_km) = match config.image_range {
+        ImageRange::Slant => {
+            let slant = image_distance_km;
+            let height = beam_height_above_radar(slant, elevation, effective_radius);
+            let ground = effective_radius * (slant * elevation.cos() / (effective_radius + height)).asin();
+            (slant, ground)
+        }
+        ImageRange::Ground => {
+            // Law of sines in the triangle of earth center, radar and beam point, angle is the one at the earth center
+            let ground = image_distance_km;
+            let angle = ground / effective_radius;
+            let slant = effective_radius * angle.sin() / (elevation + angle).cos();
+            (slant, ground)
+        }
+    };
+    let beam_height_km = beam_height_above_radar(slant_range_km, elevation, effective_radius) + station_altitude_m / 1000.0;
+    BeamGeometry {
+        elevation_deg,
+        slant_range_km,
+        ground_range_km,
+        beam_height_km,
+        beyond_reliable_range: slant_range_km > config.max_reliable_range_km || beam_height_km > config.max_beam_height_km,
+    }
+}
+
+fn beam_height_above_radar(slant_range: f64, elevation: f64, effective_radius: f64) -> f64 {
+    (slant_range * slant_range + effective_radius * effective_radius + 2.0 * slant_range * effective_radius * elevation.sin()).sqrt()
+        - effective_radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-3
+    }
+
+    #[test]
+    fn beam_height_and_ground_range_from_the_slant_range() {
+        let config = BeamConfig::default();
+        // (slant range km, elevation deg, beam height km, ground range km)
+        let cases = [(0.0, 0.5, 0.0, 0.0), (100.0, 0.5, 1.4611, 99.9813), (200.0, 0.5, 4.0987, 199.9144), (50.0, 1.5, 1.4559, 49.9746)];
+        for (slant, elevation, height, ground) in cases {
+            let geometry = beam_geometry(slant, elevation, 0.0, &config);
+            assert!(close(geometry.beam_height_km, height), "{} km at {}°: {:?}", slant, elevation, geometry);
+            assert!(close(geometry.ground_range_km, ground), "{} km at {}°: {:?}", slant, elevation, geometry);
+        }
+    }
+
+    #[test]
+    fn ground_image_range_is_converted_back_to_the_slant_range() {
+        let config = BeamConfig { image_range: ImageRange::Ground, ..BeamConfig::default() };
+        let geometry = beam_geometry(100.0, 0.5, 0.0, &config);
+        assert!(close(geometry.slant_range_km, 100.0187));
+        assert!(close(geometry.beam_height_km, 1.4615));
+        assert_eq!(geometry.ground_range_km, 100.0);
+    }
+
+    #[test]
+    fn station_altitude_and_limits_flag_the_reliable_range() {
+        let config = BeamConfig::default();
+        let geometry = beam_geometry(100.0, 0.5, 500.0, &config);
+        assert!(close(geometry.beam_height_km, 1.9611));
+        assert!(!geometry.beyond_reliable_range);
+        assert!(beam_geometry(210.0, 0.5, 0.0, &config).beyond_reliable_range);
+        // 200 km at 1.5° puts the beam above 6 km
+        assert!(beam_geometry(200.0, 1.5, 0.0, &config).beyond_reliable_range);
+    }
+
+    #[test]
+    fn elevation_is_read_from_the_image_name() {
+        let config = BeamConfig::default();
+        let cases = [
+            ("data/radar/Z9200_20240424134800Z_PPI_02_19.png", Some(1.5)),
+            ("Z_RADR_I_Z9200_20240424134800_P_DOR_SA_R_10_230_15.200.png", Some(1.5)),
+            ("Z_RADR_I_Z9200_20240424134800_P_DOR_SA_R_10_230_24.200.png", Some(2.4)),
+            ("scan_2.4deg.png", Some(2.4)),
+            ("scan_0.5°_19.png", Some(0.5)),
+            ("Z9200_20240424134800.png", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(elevation_from_name(name, &config), expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,558 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::config::{Config, StationConfig};
+use crate::districts::{district_summaries, District};
+use crate::geo::storm_footprint;
+use crate::pipeline::Detection;
+use crate::poi::proximity_report;
+use crate::severe::severe_indicators;
+use crate::storm::Storm;
+use crate::svg::escape_xml;
+use crate::tracking::StormMotion;
+use crate::utils::{azimuth_to_direction, parse_frame_time};
+
+const CAP_NAMESPACE: &str = "urn:oasis:names:tc:emergency:cap:1.2";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapStatus {
+    #[default]
+    Actual,
+    Exercise,
+    System,
+    Test,
+    Draft,
+}
+
+impl CapStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CapStatus::Actual => "Actual",
+            CapStatus::Exercise => "Exercise",
+            CapStatus::System => "System",
+            CapStatus::Test => "Test",
+            CapStatus::Draft => "Draft",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CapConfig {
+    pub enabled: bool,
+    // One <identifier>.xml file per message
+    pub output_dir: String,
+    pub sender: String,
+    pub status: CapStatus,
+    // A storm alerts when its maximum reaches min_dbz or at least min_area_km2 of it reach area_dbz
+    pub min_dbz: u32,
+    pub area_dbz: u32,
+    pub min_area_km2: f64,
+    // With districts loaded, only storms over one of them alert
+    pub require_district: bool,
+    // With districts loaded, storms whose echoes over a district reach this also alert, whatever their size; 0 for off
+    pub district_min_dbz: u32,
+    // Storms reaching this maximum are Extreme, storms with a severe core Severe, others Moderate
+    pub extreme_dbz: u32,
+    pub expires_minutes: u32,
+}
+
+impl Default for CapConfig {
+    fn default() -> Self {
+        CapConfig {
+            enabled: false,
+            output_dir: String::from("data/output/cap"),
+            sender: String::from("storm_detect@localhost"),
+            status: CapStatus::default(),
+            min_dbz: 55,
+            area_dbz: 50,
+            min_area_km2: 20.0,
+            require_district: false,
+            district_min_dbz: 0,
+            extreme_dbz: 65,
+            expires_minutes: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Alert,
+    Update,
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Moderate,
+    Severe,
+    Extreme,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    // The storm is over a district or inside or arriving at a point of interest
+    Immediate,
+    Expected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Certainty {
+    // Tracked over consecutive frames
+    Observed,
+    // Seen in a single frame, which may still be clutter
+    Likely,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapInfo {
+    pub event: String,
+    pub urgency: Urgency,
+    pub severity: Severity,
+    pub certainty: Certainty,
+    pub expires: DateTime<Utc>,
+    pub headline: String,
+    pub description: String,
+    pub parameters: Vec<(String, String)>,
+    pub area_desc: String,
+    // Closed ring of (latitude, longitude) around the storm footprint
+    pub polygon: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapMessage {
+    pub identifier: String,
+    pub sent: DateTime<Utc>,
+    pub msg_type: MessageType,
+    pub source: String,
+    pub note: Option<String>,
+    // Earlier messages of the same storm as sender,identifier,sent
+    pub references: Vec<String>,
+    // Cancel messages carry no info block
+    pub info: Option<CapInfo>,
+}
+
+// A storm is followed by its track id, or by its storm id in an untracked frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum AlertKey {
+    Track(u32),
+    Storm(u32),
+}
+
+impl AlertKey {
+    fn id(&self) -> u32 {
+        match *self {
+            AlertKey::Track(id) | AlertKey::Storm(id) => id,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            AlertKey::Track(id) => format!("Storm track {}", id),
+            AlertKey::Storm(id) => format!("Storm {}", id),
+        }
+    }
+}
+
+// Storms alerted in earlier frames with the references of their messages
+#[derive(Debug, Clone, Default)]
+pub struct CapAlerter {
+    active: BTreeMap<AlertKey, Vec<String>>,
+}
+
+impl CapAlerter {
+    pub fn new() -> Self {
+        CapAlerter::default()
+    }
+
+    // Alert for storms newly meeting the criteria, update for storms alerted before and cancel for alerted storms
+    // that no longer meet them or are gone. Frames must be passed in time order.
+    pub fn process(&mut self, detection: &Detection, districts: &[District], config: &Config) -> Vec<CapMessage> {
+        let cap = &config.cap;
+        // Messages are dated by the scan they describe, so rerunning a sequence gives the same identifiers
+        let sent = parse_frame_time(&detection.source).unwrap_or_else(Utc::now);
+        let summaries = district_summaries(&detection.storms, districts, config.station.km_per_pixel);
+        let proximity = proximity_report(&detection.storms, &detection.motion, &config.poi, &config.station);
+
+        let mut messages = Vec::new();
+        let mut alerted = BTreeMap::new();
+        for (index, storm) in detection.storms.iter().enumerate() {
+            let motion = detection.motion.get(index);
+            let key = match motion {
+                Some(motion) => AlertKey::Track(motion.track_id),
+                None => AlertKey::Storm(storm.storm_id),
+            };
+            // Districts the storm overlaps with the maximum of its echoes over each
+            let overlaps: Vec<(&str, u32)> = summaries
+                .iter()
+                .filter_map(|summary| {
+                    let district_storm = summary.storms.iter().find(|district_storm| district_storm.storm_id == storm.storm_id)?;
+                    Some((summary.district.as_str(), district_storm.max_dbz))
+                })
+                .collect();
+            if !meets_criteria(storm, &overlaps, !districts.is_empty(), config) {
+                continue;
+            }
+            let storm_districts: Vec<&str> = overlaps.iter().map(|(district, _)| *district).collect();
+            let near_poi = proximity.iter().any(|entry| entry.storm_id == storm.storm_id && entry.is_alert());
+            let references = self.active.remove(&key).unwrap_or_default();
+            let identifier = message_identifier(&config.station, sent, key.id());
+            let info = storm_info(storm, motion, &storm_districts, near_poi, sent, config);
+            messages.push(CapMessage {
+                identifier: identifier.clone(),
+                sent,
+                msg_type: if references.is_empty() { MessageType::Alert } else { MessageType::Update },
+                source: detection.source.clone(),
+                note: None,
+                references: references.clone(),
+                info: Some(info),
+            });
+            let mut chain = references;
+            chain.push(message_reference(&cap.sender, &identifier, sent));
+            alerted.insert(key, chain);
+        }
+
+        for (key, references) in std::mem::replace(&mut self.active, alerted) {
+            messages.push(CapMessage {
+                identifier: format!("{}-cancel", message_identifier(&config.station, sent, key.id())),
+                sent,
+                msg_type: MessageType::Cancel,
+                source: detection.source.clone(),
+                note: Some(format!("{} no longer meets the alert criteria", key.describe())),
+                references,
+                info: None,
+            });
+        }
+        messages
+    }
+}
+
+impl CapMessage {
+    pub fn to_xml(&self, config: &CapConfig, station: &StationConfig) -> String {
+        let mut xml = String::new();
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(xml, r#"<alert xmlns="{}">"#, CAP_NAMESPACE);
+        write_element(&mut xml, 1, "identifier", &self.identifier);
+        write_element(&mut xml, 1, "sender", &config.sender);
+        write_element(&mut xml, 1, "sent", &cap_time(self.sent));
+        write_element(&mut xml, 1, "status", config.status.as_str());
+        let msg_type = match self.msg_type {
+            MessageType::Alert => "Alert",
+            MessageType::Update => "Update",
+            MessageType::Cancel => "Cancel",
+        };
+        write_element(&mut xml, 1, "msgType", msg_type);
+        write_element(&mut xml, 1, "source", &self.source);
+        write_element(&mut xml, 1, "scope", "Public");
+        if let Some(note) = &self.note {
+            write_element(&mut xml, 1, "note", note);
+        }
+        if !self.references.is_empty() {
+            write_element(&mut xml, 1, "references", &self.references.join(" "));
+        }
+        if let Some(info) = &self.info {
+            write_info(&mut xml, info, station);
+        }
+        xml.push_str("</alert>\n");
+        xml
+    }
+}
+
+// Write every message as <identifier>.xml into the output directory of the config
+pub fn write_cap_messages(messages: &[CapMessage], config: &Config) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let output_dir = Path::new(&config.cap.output_dir);
+    fs::create_dir_all(output_dir).map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
+    let mut paths = Vec::with_capacity(messages.len());
+    for message in messages {
+        let path = output_dir.join(format!("{}.xml", message.identifier));
+        fs::write(&path, message.to_xml(&config.cap, &config.station)).map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+// Overlaps are the districts of the storm with the maximum of its echoes over each
+fn meets_criteria(storm: &Storm, overlaps: &[(&str, u32)], have_districts: bool, config: &Config) -> bool {
+    let cap = &config.cap;
+    if cap.require_district && have_districts && overlaps.is_empty() {
+        return false;
+    }
+    let over_district = cap.district_min_dbz > 0 && overlaps.iter().any(|&(_, max_dbz)| max_dbz >= cap.district_min_dbz);
+    over_district
+        || storm.max_intensity >= cap.min_dbz || area_at(storm, cap.area_dbz, config.station.km_per_pixel) >= cap.min_area_km2
+}
+
+fn storm_info(
+    storm: &Storm,
+    motion: Option<&StormMotion>,
+    storm_districts: &[&str],
+    near_poi: bool,
+    sent: DateTime<Utc>,
+    config: &Config,
+) -> CapInfo {
+    let (cap, station) = (&config.cap, &config.station);
+    let severe = severe_indicators(storm, &config.severe, station.km_per_pixel);
+    let severity = if storm.max_intensity >= cap.extreme_dbz {
+        Severity::Extreme
+    } else if severe.severe_core {
+        Severity::Severe
+    } else {
+        Severity::Moderate
+    };
+    let urgency = if near_poi || !storm_districts.is_empty() { Urgency::Immediate } else { Urgency::Expected };
+    let velocity = motion.and_then(|motion| motion.velocity);
+    let certainty = if velocity.is_some() { Certainty::Observed } else { Certainty::Likely };
+    let event = if severity >= Severity::Severe { "Severe Thunderstorm" } else { "Thunderstorm" };
+    let location = format!("{:.0} km {} of {}", storm.distance, azimuth_to_direction(storm.direction), station.name);
+    let area = area_at(storm, cap.area_dbz, station.km_per_pixel);
+
+    let mut description = format!(
+        "Storm with maximum reflectivity {} dBZ, {:.1} km2 at or above {} dBZ, hail probability {:.0}%.",
+        storm.max_intensity,
+        area,
+        cap.area_dbz,
+        severe.hail_probability * 100.0
+    );
+    let mut parameters = vec![
+        (String::from("maxReflectivityDBZ"), storm.max_intensity.to_string()),
+        (format!("areaAbove{}DBZKm2", cap.area_dbz), format!("{:.1}", area)),
+        (String::from("hailProbability"), format!("{:.2}", severe.hail_probability)),
+    ];
+    if let Some(motion) = motion.filter(|motion| motion.velocity.is_some()) {
+        let speed = motion.speed_kmh(station.km_per_pixel).unwrap_or(0.0);
+        match motion.heading_deg() {
+            Some(heading) => {
+                let _ = write!(description, " Moving {} at {:.0} km/h.", azimuth_to_direction(heading), speed);
+                parameters.push((String::from("motionHeadingDeg"), format!("{:.0}", heading)));
+            }
+            None => description.push_str(" Stationary."),
+        }
+        parameters.push((String::from("motionSpeedKmh"), format!("{:.1}", speed)));
+    }
+    if !storm_districts.is_empty() {
+        let _ = write!(description, " Affecting {}.", storm_districts.join(", "));
+    }
+
+    CapInfo {
+        event: event.to_string(),
+        urgency,
+        severity,
+        certainty,
+        expires: sent + Duration::minutes(cap.expires_minutes as i64),
+        headline: format!("{} {} dBZ {}", event, storm.max_intensity, location),
+        description,
+        parameters,
+        area_desc: if storm_districts.is_empty() { location } else { storm_districts.join(", ") },
+        polygon: storm_footprint(storm, station),
+    }
+}
+
+fn write_info(xml: &mut String, info: &CapInfo, station: &StationConfig) {
+    xml.push_str("  <info>\n");
+    write_element(xml, 2, "language", "en-US");
+    write_element(xml, 2, "category", "Met");
+    write_element(xml, 2, "event", &info.event);
+    write_element(xml, 2, "responseType", "Monitor");
+    let urgency = match info.urgency {
+        Urgency::Immediate => "Immediate",
+        Urgency::Expected => "Expected",
+    };
+    let severity = match info.severity {
+        Severity::Extreme => "Extreme",
+        Severity::Severe => "Severe",
+        Severity::Moderate => "Moderate",
+    };
+    let certainty = match info.certainty {
+        Certainty::Observed => "Observed",
+        Certainty::Likely => "Likely",
+    };
+    write_element(xml, 2, "urgency", urgency);
+    write_element(xml, 2, "severity", severity);
+    write_element(xml, 2, "certainty", certainty);
+    write_element(xml, 2, "expires", &cap_time(info.expires));
+    write_element(xml, 2, "senderName", &format!("{} ({})", station.name, station.code));
+    write_element(xml, 2, "headline", &info.headline);
+    write_element(xml, 2, "description", &info.description);
+    for (name, value) in &info.parameters {
+        xml.push_str("    <parameter>\n");
+        write_element(xml, 3, "valueName", name);
+        write_element(xml, 3, "value", value);
+        xml.push_str("    </parameter>\n");
+    }
+    xml.push_str("    <area>\n");
+    write_element(xml, 3, "areaDesc", &info.area_desc);
+    if info.polygon.len() >= 4 {
+        let points: Vec<String> = info.polygon.iter().map(|(latitude, longitude)| format!("{:.4},{:.4}", latitude, longitude)).collect();
+        write_element(xml, 3, "polygon", &points.join(" "));
+    }
+    xml.push_str("    </area>\n");
+    xml.push_str("  </info>\n");
+}
+
+fn write_element(xml: &mut String, depth: usize, name: &str, text: &str) {
+    let _ = writeln!(xml, "{}<{}>{}</{}>", "  ".repeat(depth), name, escape_xml(text), name);
+}
+
+// CAP times carry an explicit offset, "Z" is not allowed and UTC is written as -00:00 (CAP 1.2 3.3.2)
+fn cap_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S-00:00").to_string()
+}
+
+fn message_identifier(station: &StationConfig, sent: DateTime<Utc>, key: u32) -> String {
+    format!("{}-{}-{}", station.code, sent.format("%Y%m%d%H%M%S"), key)
+}
+
+fn message_reference(sender: &str, identifier: &str, sent: DateTime<Utc>) -> String {
+    format!("{},{},{}", sender, identifier, cap_time(sent))
+}
+
+fn area_at(storm: &Storm, dbz: u32, km_per_pixel: f64) -> f64 {
+    storm.pixels.iter().filter(|pixel| pixel.intensity >= dbz).count() as f64 * km_per_pixel * km_per_pixel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block, detection_of, storm_from_pixels};
+    use nalgebra::Point2;
+
+    fn frame(minute: u32) -> String {
+        format!("Z_RADR_I_Z9200_2024042413{:02}_P_DOR_SA_R_10_230_15.200.png", minute)
+    }
+
+    // A square of size x size pixels from (310, 310), every pixel at max_intensity
+    fn storm(storm_id: u32, max_intensity: u32, size: i32) -> Storm {
+        Storm { distance: 12.0, direction: 135.0, ..storm_from_pixels(storm_id, &block(310, 310, size, size, max_intensity)) }
+    }
+
+    fn tracked(track_id: u32) -> Vec<StormMotion> {
+        vec![StormMotion { track_id, velocity: Some((10.0, 0.0)) }]
+    }
+
+    // A square district around the storms of the helper
+    fn district() -> District {
+        let ring = [(300.0, 300.0), (330.0, 300.0), (330.0, 330.0), (300.0, 330.0)].map(|(x, y)| Point2::new(x, y));
+        District::new(String::from("Center"), vec![ring.to_vec()])
+    }
+
+    #[test]
+    fn tracked_storm_goes_from_alert_over_update_to_cancel() {
+        let config = Config::default();
+        let mut alerter = CapAlerter::new();
+
+        let first = alerter.process(&detection_of(&frame(48), vec![storm(1, 60, 2)], tracked(7)), &[], &config);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].msg_type, MessageType::Alert);
+        assert_eq!(first[0].identifier, "Z9200-20240424134800-7");
+        assert!(first[0].references.is_empty());
+
+        // The storm id changes between frames, the track id does not
+        let second = alerter.process(&detection_of(&frame(54), vec![storm(4, 60, 2)], tracked(7)), &[], &config);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].msg_type, MessageType::Update);
+        assert_eq!(second[0].references, ["storm_detect@localhost,Z9200-20240424134800-7,2024-04-24T13:48:00-00:00"]);
+
+        let third = alerter.process(&detection_of(&frame(59), vec![storm(2, 40, 2)], tracked(7)), &[], &config);
+        assert_eq!(third.len(), 1);
+        let cancel = &third[0];
+        assert_eq!(cancel.msg_type, MessageType::Cancel);
+        assert_eq!(cancel.identifier, "Z9200-20240424135900-7-cancel");
+        assert_eq!(cancel.references.len(), 2);
+        assert_eq!(cancel.references[1], "storm_detect@localhost,Z9200-20240424135400-7,2024-04-24T13:54:00-00:00");
+        assert_eq!(cancel.note.as_deref(), Some("Storm track 7 no longer meets the alert criteria"));
+        assert!(cancel.info.is_none());
+        let xml = cancel.to_xml(&config.cap, &config.station);
+        assert!(xml.contains("<msgType>Cancel</msgType>"));
+        assert!(!xml.contains("<info>"));
+    }
+
+    #[test]
+    fn untracked_storm_is_cancelled_by_its_storm_id() {
+        let config = Config::default();
+        let mut alerter = CapAlerter::new();
+        alerter.process(&detection_of(&frame(48), vec![storm(2, 60, 2)], Vec::new()), &[], &config);
+        let messages = alerter.process(&detection_of(&frame(54), Vec::new(), Vec::new()), &[], &config);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].note.as_deref(), Some("Storm 2 no longer meets the alert criteria"));
+    }
+
+    #[test]
+    fn severity_urgency_and_certainty_follow_the_storm() {
+        let config = Config::default();
+        let sent = parse_frame_time(&frame(48)).unwrap();
+        let motion = StormMotion { track_id: 1, velocity: Some((10.0, 0.0)) };
+        // A 5 x 5 storm covers about 18 km2, enough for a severe core
+        let cases = [
+            (storm(1, 66, 1), None, &[][..], Severity::Extreme, Urgency::Expected, Certainty::Likely),
+            (storm(1, 58, 5), Some(&motion), &["Center"][..], Severity::Severe, Urgency::Immediate, Certainty::Observed),
+            (storm(1, 58, 2), Some(&motion), &[][..], Severity::Moderate, Urgency::Expected, Certainty::Observed),
+        ];
+        for (storm, motion, districts, severity, urgency, certainty) in cases {
+            let info = storm_info(&storm, motion, districts, false, sent, &config);
+            assert_eq!((info.severity, info.urgency, info.certainty), (severity, urgency, certainty), "{} dBZ", storm.max_intensity);
+            assert_eq!(info.expires, sent + Duration::minutes(30));
+        }
+        let near_poi = storm_info(&storm(1, 58, 2), None, &[], true, sent, &config);
+        assert_eq!(near_poi.urgency, Urgency::Immediate);
+    }
+
+    #[test]
+    fn polygon_is_a_closed_ring_of_latitude_longitude_pairs() {
+        let config = Config::default();
+        let messages = CapAlerter::new().process(&detection_of(&frame(48), vec![storm(1, 60, 3)], Vec::new()), &[], &config);
+        let xml = messages[0].to_xml(&config.cap, &config.station);
+        let polygon = xml.split("<polygon>").nth(1).and_then(|rest| rest.split("</polygon>").next()).unwrap();
+        let points: Vec<(f64, f64)> = polygon
+            .split(' ')
+            .map(|pair| {
+                let (latitude, longitude) = pair.split_once(',').unwrap();
+                (latitude.parse().unwrap(), longitude.parse().unwrap())
+            })
+            .collect();
+        assert!(points.len() >= 4);
+        assert_eq!(points.first(), points.last());
+        // The storm lies south east of the station at 23.0039 N, 113.355 E
+        assert!(points.iter().all(|&(latitude, longitude)| latitude < 23.0039 && latitude > 22.9 && longitude > 113.355 && longitude < 113.5));
+    }
+
+    #[test]
+    fn text_is_escaped_in_the_xml() {
+        let mut config = Config::default();
+        config.station.name = String::from("A&B <\"Radar\">");
+        let messages = CapAlerter::new().process(&detection_of(&frame(48), vec![storm(1, 60, 2)], Vec::new()), &[], &config);
+        let xml = messages[0].to_xml(&config.cap, &config.station);
+        assert!(xml.contains("<senderName>A&amp;B &lt;&quot;Radar&quot;&gt; (Z9200)</senderName>"));
+        assert!(!xml.contains("A&B"));
+    }
+
+    #[test]
+    fn district_overlap_can_trigger_an_alert() {
+        let mut config = Config::default();
+        // 50 dBZ over 4 pixels stays below min_dbz and min_area_km2
+        let weak = || detection_of(&frame(48), vec![storm(1, 50, 2)], Vec::new());
+        assert!(CapAlerter::new().process(&weak(), &[district()], &config).is_empty());
+
+        config.cap.district_min_dbz = 45;
+        let messages = CapAlerter::new().process(&weak(), &[district()], &config);
+        assert_eq!(messages.len(), 1);
+        let info = messages[0].info.as_ref().unwrap();
+        assert_eq!((info.area_desc.as_str(), info.urgency), ("Center", Urgency::Immediate));
+        // Without districts loaded the setting has no effect
+        assert!(CapAlerter::new().process(&weak(), &[], &config).is_empty());
+    }
+
+    #[test]
+    fn require_district_drops_storms_outside_of_every_district() {
+        let mut config = Config::default();
+        config.cap.require_district = true;
+        let far = District::new(String::from("Far"), vec![vec![Point2::new(0.0, 0.0), Point2::new(10.0, 0.0), Point2::new(10.0, 10.0)]]);
+        let strong = detection_of(&frame(48), vec![storm(1, 60, 2)], Vec::new());
+        assert!(CapAlerter::new().process(&strong, &[far], &config).is_empty());
+        assert_eq!(CapAlerter::new().process(&strong, &[district()], &config).len(), 1);
+    }
+}
@@ -0,0 +1,803 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::error::Error;
+use std::fs;
+use std::io::stdout;
+use std::path::{Path, PathBuf};
+use test_storm_detect::accumulation::{frame_durations, write_grid, Accumulation, GridFormat};
+use test_storm_detect::cap::{write_cap_messages, CapAlerter};
+use test_storm_detect::config::Config;
+use test_storm_detect::districts::{load_districts, District};
+use test_storm_detect::legend::LegendMode;
+use test_storm_detect::pipeline::{
+    decode_reflectivity, detect_storms, render_accumulation, render_animation, render_detection, render_detection_svg, Detection,
+};
+use test_storm_detect::precipitation::RainRateGrid;
+#[cfg(feature = "sqlite")]
+use test_storm_detect::storage::{DailyMaximum, StoredStorm};
+use test_storm_detect::storage::Storage;
+use test_storm_detect::report::{write_csv, write_geojson, write_json_array, write_ndjson, write_report, DetectionResult, ReportFormat};
+use test_storm_detect::tracking::StormTracker;
+use test_storm_detect::webhook::{WebhookEndpoint, WebhookNotifier};
+use test_storm_detect::utils::{
+    download_image, generate_url, generate_url_for, parse_frame_time, print_district_summary, print_poi_alerts, print_storm_hierarchy,
+    print_storms,
+};
+
+// Process exit codes, success also means at least one storm was found
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_NO_STORMS: i32 = 2;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Identify active storms in weather radar reflectivity images")]
+pub struct Cli {
+    /// TOML config file, defaults to $STORM_CONFIG or ./storm_detect.toml when present
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+    /// Named profile of the config file, defaults to $STORM_PROFILE or the file's default_profile
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Detect storms in a single radar image
+    Detect {
+        /// Input radar image
+        image: String,
+        /// Write the analysis result image to this path
+        #[arg(long)]
+        output: Option<String>,
+        /// Write the analysis overlay as svg to this path
+        #[arg(long)]
+        svg: Option<String>,
+        #[command(flatten)]
+        detect_args: DetectArgs,
+    },
+    /// Download a radar image from the image server
+    Fetch {
+        /// UTC scan time as yyyyMMddHHmm, the latest available scan when omitted
+        #[arg(long)]
+        time: Option<String>,
+        /// Local path of the downloaded image, defaults to data/input/<image name>
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Detect storms in every png image of a directory
+    Batch {
+        /// Directory holding the input radar images
+        dir: String,
+        /// Write one analysis result image per input into this directory
+        #[arg(long)]
+        output_dir: Option<String>,
+        /// Write one svg overlay per input into this directory
+        #[arg(long)]
+        svg_dir: Option<String>,
+        #[command(flatten)]
+        detect_args: DetectArgs,
+    },
+    /// Render a sequence of radar images into an animated gif or png
+    Animate {
+        /// Input radar images or directories of them, frames are ordered by the scan time in their names
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Animation file, .gif for gif and .png or .apng for animated png
+        #[arg(long)]
+        output: String,
+        /// Delay between frames in milliseconds
+        #[arg(long)]
+        delay: Option<u32>,
+        /// Draw the trail of previous centers of every tracked storm
+        #[arg(long)]
+        trails: bool,
+        #[command(flatten)]
+        detect_args: DetectArgs,
+    },
+    /// Accumulate the rainfall of a sequence of radar images over windows ending at the last scan
+    Accumulate {
+        /// Input radar images or directories of them, the scan time is taken from their names
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Window length in hours, may be repeated
+        #[arg(long = "window", default_values_t = [1, 3, 24])]
+        windows: Vec<u32>,
+        /// Directory for the accumulation images and raw grids
+        #[arg(long, default_value = "data/output")]
+        output_dir: String,
+        /// Raw grid format, rain depth in mm per pixel of the radar area
+        #[arg(long, value_enum, default_value_t = GridArg::Npy)]
+        grid: GridArg,
+        /// No-echo base image used for overlay removal and as image background
+        #[arg(long)]
+        base: Option<String>,
+    },
+    /// Query the storms stored in the SQLite database for the configured station
+    #[cfg(feature = "sqlite")]
+    Query {
+        /// SQLite database, defaults to storage.database of the config
+        #[arg(long)]
+        database: Option<String>,
+        /// Start of the time range as UTC yyyyMMddHHmm
+        #[arg(long, required_unless_present = "daily_max")]
+        from: Option<String>,
+        /// End of the time range as UTC yyyyMMddHHmm, exclusive
+        #[arg(long, required_unless_present = "daily_max")]
+        to: Option<String>,
+        /// List the strongest storm of every day instead of the storms of a time range
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        daily_max: bool,
+        /// Print json instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+    Geojson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LegendArg {
+    Copy,
+    Generated,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GridArg {
+    Csv,
+    Npy,
+}
+
+#[derive(Debug, Args)]
+pub struct DetectArgs {
+    /// No-echo base image used for overlay removal and as result background
+    #[arg(long)]
+    base: Option<String>,
+    /// Storm list output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+    /// Minimum storm size in pixels
+    #[arg(long)]
+    min_size: Option<usize>,
+    /// Minimum maximum reflectivity of a storm in dBZ
+    #[arg(long)]
+    min_intensity: Option<u32>,
+    /// Maximum pixel gap between pixels of the same storm
+    #[arg(long)]
+    adjacent: Option<i32>,
+    /// Legend panel of the result image: copied from the input, generated from the palette, or left as in the base image
+    #[arg(long, value_enum)]
+    legend: Option<LegendArg>,
+    /// Reference the base image from svg output instead of embedding it, the legend panel is then left out
+    #[arg(long)]
+    svg_link_base: bool,
+    /// Include raw storm pixels in json output
+    #[arg(long)]
+    include_pixels: bool,
+    /// GeoJSON file of district polygons to report storm overlap for
+    #[arg(long)]
+    districts: Option<String>,
+    /// Write CAP 1.2 alert messages for storms meeting the alert criteria into this directory
+    #[arg(long)]
+    cap_dir: Option<String>,
+    /// Post new, intensifying and point of interest storm events to this url, may be repeated
+    #[arg(long = "webhook")]
+    webhooks: Vec<String>,
+    /// Store every processed frame and its storms in this SQLite database, needs the sqlite feature
+    #[arg(long)]
+    database: Option<String>,
+}
+
+impl DetectArgs {
+    // Command line flags are the last override layer, on top of the loaded config
+    fn apply_overrides(&self, config: &mut Config) -> Result<(), Box<dyn Error>> {
+        if let Some(base) = &self.base {
+            config.station.base_image = base.clone();
+        }
+        if let Some(min_size) = self.min_size {
+            config.detection.min_size = min_size;
+        }
+        if let Some(min_intensity) = self.min_intensity {
+            config.detection.min_intensity = min_intensity;
+        }
+        if let Some(adjacent) = self.adjacent {
+            config.detection.adjacent_threshold = adjacent;
+        }
+        if let Some(legend) = self.legend {
+            config.legend.mode = legend_mode(legend);
+        }
+        if let Some(districts) = &self.districts {
+            config.districts.file = districts.clone();
+        }
+        if let Some(cap_dir) = &self.cap_dir {
+            config.cap.enabled = true;
+            config.cap.output_dir = cap_dir.clone();
+        }
+        if !self.webhooks.is_empty() {
+            config.webhook.enabled = true;
+            config.webhook.endpoints.extend(self.webhooks.iter().map(|url| WebhookEndpoint::new(url)));
+        }
+        if let Some(database) = &self.database {
+            config.storage.database = database.clone();
+        }
+        config.validate()?;
+        Ok(())
+    }
+}
+
+pub fn run(cli: Cli) -> Result<i32, Box<dyn Error>> {
+    let mut config = Config::load(cli.config.as_deref(), cli.profile.as_deref())?;
+    match cli.command {
+        Command::Detect { image, output, svg, detect_args } => {
+            detect_args.apply_overrides(&mut config)?;
+            run_detect(&image, output.as_deref(), svg.as_deref(), &detect_args, &config)
+        }
+        Command::Fetch { time, output } => run_fetch(time.as_deref(), output.as_deref(), &config),
+        Command::Batch { dir, output_dir, svg_dir, detect_args } => {
+            detect_args.apply_overrides(&mut config)?;
+            run_batch(&dir, output_dir.as_deref(), svg_dir.as_deref(), &detect_args, &config)
+        }
+        Command::Animate { inputs, output, delay, trails, detect_args } => {
+            if let Some(delay) = delay {
+                config.animation.frame_delay_ms = delay;
+            }
+            if trails {
+                config.animation.trails = true;
+            }
+            detect_args.apply_overrides(&mut config)?;
+            run_animate(&inputs, &output, &detect_args, &config)
+        }
+        Command::Accumulate { inputs, windows, output_dir, grid, base } => {
+            if let Some(base) = base {
+                config.station.base_image = base;
+            }
+            run_accumulate(&inputs, &windows, &output_dir, grid_format(grid), &config)
+        }
+        #[cfg(feature = "sqlite")]
+        Command::Query { database, from, to, daily_max, json } => {
+            if let Some(database) = database {
+                config.storage.database = database;
+            }
+            run_query(from.as_deref(), to.as_deref(), daily_max, json, &config)
+        }
+    }
+}
+
+fn run_detect(
+    image: &str,
+    output: Option<&str>,
+    svg: Option<&str>,
+    detect_args: &DetectArgs,
+    config: &Config,
+) -> Result<i32, Box<dyn Error>> {
+    let districts = load_districts(&config.districts, &config.station)?;
+    let detection = detect_storms(image, config)?;
+    if let Some(output_path) = output {
+        render_detection(&detection, config, output_path)?;
+    }
+    if let Some(svg_path) = svg {
+        render_detection_svg(&detection, config, svg_path, !detect_args.svg_link_base)?;
+    }
+    emit_cap_alerts(&mut CapAlerter::new(), &detection, &districts, config)?;
+    notify_webhooks(webhook_notifier(config)?.as_mut(), &detection, config);
+    if let Some(storage) = Storage::from_config(config)?.as_mut() {
+        storage.store_detection(&detection, config)?;
+    }
+
+    match detect_args.format {
+        OutputFormat::Table => print_frame(&detection, &districts, config),
+        format => write_report(&detection.to_result(config, &districts, detect_args.include_pixels), report_format(format), stdout())?,
+    }
+
+    Ok(storms_exit_code(detection.storms.len()))
+}
+
+fn run_fetch(time: Option<&str>, output: Option<&str>, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let url = match time {
+        Some(time) => {
+            let scan_time = NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M")
+                .map_err(|e| format!("Invalid scan time {} (expected yyyyMMddHHmm): {}", time, e))?
+                .and_utc();
+            generate_url_for(&scan_time, &config.station)
+        }
+        None => generate_url(&config.station),
+    };
+    let output_path = match output {
+        Some(output) => output.to_string(),
+        None => {
+            let image_name = url.rsplit('/').next().unwrap_or("radar.png");
+            format!("data/input/{}", image_name)
+        }
+    };
+
+    download_image(&url, &output_path).map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    println!("{} downloaded to {} at {}", url, output_path, Utc::now());
+    Ok(EXIT_SUCCESS)
+}
+
+fn run_batch(
+    dir: &str,
+    output_dir: Option<&str>,
+    svg_dir: Option<&str>,
+    detect_args: &DetectArgs,
+    config: &Config,
+) -> Result<i32, Box<dyn Error>> {
+    let images = png_images(dir)?;
+    if images.is_empty() {
+        return Err(format!("No png images found in {}", dir).into());
+    }
+    for dir in [output_dir, svg_dir].into_iter().flatten() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let districts = load_districts(&config.districts, &config.station)?;
+    // Images are taken in name order, which is scan time order for the names of one station
+    let mut tracker = StormTracker::new(&config.tracking, config.station.km_per_pixel);
+    let mut alerter = CapAlerter::new();
+    let mut notifier = webhook_notifier(config)?;
+    let mut storage = Storage::from_config(config)?;
+    let mut results = Vec::new();
+    let mut failed = 0;
+    let mut storm_count = 0;
+    for image in &images {
+        let image_path = image.to_string_lossy();
+        match process_batch_image(&image_path, output_dir, svg_dir, detect_args, config) {
+            Ok(mut detection) => {
+                detection.track(&mut tracker);
+                if !publish_frame(&detection, &mut alerter, notifier.as_mut(), storage.as_mut(), &districts, config) {
+                    failed += 1;
+                }
+                storm_count += detection.storms.len();
+                if detect_args.format == OutputFormat::Table {
+                    println!("== {} ==", image_path);
+                    print_frame(&detection, &districts, config);
+                } else {
+                    results.push(detection.to_result(config, &districts, detect_args.include_pixels));
+                }
+            }
+            Err(e) => {
+                eprintln!("Error processing {}: {}", image_path, e);
+                failed += 1;
+            }
+        }
+    }
+    write_batch_results(&results, detect_args.format)?;
+
+    if failed > 0 {
+        eprintln!("{} of {} images failed", failed, images.len());
+        return Ok(EXIT_ERROR);
+    }
+    Ok(storms_exit_code(storm_count))
+}
+
+fn run_animate(inputs: &[String], output: &str, detect_args: &DetectArgs, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let images = frame_images(inputs)?;
+    if images.is_empty() {
+        return Err("No png images found to animate".into());
+    }
+    let districts = load_districts(&config.districts, &config.station)?;
+
+    // Like batch, a failing frame is reported and counted but the other frames are still processed
+    let mut detections = Vec::new();
+    let mut failed = 0;
+    for image in &images {
+        let image_path = image.to_string_lossy();
+        match detect_storms(&image_path, config) {
+            Ok(detection) => detections.push(detection),
+            Err(e) => {
+                eprintln!("Error processing {}: {}", image_path, e);
+                failed += 1;
+            }
+        }
+    }
+    if detections.is_empty() {
+        return Err("None of the frames could be processed".into());
+    }
+    let mut tracker = StormTracker::new(&config.tracking, config.station.km_per_pixel);
+    let mut alerter = CapAlerter::new();
+    let mut notifier = webhook_notifier(config)?;
+    let mut storage = Storage::from_config(config)?;
+    for detection in &mut detections {
+        detection.track(&mut tracker);
+        if !publish_frame(detection, &mut alerter, notifier.as_mut(), storage.as_mut(), &districts, config) {
+            failed += 1;
+        }
+    }
+    render_animation(&detections, config, output)?;
+
+    if detect_args.format == OutputFormat::Table {
+        for detection in &detections {
+            println!("== {} ==", detection.source);
+            print_frame(detection, &districts, config);
+        }
+        println!("{} of {} frames written to {}", detections.len(), images.len(), output);
+    } else {
+        let results: Vec<DetectionResult> = detections
+            .iter()
+            .map(|detection| detection.to_result(config, &districts, detect_args.include_pixels))
+            .collect();
+        write_batch_results(&results, detect_args.format)?;
+    }
+
+    if failed > 0 {
+        eprintln!("{} of {} frames failed", failed, images.len());
+        return Ok(EXIT_ERROR);
+    }
+    let storm_count: usize = detections.iter().map(|detection| detection.storms.len()).sum();
+    Ok(storms_exit_code(storm_count))
+}
+
+fn run_accumulate(inputs: &[String], windows: &[u32], output_dir: &str, grid_format: GridFormat, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let mut frames = Vec::new();
+    for image in frame_images(inputs)? {
+        let image_path = image.to_string_lossy().into_owned();
+        match parse_frame_time(&image_path) {
+            Some(frame_time) => frames.push((frame_time, image_path)),
+            None => eprintln!("Skipping {}: no scan time in its name", image_path),
+        }
+    }
+    let end = frames.last().map(|(frame_time, _)| *frame_time).ok_or("No png images with a scan time found")?;
+    fs::create_dir_all(output_dir)?;
+
+    // Every frame is decoded once and added to each window it falls into
+    let times: Vec<DateTime<Utc>> = frames.iter().map(|(frame_time, _)| *frame_time).collect();
+    let size = config.station.radar_area();
+    let mut accumulations: Vec<(Accumulation, Vec<Duration>)> = windows
+        .iter()
+        .map(|&hours| {
+            let window = Duration::hours(hours as i64);
+            (Accumulation::new(end, window, size), frame_durations(&times, end, window, &config.accumulation))
+        })
+        .collect();
+    let mut failed = 0;
+    for (index, (_, image_path)) in frames.iter().enumerate() {
+        if accumulations.iter().all(|(_, durations)| durations[index].is_zero()) {
+            continue;
+        }
+        let rain_rate = match decode_reflectivity(image_path, config) {
+            Ok(reflectivity) => RainRateGrid::from_reflectivity(&reflectivity, &config.precipitation),
+            Err(e) => {
+                eprintln!("Skipping frame {}: {}", image_path, e);
+                failed += 1;
+                continue;
+            }
+        };
+        for (accumulation, durations) in &mut accumulations {
+            accumulation.add_frame(&rain_rate, durations[index]);
+        }
+    }
+
+    for ((accumulation, _), hours) in accumulations.iter().zip(windows) {
+        let stem = format!("rain_{}h_{}", hours, end.format("%Y%m%d%H%M"));
+        let image_path = Path::new(output_dir).join(format!("{}.png", stem));
+        let grid_path = Path::new(output_dir).join(format!("{}.{}", stem, grid_format.extension()));
+        render_accumulation(accumulation, config, &image_path.to_string_lossy())?;
+        write_grid(accumulation.values(), size, grid_format, &grid_path.to_string_lossy())?;
+        println!(
+            "{}h until {}: {} frames, {:.0}% covered, max {:.1} mm -> {}",
+            hours,
+            end.format("%Y-%m-%d %H:%M UTC"),
+            accumulation.frame_count,
+            accumulation.coverage() * 100.0,
+            accumulation.max(),
+            image_path.display()
+        );
+    }
+
+    if failed > 0 {
+        eprintln!("{} of {} frames failed", failed, frames.len());
+        return Ok(EXIT_ERROR);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+#[cfg(feature = "sqlite")]
+fn run_query(from: Option<&str>, to: Option<&str>, daily_max: bool, json: bool, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let storage = Storage::from_config(config)?.ok_or("No database given, set storage.database or pass --database")?;
+    let station_code = &config.station.code;
+    if daily_max {
+        let maxima = storage.strongest_per_day(station_code)?;
+        if json {
+            serde_json::to_writer_pretty(stdout(), &maxima)?;
+            println!();
+        } else {
+            print_daily_maxima(&maxima);
+        }
+        return Ok(storms_exit_code(maxima.len()));
+    }
+
+    let (Some(from), Some(to)) = (from, to) else {
+        return Err("--from and --to are required without --daily-max".into());
+    };
+    let storms = storage.storms_between(station_code, query_time(from)?, query_time(to)?)?;
+    if json {
+        serde_json::to_writer_pretty(stdout(), &storms)?;
+        println!();
+    } else {
+        print_stored_storms(&storms);
+    }
+    Ok(storms_exit_code(storms.len()))
+}
+
+#[cfg(feature = "sqlite")]
+fn query_time(time: &str) -> Result<DateTime<Utc>, Box<dyn Error>> {
+    let time = NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M").map_err(|e| format!("Invalid time {} (expected yyyyMMddHHmm): {}", time, e))?;
+    Ok(time.and_utc())
+}
+
+#[cfg(feature = "sqlite")]
+fn print_stored_storms(storms: &[StoredStorm]) {
+    println!(
+        "{:<18} {:<6} {:<7} {:<20} {:<14} {:<11} {:<10} {:<10}",
+        "Frame Time (UTC)", "ID", "Track", "Max Intensity (dBZ)", "Type", "Area (km2)", "Lat", "Lon"
+    );
+    for storm in storms {
+        println!(
+            "{:<18} {:<6} {:<7} {:<20} {:<14} {:<11.1} {:<10.4} {:<10.4}",
+            storm.frame_time.map(|time| time.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default(),
+            storm.storm_id,
+            storm.track_id.map(|track_id| track_id.to_string()).unwrap_or_default(),
+            storm.max_intensity,
+            storm.storm_type,
+            storm.area_km2,
+            storm.latitude,
+            storm.longitude
+        );
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn print_daily_maxima(maxima: &[DailyMaximum]) {
+    println!(
+        "{:<12} {:<8} {:<18} {:<20} {:<14} {:<11} {:<10} {:<10}",
+        "Day (UTC)", "Frames", "Time", "Max Intensity (dBZ)", "Type", "Area (km2)", "Lat", "Lon"
+    );
+    for maximum in maxima {
+        let storm = &maximum.strongest;
+        println!(
+            "{:<12} {:<8} {:<18} {:<20} {:<14} {:<11.1} {:<10.4} {:<10.4}",
+            maximum.day,
+            maximum.frame_count,
+            storm.frame_time.map(|time| time.format("%H:%M").to_string()).unwrap_or_default(),
+            storm.max_intensity,
+            storm.storm_type,
+            storm.area_km2,
+            storm.latitude,
+            storm.longitude
+        );
+    }
+}
+
+// Png images of the inputs, directories expanded. Images without a scan time in their name follow the timed ones.
+fn frame_images(inputs: &[String]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut images = Vec::new();
+    for input in inputs {
+        if Path::new(input).is_dir() {
+            images.extend(png_images(input)?);
+        } else {
+            images.push(PathBuf::from(input));
+        }
+    }
+    images.sort_by_cached_key(|image| {
+        let frame_time = parse_frame_time(&image.to_string_lossy());
+        (frame_time.is_none(), frame_time, image.clone())
+    });
+    images.dedup();
+    Ok(images)
+}
+
+// Png images of the directory, sorted by name
+fn png_images(dir: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read directory {}: {}", dir, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png")))
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
+fn process_batch_image(
+    image_path: &str,
+    output_dir: Option<&str>,
+    svg_dir: Option<&str>,
+    detect_args: &DetectArgs,
+    config: &Config,
+) -> Result<Detection, Box<dyn Error>> {
+    let detection = detect_storms(image_path, config)?;
+    let file_name = Path::new(image_path).file_name().ok_or("Invalid image path")?;
+    if let Some(output_dir) = output_dir {
+        let output_path = Path::new(output_dir).join(file_name);
+        render_detection(&detection, config, &output_path.to_string_lossy())?;
+    }
+    if let Some(svg_dir) = svg_dir {
+        let svg_path = Path::new(svg_dir).join(file_name).with_extension("svg");
+        render_detection_svg(&detection, config, &svg_path.to_string_lossy(), !detect_args.svg_link_base)?;
+    }
+    Ok(detection)
+}
+
+// Storm table of one frame followed by its point of interest, district and storm hierarchy tables
+fn print_frame(detection: &Detection, districts: &[District], config: &Config) {
+    print_storms(&detection.storms, config);
+    print_poi_alerts(&detection.storms, &detection.motion, config);
+    print_district_summary(&detection.storms, districts, config);
+    if let Some(hierarchy) = &detection.hierarchy {
+        print_storm_hierarchy(hierarchy);
+    }
+}
+
+// Alerts, webhooks and storage of one frame of a batch or animation. Errors are reported and the frame
+// counts as failed, the remaining frames are still processed
+fn publish_frame(
+    detection: &Detection,
+    alerter: &mut CapAlerter,
+    notifier: Option<&mut WebhookNotifier>,
+    storage: Option<&mut Storage>,
+    districts: &[District],
+    config: &Config,
+) -> bool {
+    let mut succeeded = true;
+    if let Err(e) = emit_cap_alerts(alerter, detection, districts, config) {
+        eprintln!("Error writing alerts of {}: {}", detection.source, e);
+        succeeded = false;
+    }
+    notify_webhooks(notifier, detection, config);
+    if let Some(Err(e)) = storage.map(|storage| storage.store_detection(detection, config)) {
+        eprintln!("Error storing {}: {}", detection.source, e);
+        succeeded = false;
+    }
+    succeeded
+}
+
+// Write the CAP messages of the frame when alerts are enabled
+fn emit_cap_alerts(alerter: &mut CapAlerter, detection: &Detection, districts: &[District], config: &Config) -> Result<(), Box<dyn Error>> {
+    if config.cap.enabled {
+        let messages = alerter.process(detection, districts, config);
+        write_cap_messages(&messages, config)?;
+    }
+    Ok(())
+}
+
+fn webhook_notifier(config: &Config) -> Result<Option<WebhookNotifier>, Box<dyn Error>> {
+    if config.webhook.enabled && !config.webhook.endpoints.is_empty() {
+        Ok(Some(WebhookNotifier::new(&config.webhook)?))
+    } else {
+        Ok(None)
+    }
+}
+
+// Post the events of the frame, failed and rate limited posts are reported but do not stop the run
+fn notify_webhooks(notifier: Option<&mut WebhookNotifier>, detection: &Detection, config: &Config) {
+    let Some(notifier) = notifier else {
+        return;
+    };
+    let events = notifier.events(detection, config);
+    let report = notifier.notify(&events);
+    if report.rate_limited > 0 {
+        eprintln!("{} webhook posts of {} dropped by the rate limit", report.rate_limited, detection.source);
+    }
+}
+
+fn write_batch_results(results: &[DetectionResult], format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    match format {
+        OutputFormat::Table => Ok(()),
+        OutputFormat::Json => write_json_array(results, stdout()),
+        // A stream of frames is emitted as one json document per line
+        OutputFormat::Ndjson => write_ndjson(results, stdout()),
+        OutputFormat::Csv => write_csv(results, stdout(), true),
+        OutputFormat::Geojson => write_geojson(results, stdout()),
+    }
+}
+
+fn report_format(format: OutputFormat) -> ReportFormat {
+    match format {
+        OutputFormat::Json | OutputFormat::Table => ReportFormat::Json,
+        OutputFormat::Ndjson => ReportFormat::Ndjson,
+        OutputFormat::Csv => ReportFormat::Csv,
+        OutputFormat::Geojson => ReportFormat::GeoJson,
+    }
+}
+
+fn grid_format(grid: GridArg) -> GridFormat {
+    match grid {
+        GridArg::Csv => GridFormat::Csv,
+        GridArg::Npy => GridFormat::Npy,
+    }
+}
+
+fn legend_mode(legend: LegendArg) -> LegendMode {
+    match legend {
+        LegendArg::Copy => LegendMode::Copy,
+        LegendArg::Generated => LegendMode::Generated,
+        LegendArg::None => LegendMode::None,
+    }
+}
+
+fn storms_exit_code(storm_count: usize) -> i32 {
+    if storm_count > 0 {
+        EXIT_SUCCESS
+    } else {
+        EXIT_NO_STORMS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_args(cli: Cli) -> DetectArgs {
+        match cli.command {
+            Command::Detect { detect_args, .. } => detect_args,
+            other => panic!("expected detect, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn flags_override_every_config_layer() {
+        let path = std::env::temp_dir().join(format!("storm_detect_cli_{}.toml", std::process::id()));
+        fs::write(&path, "[detection]\nmin_size = 30\nadjacent_threshold = 3\n[profiles.strict.detection]\nadjacent_threshold = 5\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let cli = Cli::try_parse_from(["storm_detect", "--config", &path, "--profile", "strict", "detect", "frame.png", "--adjacent", "7"]).unwrap();
+        let vars = [(String::from("STORM_DETECTION__ADJACENT_THRESHOLD"), String::from("6"))];
+        let mut config = Config::load_with(cli.config.as_deref(), cli.profile.as_deref(), vars).unwrap();
+        assert_eq!(config.detection.adjacent_threshold, 6);
+
+        detect_args(cli).apply_overrides(&mut config).unwrap();
+        assert_eq!(config.detection.adjacent_threshold, 7);
+        assert_eq!(config.detection.min_size, 30);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn detect_flags_override_the_config() {
+        let cli = Cli::try_parse_from([
+            "storm_detect", "detect", "frame.png", "--base", "base.png", "--min-size", "12", "--min-intensity", "35", "--legend", "generated",
+            "--districts", "districts.geojson", "--cap-dir", "out/cap", "--webhook", "http://a.example", "--webhook", "http://b.example",
+        ])
+        .unwrap();
+        let mut config = Config::default();
+        detect_args(cli).apply_overrides(&mut config).unwrap();
+        assert_eq!(config.station.base_image, "base.png");
+        assert_eq!((config.detection.min_size, config.detection.min_intensity), (12, 35));
+        assert_eq!(config.detection.adjacent_threshold, Config::default().detection.adjacent_threshold);
+        assert_eq!(config.legend.mode, LegendMode::Generated);
+        assert_eq!(config.districts.file, "districts.geojson");
+        assert!(config.cap.enabled && config.cap.output_dir == "out/cap");
+        let urls: Vec<&str> = config.webhook.endpoints.iter().map(|endpoint| endpoint.url.as_str()).collect();
+        assert!(config.webhook.enabled && urls == ["http://a.example", "http://b.example"]);
+    }
+
+    #[test]
+    fn overridden_config_is_validated() {
+        let cli = Cli::try_parse_from(["storm_detect", "detect", "frame.png", "--adjacent", "0"]).unwrap();
+        let error = detect_args(cli).apply_overrides(&mut Config::default()).unwrap_err();
+        assert!(error.to_string().contains("detection.adjacent_threshold must be at least 1, got 0"), "{}", error);
+    }
+
+    #[test]
+    fn exit_code_tells_storms_from_no_storms_and_errors() {
+        assert_eq!((EXIT_SUCCESS, EXIT_ERROR, EXIT_NO_STORMS), (0, 1, 2));
+        assert_eq!(storms_exit_code(3), EXIT_SUCCESS);
+        assert_eq!(storms_exit_code(0), EXIT_NO_STORMS);
+
+        // The no-echo base image holds no storms, an unreadable image fails the batch
+        let dir = std::env::temp_dir().join(format!("storm_detect_exit_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(Config::default().station.base_image, dir.join("clear.png")).unwrap();
+        let batch = |dir: &Path| run(Cli::try_parse_from(["storm_detect", "batch", &dir.to_string_lossy(), "--format", "json"]).unwrap()).unwrap();
+        assert_eq!(batch(&dir), EXIT_NO_STORMS);
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+        assert_eq!(batch(&dir), EXIT_ERROR);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
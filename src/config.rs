@@ -0,0 +1,683 @@
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use toml::{Table, Value};
+use crate::accumulation::AccumulationConfig;
+use crate::animation::AnimationConfig;
+use crate::beam::BeamConfig;
+use crate::cap::CapConfig;
+use crate::consts::*;
+use crate::districts::DistrictConfig;
+use crate::hierarchy::HierarchyParams;
+use crate::legend::LegendConfig;
+use crate::morphology::MorphologyOptions;
+use crate::pixel::Palette;
+use crate::poi::PoiConfig;
+use crate::precipitation::{PrecipitationConfig, ZrRelation};
+use crate::render::RenderStyle;
+use crate::severe::SevereConfig;
+use crate::storage::StorageConfig;
+use crate::storm::DetectionParams;
+use crate::tracking::TrackingConfig;
+use crate::watershed::SegmentationMode;
+use crate::webhook::WebhookConfig;
+
+// Config file looked up in the working directory when neither --config nor STORM_CONFIG is given
+pub const DEFAULT_CONFIG_PATH: &str = "storm_detect.toml";
+// Environment overrides are STORM_<SECTION>__<KEY>, e.g. STORM_DETECTION__MIN_SIZE=30
+pub const ENV_PREFIX: &str = "STORM_";
+pub const ENV_CONFIG_PATH: &str = "STORM_CONFIG";
+pub const ENV_PROFILE: &str = "STORM_PROFILE";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StationConfig {
+    pub name: String,
+    pub code: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    // Antenna height above sea level in metres
+    pub altitude_m: f64,
+    // No-echo reference frame of the station, used for overlay removal and as result background
+    pub base_image: String,
+    pub url_head: String,
+    pub url_middle: String,
+    pub url_end: String,
+    pub radar_center: [f64; 2],
+    pub radar_area: [u32; 2],
+    pub km_per_pixel: f64,
+}
+
+impl Default for StationConfig {
+    fn default() -> Self {
+        StationConfig {
+            name: STATION_NAME.to_string(),
+            code: STATION_CODE.to_string(),
+            latitude: STATION_LATITUDE,
+            longitude: STATION_LONGITUDE,
+            altitude_m: 0.0,
+            base_image: String::from("data/base/base.png"),
+            url_head: URL_HEAD.to_string(),
+            url_middle: URL_MIDDLE.to_string(),
+            url_end: URL_END.to_string(),
+            radar_center: [RADAR_CENTER.x, RADAR_CENTER.y],
+            radar_area: [RADAR_AREA.0, RADAR_AREA.1],
+            km_per_pixel: DISTANCE_RATIO,
+        }
+    }
+}
+
+impl StationConfig {
+    pub fn radar_center(&self) -> Point2<f64> {
+        Point2::new(self.radar_center[0], self.radar_center[1])
+    }
+
+    pub fn radar_area(&self) -> (u32, u32) {
+        (self.radar_area[0], self.radar_area[1])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayConfig {
+    pub enabled: bool,
+    pub dilate_radius: u32,
+    pub fill_passes: u32,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        OverlayConfig {
+            enabled: true,
+            dilate_radius: 0,
+            fill_passes: 3,
+        }
+    }
+}
+
+// Runtime configuration of a detection run
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub station: StationConfig,
+    pub palette: Palette,
+    pub detection: DetectionParams,
+    pub hierarchy: HierarchyParams,
+    pub morphology: MorphologyOptions,
+    pub overlay: OverlayConfig,
+    pub legend: LegendConfig,
+    pub render: RenderStyle,
+    pub tracking: TrackingConfig,
+    pub animation: AnimationConfig,
+    pub precipitation: PrecipitationConfig,
+    pub accumulation: AccumulationConfig,
+    pub severe: SevereConfig,
+    pub beam: BeamConfig,
+    pub poi: PoiConfig,
+    pub districts: DistrictConfig,
+    pub cap: CapConfig,
+    pub webhook: WebhookConfig,
+    pub storage: StorageConfig,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: String, source: std::io::Error },
+    Parse { path: String, message: String },
+    UnknownProfile { name: String, available: Vec<String> },
+    Environment { variable: String, message: String },
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => write!(f, "Failed to read config file {}: {}", path, source),
+            ConfigError::Parse { path, message } => write!(f, "Invalid config file {}: {}", path, message),
+            ConfigError::UnknownProfile { name, available } if available.is_empty() => {
+                write!(f, "Unknown profile '{}', the config file defines no profiles", name)
+            }
+            ConfigError::UnknownProfile { name, available } => {
+                write!(f, "Unknown profile '{}', available profiles: {}", name, available.join(", "))
+            }
+            ConfigError::Environment { variable, message } => write!(f, "Invalid environment override {}: {}", variable, message),
+            ConfigError::Invalid(problems) => write!(f, "Invalid configuration:\n  - {}", problems.join("\n  - ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // Layers, from lowest to highest priority: built-in defaults, top level tables of the config file,
+    // the selected profile, then STORM_* environment variables. CLI flags are applied by the caller on top.
+    pub fn load(config_path: Option<&str>, profile: Option<&str>) -> Result<Config, ConfigError> {
+        Config::load_with(config_path, profile, std::env::vars())
+    }
+
+    // Config::load with the given environment variables in place of the process environment
+    pub fn load_with(
+        config_path: Option<&str>,
+        profile: Option<&str>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config, ConfigError> {
+        let vars: Vec<(String, String)> = vars.into_iter().collect();
+        let env_var = |name: &str| vars.iter().find(|(variable, _)| variable == name).map(|(_, value)| value.clone());
+        let explicit_path = config_path.map(str::to_string).or_else(|| env_var(ENV_CONFIG_PATH));
+        let path = match explicit_path {
+            Some(path) => Some(path),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Some(DEFAULT_CONFIG_PATH.to_string()),
+            None => None,
+        };
+        let profile = profile.map(str::to_string).or_else(|| env_var(ENV_PROFILE));
+
+        let mut layered = Value::try_from(Config::default()).map_err(|e| ConfigError::Parse {
+            path: String::from("<defaults>"),
+            message: e.to_string(),
+        })?;
+
+        match &path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read { path: path.clone(), source })?;
+                let file_layers = file_layers(path, &content, profile.as_deref())?;
+                for layer in file_layers {
+                    merge_values(&mut layered, layer);
+                }
+            }
+            None => {
+                if let Some(name) = profile {
+                    return Err(ConfigError::UnknownProfile { name, available: Vec::new() });
+                }
+            }
+        }
+
+        apply_env_overrides(&mut layered, vars.into_iter())?;
+
+        let config: Config = layered.try_into().map_err(|e: toml::de::Error| ConfigError::Parse {
+            path: path.unwrap_or_else(|| String::from("<defaults and environment>")),
+            message: e.message().to_string(),
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Runtime detection parameters, with the pixel scale taken from the station
+    pub fn detection_params(&self) -> DetectionParams {
+        DetectionParams {
+            km_per_pixel: self.station.km_per_pixel,
+            ..self.detection
+        }
+    }
+
+    // Threshold ladder with the pixel gap of the detection and the scale of the station
+    pub fn hierarchy_params(&self) -> HierarchyParams {
+        HierarchyParams {
+            adjacent_threshold: self.detection.adjacent_threshold,
+            km_per_pixel: self.station.km_per_pixel,
+            ..self.hierarchy.clone()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let station = &self.station;
+        if station.radar_area[0] == 0 || station.radar_area[1] == 0 {
+            problems.push(format!("station.radar_area must be positive, got {:?}", station.radar_area));
+        }
+        let [center_x, center_y] = station.radar_center;
+        if center_x < 0.0 || center_y < 0.0 || center_x > station.radar_area[0] as f64 || center_y > station.radar_area[1] as f64 {
+            problems.push(format!(
+                "station.radar_center {:?} lies outside of station.radar_area {:?}",
+                station.radar_center, station.radar_area
+            ));
+        }
+        if station.km_per_pixel <= 0.0 || !station.km_per_pixel.is_finite() {
+            problems.push(format!("station.km_per_pixel must be positive, got {}", station.km_per_pixel));
+        }
+        if !(-90.0..=90.0).contains(&station.latitude) {
+            problems.push(format!("station.latitude must be within [-90, 90], got {}", station.latitude));
+        }
+        if !(-180.0..=180.0).contains(&station.longitude) {
+            problems.push(format!("station.longitude must be within [-180, 180], got {}", station.longitude));
+        }
+
+        if self.palette.levels.is_empty() {
+            problems.push(String::from("palette.levels must not be empty"));
+        }
+        if self.palette.levels.windows(2).any(|pair| pair[0].dbz >= pair[1].dbz) {
+            problems.push(String::from("palette.levels must be sorted by strictly increasing dbz"));
+        }
+
+        let detection = &self.detection;
+        if detection.adjacent_threshold < 1 {
+            problems.push(format!("detection.adjacent_threshold must be at least 1, got {}", detection.adjacent_threshold));
+        }
+        if detection.min_intensity > self.palette.max_dbz() {
+            problems.push(format!(
+                "detection.min_intensity {} exceeds the highest palette level {}, no storm could ever be found",
+                detection.min_intensity,
+                self.palette.max_dbz()
+            ));
+        }
+        if detection.core_dbz > self.palette.max_dbz() {
+            problems.push(format!(
+                "detection.core_dbz {} exceeds the highest palette level {}, no storm could ever have a core",
+                detection.core_dbz,
+                self.palette.max_dbz()
+            ));
+        }
+        if !(detection.type_threshold > 0.0 && detection.type_threshold <= 1.0) {
+            problems.push(format!("detection.type_threshold must be within (0, 1], got {}", detection.type_threshold));
+        }
+        if let SegmentationMode::Watershed(watershed) = detection.segmentation {
+            if watershed.min_separation < 0.0 {
+                problems.push(format!(
+                    "detection.segmentation.watershed.min_separation must not be negative, got {}",
+                    watershed.min_separation
+                ));
+            }
+        }
+
+        let hierarchy = &self.hierarchy;
+        if hierarchy.enabled && hierarchy.levels.is_empty() {
+            problems.push(String::from("hierarchy.levels must not be empty"));
+        }
+        if hierarchy.levels.windows(2).any(|pair| pair[0].threshold >= pair[1].threshold) {
+            problems.push(String::from("hierarchy.levels must be sorted by strictly increasing threshold"));
+        }
+        if hierarchy.cell_threshold > hierarchy.core_threshold {
+            problems.push(format!(
+                "hierarchy.cell_threshold {} must not exceed hierarchy.core_threshold {}",
+                hierarchy.cell_threshold, hierarchy.core_threshold
+            ));
+        }
+
+        if self.morphology.fill_holes && self.morphology.max_hole_size == 0 {
+            problems.push(String::from("morphology.max_hole_size must be positive when fill_holes is on"));
+        }
+
+        let render = &self.render;
+        if !(render.ring_interval_km > 0.0 && render.ring_interval_km.is_finite()) {
+            problems.push(format!("render.ring_interval_km must be positive, got {}", render.ring_interval_km));
+        }
+        if !(render.spoke_interval_deg > 0.0 && render.spoke_interval_deg <= 360.0) {
+            problems.push(format!("render.spoke_interval_deg must be within (0, 360], got {}", render.spoke_interval_deg));
+        }
+        if !(render.scale_bar_km > 0.0 && render.scale_bar_km.is_finite()) {
+            problems.push(format!("render.scale_bar_km must be positive, got {}", render.scale_bar_km));
+        }
+
+        let tracking = &self.tracking;
+        if !(tracking.max_speed_kmh > 0.0 && tracking.max_speed_kmh.is_finite()) {
+            problems.push(format!("tracking.max_speed_kmh must be positive, got {}", tracking.max_speed_kmh));
+        }
+        if !(tracking.max_distance_km > 0.0 && tracking.max_distance_km.is_finite()) {
+            problems.push(format!("tracking.max_distance_km must be positive, got {}", tracking.max_distance_km));
+        }
+
+        // Gif delays are stored in hundredths of a second, apng delays as 16 bit milliseconds
+        let animation = &self.animation;
+        for (key, delay) in [("frame_delay_ms", animation.frame_delay_ms), ("last_frame_delay_ms", animation.last_frame_delay_ms)] {
+            if !(10..=u16::MAX as u32).contains(&delay) {
+                problems.push(format!("animation.{} must be within [10, {}], got {}", key, u16::MAX, delay));
+            }
+        }
+
+        let precipitation = &self.precipitation;
+        if let ZrRelation::Custom { a, b } = precipitation.relation {
+            if !(a > 0.0 && b > 0.0 && a.is_finite() && b.is_finite()) {
+                problems.push(format!("precipitation.relation.custom needs positive a and b, got a = {}, b = {}", a, b));
+            }
+        }
+        if !(precipitation.hail_cap_dbz > 0.0 && precipitation.hail_cap_dbz.is_finite()) {
+            problems.push(format!("precipitation.hail_cap_dbz must be positive, got {}", precipitation.hail_cap_dbz));
+        }
+
+        let accumulation = &self.accumulation;
+        if !(accumulation.max_gap_minutes > 0.0 && accumulation.max_gap_minutes.is_finite()) {
+            problems.push(format!("accumulation.max_gap_minutes must be positive, got {}", accumulation.max_gap_minutes));
+        }
+        if accumulation.levels.windows(2).any(|pair| pair[0].mm >= pair[1].mm) {
+            problems.push(String::from("accumulation.levels must be sorted by strictly increasing mm"));
+        }
+
+        let severe = &self.severe;
+        if !(severe.severe_core_min_area_km2 >= 0.0 && severe.severe_core_min_area_km2.is_finite()) {
+            problems.push(format!("severe.severe_core_min_area_km2 must not be negative, got {}", severe.severe_core_min_area_km2));
+        }
+        if severe.hail_probability_min_dbz > severe.hail_probability_max_dbz {
+            problems.push(format!(
+                "severe.hail_probability_min_dbz {} must not exceed severe.hail_probability_max_dbz {}",
+                severe.hail_probability_min_dbz, severe.hail_probability_max_dbz
+            ));
+        }
+
+        let beam = &self.beam;
+        for (product, elevation) in &beam.product_elevations {
+            if !(-2.0..=90.0).contains(elevation) {
+                problems.push(format!("beam.product_elevations.{} must be within [-2, 90] degrees, got {}", product, elevation));
+            }
+        }
+        if !(-2.0..=90.0).contains(&beam.default_elevation_deg) {
+            problems.push(format!("beam.default_elevation_deg must be within [-2, 90] degrees, got {}", beam.default_elevation_deg));
+        }
+
+        let poi = &self.poi;
+        if !(poi.lookahead_minutes >= 0.0 && poi.lookahead_minutes.is_finite()) {
+            problems.push(format!("poi.lookahead_minutes must not be negative, got {}", poi.lookahead_minutes));
+        }
+        for point in &poi.points {
+            if !(point.radius_km >= 0.0 && point.radius_km.is_finite()) {
+                problems.push(format!("poi.points {} radius_km must not be negative, got {}", point.name, point.radius_km));
+            }
+            if point.pixel.is_none() && (point.latitude.is_none() || point.longitude.is_none()) {
+                problems.push(format!("poi.points {} needs a pixel or both latitude and longitude", point.name));
+            }
+        }
+        if self.districts.name_property.is_empty() {
+            problems.push(String::from("districts.name_property must not be empty"));
+        }
+
+        let cap = &self.cap;
+        if cap.sender.is_empty() || cap.sender.contains([' ', ',', '<', '&']) {
+            problems.push(format!("cap.sender must be non-empty without spaces, commas, < or &, got '{}'", cap.sender));
+        }
+        if !(cap.min_area_km2 >= 0.0 && cap.min_area_km2.is_finite()) {
+            problems.push(format!("cap.min_area_km2 must not be negative, got {}", cap.min_area_km2));
+        }
+        if cap.expires_minutes == 0 {
+            problems.push(String::from("cap.expires_minutes must be positive"));
+        }
+
+        let webhook = &self.webhook;
+        for endpoint in &webhook.endpoints {
+            if !(endpoint.url.starts_with("http://") || endpoint.url.starts_with("https://")) {
+                problems.push(format!("webhook.endpoints url must be an http or https url, got '{}'", endpoint.url));
+            }
+        }
+        if webhook.max_per_minute == 0 {
+            problems.push(String::from("webhook.max_per_minute must be positive"));
+        }
+        if webhook.timeout_seconds == 0 {
+            problems.push(String::from("webhook.timeout_seconds must be positive"));
+        }
+        if cfg!(not(feature = "sqlite")) && !self.storage.database.is_empty() {
+            problems.push(String::from("storage.database needs a build with the sqlite feature"));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+// Top level tables of the file, followed by the selected profile.
+// The profile comes from the argument, or else the file's default_profile key.
+fn file_layers(path: &str, content: &str, profile: Option<&str>) -> Result<Vec<Value>, ConfigError> {
+    let mut table: Table = content.parse().map_err(|e: toml::de::Error| ConfigError::Parse {
+        path: path.to_string(),
+        message: e.to_string(),
+    })?;
+
+    let default_profile = match table.remove("default_profile") {
+        Some(Value::String(name)) => Some(name),
+        Some(other) => {
+            return Err(ConfigError::Parse {
+                path: path.to_string(),
+                message: format!("default_profile must be a string, got {}", other.type_str()),
+            })
+        }
+        None => None,
+    };
+    let mut profiles = match table.remove("profiles") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(other) => {
+            return Err(ConfigError::Parse {
+                path: path.to_string(),
+                message: format!("profiles must be a table, got {}", other.type_str()),
+            })
+        }
+        None => Table::new(),
+    };
+
+    let mut layers = vec![Value::Table(table)];
+    if let Some(name) = profile.map(str::to_string).or(default_profile) {
+        match profiles.remove(&name) {
+            Some(layer) => layers.push(layer),
+            None => {
+                return Err(ConfigError::UnknownProfile {
+                    name,
+                    available: profiles.keys().cloned().collect(),
+                })
+            }
+        }
+    }
+    Ok(layers)
+}
+
+// Tables are merged key by key, any other value replaces the lower layer
+fn merge_values(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Table(base_table), Value::Table(layer_table)) => {
+            for (key, value) in layer_table {
+                match base_table.get_mut(&key) {
+                    Some(base_value) => merge_values(base_value, value),
+                    None => {
+                        base_table.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+fn apply_env_overrides(layered: &mut Value, vars: impl Iterator<Item = (String, String)>) -> Result<(), ConfigError> {
+    for (variable, raw) in vars {
+        let Some(key_path) = variable.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        // STORM_CONFIG and STORM_PROFILE select the file and profile, they do not name a key
+        if !key_path.contains("__") {
+            continue;
+        }
+        let keys: Vec<String> = key_path.split("__").map(|key| key.to_lowercase()).collect();
+
+        let mut target = &mut *layered;
+        for key in &keys {
+            target = match target {
+                Value::Table(table) => table.get_mut(key).ok_or_else(|| ConfigError::Environment {
+                    variable: variable.clone(),
+                    message: format!("unknown key {}", keys.join(".")),
+                })?,
+                _ => {
+                    return Err(ConfigError::Environment {
+                        variable: variable.clone(),
+                        message: format!("{} is not a table", keys.join(".")),
+                    })
+                }
+            };
+        }
+
+        // Strings are taken verbatim, anything else is parsed as a TOML value
+        *target = match target {
+            Value::String(_) => Value::String(raw),
+            _ => {
+                let parsed: Table = format!("value = {}", raw).parse().map_err(|e: toml::de::Error| ConfigError::Environment {
+                    variable: variable.clone(),
+                    message: e.message().to_string(),
+                })?;
+                parsed["value"].clone()
+            }
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hierarchy::ThresholdLevel;
+    use crate::poi::PointOfInterest;
+    use crate::webhook::WebhookEndpoint;
+
+    // Config file of one test in the temp directory
+    fn config_file(test: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("storm_detect_config_{}_{}.toml", std::process::id(), test));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    const PROFILES: &str = "
+default_profile = \"wide\"
+
+[detection]
+min_size = 30
+min_intensity = 40
+adjacent_threshold = 3
+
+[profiles.wide.detection]
+adjacent_threshold = 4
+
+[profiles.strict.detection]
+min_intensity = 50
+adjacent_threshold = 5
+";
+
+    #[test]
+    fn layers_override_in_order() {
+        let path = config_file("layers", PROFILES);
+        let vars = env(&[("STORM_DETECTION__ADJACENT_THRESHOLD", "6"), ("STORM_STATION__NAME", "Test 1"), ("HOME", "/root")]);
+        let config = Config::load_with(Some(&path), Some("strict"), vars).unwrap();
+        // Default, file, profile and environment in turn
+        assert_eq!(config.detection.type_threshold, DetectionParams::default().type_threshold);
+        assert_eq!(config.detection.min_size, 30);
+        assert_eq!(config.detection.min_intensity, 50);
+        assert_eq!(config.detection.adjacent_threshold, 6);
+        // Strings are taken verbatim
+        assert_eq!(config.station.name, "Test 1");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn explicit_profile_wins_over_default_profile() {
+        let path = config_file("default_profile", PROFILES);
+        let load = |profile: Option<&str>, vars: &[(&str, &str)]| Config::load_with(Some(&path), profile, env(vars)).unwrap().detection;
+        assert_eq!(load(None, &[]).adjacent_threshold, 4);
+        assert_eq!(load(Some("strict"), &[]).adjacent_threshold, 5);
+        assert_eq!(load(None, &[("STORM_PROFILE", "strict")]).adjacent_threshold, 5);
+        assert_eq!(load(Some("wide"), &[("STORM_PROFILE", "strict")]).adjacent_threshold, 4);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_profile_lists_the_available_ones() {
+        let path = config_file("unknown_profile", PROFILES);
+        let error = Config::load_with(Some(&path), Some("calm"), env(&[])).unwrap_err();
+        assert!(matches!(&error, ConfigError::UnknownProfile { name, .. } if name == "calm"));
+        assert_eq!(error.to_string(), "Unknown profile 'calm', available profiles: strict, wide");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let path = config_file("unknown_key", "[detection]\nmin_sise = 30\n");
+        let error = Config::load_with(Some(&path), None, env(&[])).unwrap_err();
+        assert!(matches!(&error, ConfigError::Parse { message, .. } if message.contains("unknown field `min_sise`")), "{}", error);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn environment_values_must_parse() {
+        let path = config_file("environment", "");
+        let error = Config::load_with(Some(&path), None, env(&[("STORM_DETECTION__MIN_SIZE", "many")])).unwrap_err();
+        assert!(matches!(&error, ConfigError::Environment { variable, .. } if variable == "STORM_DETECTION__MIN_SIZE"), "{}", error);
+
+        let error = Config::load_with(Some(&path), None, env(&[("STORM_DETECTION__MIN_SISE", "3")])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid environment override STORM_DETECTION__MIN_SISE: unknown key detection.min_sise");
+
+        // Values of the wrong type parse as TOML but fail the structs
+        let error = Config::load_with(Some(&path), None, env(&[("STORM_DETECTION__MIN_SIZE", "\"many\"")])).unwrap_err();
+        assert!(matches!(error, ConfigError::Parse { .. }));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Makes one setting of a valid config invalid
+    type Change = fn(&mut Config);
+
+    #[test]
+    fn every_section_is_validated() {
+        let cases: Vec<(Change, &str)> = vec![
+            (|config| config.station.km_per_pixel = 0.0, "station.km_per_pixel must be positive"),
+            (|config| config.station.radar_center = [700.0, 300.0], "station.radar_center [700.0, 300.0] lies outside"),
+            (|config| config.palette.levels.reverse(), "palette.levels must be sorted"),
+            (|config| config.detection.min_intensity = 80, "detection.min_intensity 80 exceeds the highest palette level 70"),
+            (|config| config.detection.core_dbz = 75, "detection.core_dbz 75 exceeds"),
+            (|config| config.detection.type_threshold = 1.5, "detection.type_threshold must be within (0, 1]"),
+            (
+                |config| config.hierarchy.levels = vec![ThresholdLevel { threshold: 40, min_size: 1 }, ThresholdLevel { threshold: 30, min_size: 1 }],
+                "hierarchy.levels must be sorted",
+            ),
+            (|config| config.hierarchy.cell_threshold = 60, "hierarchy.cell_threshold 60 must not exceed"),
+            (
+                |config| {
+                    config.morphology.fill_holes = true;
+                    config.morphology.max_hole_size = 0;
+                },
+                "morphology.max_hole_size must be positive",
+            ),
+            (|config| config.render.spoke_interval_deg = 0.0, "render.spoke_interval_deg must be within (0, 360]"),
+            (|config| config.tracking.max_speed_kmh = f64::NAN, "tracking.max_speed_kmh must be positive"),
+            (|config| config.animation.frame_delay_ms = 5, "animation.frame_delay_ms must be within [10, 65535]"),
+            (|config| config.precipitation.relation = ZrRelation::Custom { a: 200.0, b: 0.0 }, "precipitation.relation.custom needs positive a and b"),
+            (|config| config.accumulation.max_gap_minutes = -1.0, "accumulation.max_gap_minutes must be positive"),
+            (|config| config.severe.hail_probability_min_dbz = 70.0, "severe.hail_probability_min_dbz 70 must not exceed"),
+            (|config| config.beam.default_elevation_deg = 95.0, "beam.default_elevation_deg must be within [-2, 90]"),
+            (
+                |config| {
+                    config.poi.points.push(PointOfInterest {
+                        name: String::from("Nowhere"),
+                        latitude: Some(23.0),
+                        longitude: None,
+                        pixel: None,
+                        radius_km: 5.0,
+                    })
+                },
+                "poi.points Nowhere needs a pixel or both latitude and longitude",
+            ),
+            (|config| config.districts.name_property.clear(), "districts.name_property must not be empty"),
+            (|config| config.cap.sender = String::from("storm detect"), "cap.sender must be non-empty without spaces"),
+            (|config| config.webhook.endpoints.push(WebhookEndpoint::new("ftp://example.org")), "webhook.endpoints url must be an http or https url"),
+        ];
+        assert!(Config::default().validate().is_ok());
+        for (change, expected) in cases {
+            let mut config = Config::default();
+            change(&mut config);
+            match config.validate() {
+                Err(ConfigError::Invalid(problems)) => {
+                    assert!(problems.iter().any(|problem| problem.starts_with(expected)), "expected '{}', got {:?}", expected, problems)
+                }
+                other => panic!("expected '{}', got {:?}", expected, other),
+            }
+        }
+
+        let mut config = Config::default();
+        config.storage.database = String::from("storms.db");
+        assert_eq!(config.validate().is_err(), cfg!(not(feature = "sqlite")));
+    }
+
+    #[test]
+    fn example_config_parses_and_validates() {
+        for profile in [None, Some("severe")] {
+            let config = Config::load_with(Some("config/storm_detect.toml"), profile, env(&[]));
+            assert!(config.is_ok(), "{:?}: {}", profile, config.unwrap_err());
+        }
+    }
+}
@@ -9,18 +9,26 @@ pub const RADAR_CENTER: Point2<f64> = Point2::new(300.0, 300.0);
 pub const RADAR_AREA: (u32, u32) = (599, 599);
 pub const TYPE_THRESHOLD: f64 = 0.88;
 
+pub const STATION_NAME: &str = "GuangZhou";
+pub const STATION_CODE: &str = "Z9200";
+pub const STATION_LATITUDE: f64 = 23.0039;
+pub const STATION_LONGITUDE: f64 = 113.3550;
+
 pub const URL_HEAD: &str = "http://tqyb.com.cn/data/radar/gz/19/";
 pub const URL_MIDDLE: &str = "/Z9200_";
 pub const URL_END: &str = "Z_PPI_02_19.png";
 
 pub const MIN_SIZE: usize = 40;
 pub const MIN_INTENSITY: u32 = 45;
+// Pixels from this reflectivity form the core used for the ellipse fit and the storm type
+pub const CORE_DBZ: u32 = 45;
 
 pub const ADJACENT_THRESHOLD: i32 = 2;
 pub const MAJOR_PIXEL_THRESHOLD: i32 = 50;
 
 pub const INTENSITY_CENTER_COLOR: Rgba<u8> = Rgba([0, 255, 0, 255]);
 pub const CONNECTION_LINE_COLOR: Rgba<u8> = Rgba([105, 131, 255, 255]);
+pub const ELLIPSE_COLOR: Rgba<u8> = Rgba([118, 95, 255, 255]);
 // Lazy static initialization for vectors
 pub static COLOR_LIST: Lazy<Vec<Rgba<u8>>> = Lazy::new(|| vec![
     Rgba([0, 0, 246, 255]),
@@ -37,4 +45,4 @@ pub static COLOR_LIST: Lazy<Vec<Rgba<u8>>> = Lazy::new(|| vec![
     Rgba([152, 84, 200, 255]),
 ]);
 
-pub const DISTANCE_RATIO: f64 = 200.0 / (300.0 - 65.0) as f64;
\ No newline at end of file
+pub const DISTANCE_RATIO: f64 = 200.0 / (300.0 - 65.0);
@@ -0,0 +1,270 @@
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use crate::config::StationConfig;
+use crate::geo::lat_lon_to_pixel;
+use crate::storm::Storm;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DistrictConfig {
+    // GeoJSON file of Polygon or MultiPolygon features, empty for no districts
+    pub file: String,
+    // Feature property holding the district name, features without it are named by their index
+    pub name_property: String,
+}
+
+impl Default for DistrictConfig {
+    fn default() -> Self {
+        DistrictConfig {
+            file: String::new(),
+            name_property: String::from("name"),
+        }
+    }
+}
+
+// Administrative area with its boundary projected into image pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct District {
+    pub name: String,
+    // Outer rings and holes of every polygon, a point is inside under the even-odd rule over all of them
+    pub rings: Vec<Vec<Point2<f64>>>,
+    // Bounding box as (min_x, min_y, max_x, max_y)
+    bounds: (f64, f64, f64, f64),
+}
+
+impl District {
+    pub fn new(name: String, rings: Vec<Vec<Point2<f64>>>) -> Self {
+        let bounds = rings.iter().flatten().fold(
+            (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            |(min_x, min_y, max_x, max_y), point| (min_x.min(point.x), min_y.min(point.y), max_x.max(point.x), max_y.max(point.y)),
+        );
+        District { name, rings, bounds }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let (min_x, min_y, max_x, max_y) = self.bounds;
+        if x < min_x || x > max_x || y < min_y || y > max_y {
+            return false;
+        }
+        self.rings.iter().filter(|ring| ring_contains(ring, x, y)).count() % 2 == 1
+    }
+}
+
+// Part of a storm footprint within a district
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DistrictStorm {
+    pub storm_id: u32,
+    pub overlap_km2: f64,
+    pub max_dbz: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DistrictSummary {
+    pub district: String,
+    pub storm_count: usize,
+    // Storm footprints do not overlap, so this is the affected area of the district
+    pub overlap_km2: f64,
+    pub max_dbz: u32,
+    pub storms: Vec<DistrictStorm>,
+}
+
+pub fn load_districts(config: &DistrictConfig, station: &StationConfig) -> Result<Vec<District>, Box<dyn Error>> {
+    if config.file.is_empty() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&config.file).map_err(|e| format!("Failed to read districts {}: {}", config.file, e))?;
+    let document: Value = serde_json::from_str(&content).map_err(|e| format!("Invalid districts {}: {}", config.file, e))?;
+    parse_districts(&document, &config.name_property, station).map_err(|e| format!("Invalid districts {}: {}", config.file, e).into())
+}
+
+// Districts of a GeoJSON FeatureCollection, a single Feature or a bare geometry, coordinates as [longitude, latitude]
+pub fn parse_districts(document: &Value, name_property: &str, station: &StationConfig) -> Result<Vec<District>, String> {
+    let features: Vec<&Value> = match document["type"].as_str() {
+        Some("FeatureCollection") => document["features"].as_array().ok_or("FeatureCollection without features")?.iter().collect(),
+        _ => vec![document],
+    };
+    let mut districts = Vec::with_capacity(features.len());
+    for (index, feature) in features.into_iter().enumerate() {
+        let geometry = if feature["type"] == "Feature" { &feature["geometry"] } else { feature };
+        let name = match &feature["properties"][name_property] {
+            Value::String(name) => name.clone(),
+            Value::Null => format!("district {}", index + 1),
+            other => other.to_string(),
+        };
+        let polygons = match geometry["type"].as_str() {
+            Some("Polygon") => vec![&geometry["coordinates"]],
+            Some("MultiPolygon") => geometry["coordinates"].as_array().ok_or_else(|| format!("{}: invalid MultiPolygon", name))?.iter().collect(),
+            other => return Err(format!("{}: unsupported geometry {}", name, other.unwrap_or("null"))),
+        };
+        let mut rings = Vec::new();
+        for polygon in polygons {
+            for ring in polygon.as_array().ok_or_else(|| format!("{}: invalid polygon", name))? {
+                rings.push(parse_ring(ring, station).ok_or_else(|| format!("{}: invalid ring coordinates", name))?);
+            }
+        }
+        districts.push(District::new(name, rings));
+    }
+    Ok(districts)
+}
+
+// Storms of the frame inside every district, districts without storms included
+pub fn district_summaries(storms: &[Storm], districts: &[District], km_per_pixel: f64) -> Vec<DistrictSummary> {
+    let pixel_area = km_per_pixel * km_per_pixel;
+    districts
+        .iter()
+        .map(|district| {
+            let storms: Vec<DistrictStorm> = storms
+                .iter()
+                .filter_map(|storm| {
+                    let inside: Vec<u32> = storm
+                        .pixels
+                        .iter()
+                        .filter(|pixel| district.contains(pixel.x as f64, pixel.y as f64))
+                        .map(|pixel| pixel.intensity)
+                        .collect();
+                    let max_dbz = *inside.iter().max()?;
+                    Some(DistrictStorm {
+                        storm_id: storm.storm_id,
+                        overlap_km2: inside.len() as f64 * pixel_area,
+                        max_dbz,
+                    })
+                })
+                .collect();
+            DistrictSummary {
+                district: district.name.clone(),
+                storm_count: storms.len(),
+                overlap_km2: storms.iter().fold(0.0, |total, storm| total + storm.overlap_km2),
+                max_dbz: storms.iter().map(|storm| storm.max_dbz).max().unwrap_or(0),
+                storms,
+            }
+        })
+        .collect()
+}
+
+fn parse_ring(ring: &Value, station: &StationConfig) -> Option<Vec<Point2<f64>>> {
+    let points: Option<Vec<Point2<f64>>> = ring
+        .as_array()?
+        .iter()
+        .map(|position| {
+            let longitude = position.get(0)?.as_f64()?;
+            let latitude = position.get(1)?.as_f64()?;
+            Some(lat_lon_to_pixel(latitude, longitude, station))
+        })
+        .collect();
+    points.filter(|points| points.len() >= 3)
+}
+
+fn ring_contains(ring: &[Point2<f64>], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut previous = ring[ring.len() - 1];
+    for &current in ring {
+        if (current.y > y) != (previous.y > y) {
+            let crossing_x = current.x + (y - current.y) * (previous.x - current.x) / (previous.y - current.y);
+            if x < crossing_x {
+                inside = !inside;
+            }
+        }
+        previous = current;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block, storm_from_pixels};
+
+    // A polygon with a hole and an unnamed multipolygon. The test station maps one degree to one pixel,
+    // x = longitude and y = -latitude, so the rings run along pixel borders.
+    const FIXTURE: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "properties": { "name": "North" },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [
+                        [[0.5, -0.5], [10.5, -0.5], [10.5, -10.5], [0.5, -10.5], [0.5, -0.5]],
+                        [[3.5, -3.5], [5.5, -3.5], [5.5, -5.5], [3.5, -5.5], [3.5, -3.5]]
+                    ]
+                }
+            },
+            {
+                "type": "Feature",
+                "properties": { "code": 7 },
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [
+                        [[[20.5, -0.5], [25.5, -0.5], [25.5, -5.5], [20.5, -5.5], [20.5, -0.5]]],
+                        [[[30.5, -0.5], [32.5, -0.5], [32.5, -2.5], [30.5, -2.5], [30.5, -0.5]]]
+                    ]
+                }
+            }
+        ]
+    }"#;
+
+    fn station() -> StationConfig {
+        StationConfig {
+            latitude: 0.0,
+            longitude: 0.0,
+            radar_center: [0.0, 0.0],
+            km_per_pixel: 111.32,
+            ..StationConfig::default()
+        }
+    }
+
+    fn districts() -> Vec<District> {
+        parse_districts(&serde_json::from_str(FIXTURE).unwrap(), "name", &station()).unwrap()
+    }
+
+    #[test]
+    fn polygons_and_multipolygons_are_parsed_with_every_ring() {
+        let districts = districts();
+        let names: Vec<&str> = districts.iter().map(|district| district.name.as_str()).collect();
+        assert_eq!(names, ["North", "district 2"]);
+        assert_eq!(districts[0].rings.len(), 2);
+        assert_eq!(districts[1].rings.len(), 2);
+        assert!(districts.iter().flat_map(|district| &district.rings).all(|ring| ring.len() == 5));
+    }
+
+    #[test]
+    fn holes_and_parts_follow_the_even_odd_rule() {
+        let districts = districts();
+        let cases = [
+            (0, (1.0, 1.0), true),
+            (0, (10.0, 10.0), true),
+            (0, (4.0, 5.0), false),
+            (0, (11.0, 5.0), false),
+            (1, (21.0, 1.0), true),
+            (1, (32.0, 2.0), true),
+            (1, (28.0, 1.0), false),
+            (1, (31.0, 3.0), false),
+        ];
+        for (district, (x, y), expected) in cases {
+            assert_eq!(districts[district].contains(x, y), expected, "{} at ({}, {})", districts[district].name, x, y);
+        }
+    }
+
+    #[test]
+    fn overlap_counts_the_storm_pixels_inside() {
+        // One storm over all of North, hole included, and one over the larger part of district 2
+        let mut north = block(0, 0, 12, 12, 40);
+        north[2 * 12 + 2].2 = 55;
+        let east = block(21, 1, 5, 1, 45);
+        let summaries = district_summaries(&[storm_from_pixels(1, &north), storm_from_pixels(2, &east)], &districts(), 2.0);
+
+        assert_eq!(summaries[0].storm_count, 1);
+        assert_eq!(summaries[0].storms, [DistrictStorm { storm_id: 1, overlap_km2: 384.0, max_dbz: 55 }]);
+        assert_eq!((summaries[0].overlap_km2, summaries[0].max_dbz), (384.0, 55));
+        assert_eq!(summaries[1].storms, [DistrictStorm { storm_id: 2, overlap_km2: 20.0, max_dbz: 45 }]);
+    }
+
+    #[test]
+    fn unsupported_geometry_is_an_error() {
+        let point = serde_json::json!({ "type": "Feature", "properties": { "name": "Mast" }, "geometry": { "type": "Point", "coordinates": [0.0, 0.0] } });
+        assert_eq!(parse_districts(&point, "name", &station()).unwrap_err(), "Mast: unsupported geometry Point");
+    }
+}
@@ -0,0 +1,474 @@
+use image::{Rgba, RgbaImage};
+
+// Bounds-safe drawing primitives. Positions are in pixel coordinates with pixel centers on integers;
+// anything outside of the image is clipped, and non-finite input draws nothing instead of panicking.
+
+// Pen used for outlines and lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    pub color: Rgba<u8>,
+    pub antialias: bool,
+}
+
+// Blend the color over the pixel with the given coverage in [0, 1], positions off the image are ignored
+pub fn blend_pixel(img: &mut RgbaImage, x: i32, y: i32, color: Rgba<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x as u32 >= img.width() || y as u32 >= img.height() || coverage.is_nan() {
+        return;
+    }
+    let alpha = coverage.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+    if alpha <= 0.0 {
+        return;
+    }
+    let pixel = img.get_pixel_mut(x as u32, y as u32);
+    for channel in 0..3 {
+        let blended = pixel[channel] as f32 * (1.0 - alpha) + color[channel] as f32 * alpha;
+        pixel[channel] = blended.round() as u8;
+    }
+    pixel[3] = (pixel[3] as f32 + (255.0 - pixel[3] as f32) * alpha).round() as u8;
+}
+
+fn is_finite_point(point: (f32, f32)) -> bool {
+    point.0.is_finite() && point.1.is_finite()
+}
+
+// Coverage of a pixel whose center lies at the given distance from the middle of a stroke of the given width.
+// Without anti-aliasing a pixel is either in or out, strokes stay at least one pixel wide.
+fn stroke_coverage(distance: f32, stroke: &Stroke) -> f32 {
+    let half_width = stroke.width / 2.0;
+    if stroke.antialias {
+        (half_width + 0.5 - distance).clamp(0.0, 1.0)
+    } else if distance <= half_width.max(0.5) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn disk_coverage(distance: f32, radius: f32, antialias: bool) -> f32 {
+    if antialias {
+        (radius + 0.5 - distance).clamp(0.0, 1.0)
+    } else if distance <= radius.max(0.5) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// Pixels of the image within the box around the given extent, grown by margin
+fn clipped_box(img: &RgbaImage, min: (f32, f32), max: (f32, f32), margin: f32) -> Option<(i32, i32, i32, i32)> {
+    if !(is_finite_point(min) && is_finite_point(max) && margin.is_finite()) || img.width() == 0 || img.height() == 0 {
+        return None;
+    }
+    let x0 = (min.0 - margin).floor().max(0.0);
+    let y0 = (min.1 - margin).floor().max(0.0);
+    let x1 = (max.0 + margin).ceil().min(img.width() as f32 - 1.0);
+    let y1 = (max.1 + margin).ceil().min(img.height() as f32 - 1.0);
+    if !(x0 <= x1 && y0 <= y1) {
+        return None;
+    }
+    Some((x0 as i32, y0 as i32, x1 as i32, y1 as i32))
+}
+
+// Liang-Barsky clipping of the segment to the image grown by margin, None when it misses the image
+fn clip_segment(img: &RgbaImage, start: (f32, f32), end: (f32, f32), margin: f32) -> Option<((f32, f32), (f32, f32))> {
+    let (min_x, min_y) = (-margin, -margin);
+    let (max_x, max_y) = (img.width() as f32 - 1.0 + margin, img.height() as f32 - 1.0 + margin);
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let mut t0: f32 = 0.0;
+    let mut t1: f32 = 1.0;
+    for (p, q) in [
+        (-dx, start.0 - min_x),
+        (dx, max_x - start.0),
+        (-dy, start.1 - min_y),
+        (dy, max_y - start.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    Some(((start.0 + t0 * dx, start.1 + t0 * dy), (start.0 + t1 * dx, start.1 + t1 * dy)))
+}
+
+fn distance_to_segment(point: (f32, f32), start: (f32, f32), end: (f32, f32)) -> f32 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let (px, py) = (point.0 - start.0, point.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        ((px * dx + py * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    ((px - t * dx).powi(2) + (py - t * dy).powi(2)).sqrt()
+}
+
+// Segment between two pixel positions, drawn with round caps
+pub fn draw_line(img: &mut RgbaImage, start: (f32, f32), end: (f32, f32), stroke: &Stroke) {
+    if !(is_finite_point(start) && is_finite_point(end) && stroke.width.is_finite()) {
+        return;
+    }
+    let margin = stroke.width.max(0.0) / 2.0 + 1.0;
+    let Some((clipped_start, clipped_end)) = clip_segment(img, start, end, margin) else {
+        return;
+    };
+    let min = (clipped_start.0.min(clipped_end.0), clipped_start.1.min(clipped_end.1));
+    let max = (clipped_start.0.max(clipped_end.0), clipped_start.1.max(clipped_end.1));
+    let Some((x0, y0, x1, y1)) = clipped_box(img, min, max, margin) else {
+        return;
+    };
+    for x in x0..=x1 {
+        for y in y0..=y1 {
+            // Distances use the original segment, so caps stay where they were even when clipped
+            let distance = distance_to_segment((x as f32, y as f32), start, end);
+            blend_pixel(img, x, y, stroke.color, stroke_coverage(distance, stroke));
+        }
+    }
+}
+
+// Connected segments, closed back to the first point when requested
+pub fn draw_polyline(img: &mut RgbaImage, points: &[(f32, f32)], closed: bool, stroke: &Stroke) {
+    for pair in points.windows(2) {
+        draw_line(img, pair[0], pair[1], stroke);
+    }
+    if closed && points.len() > 2 {
+        draw_line(img, points[points.len() - 1], points[0], stroke);
+    }
+}
+
+// Line with an open arrow head at the end, the head legs are head_length long and 30 degrees off the shaft
+pub fn draw_arrow(img: &mut RgbaImage, start: (f32, f32), end: (f32, f32), head_length: f32, stroke: &Stroke) {
+    draw_line(img, start, end, stroke);
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if !(length > 0.0 && length.is_finite() && head_length.is_finite()) {
+        return;
+    }
+    let (ux, uy) = (dx / length, dy / length);
+    let (cos, sin) = (30f32.to_radians().cos(), 30f32.to_radians().sin());
+    for side in [-1.0, 1.0] {
+        let leg_x = -(ux * cos - side * uy * sin);
+        let leg_y = -(uy * cos + side * ux * sin);
+        draw_line(img, end, (end.0 + leg_x * head_length, end.1 + leg_y * head_length), stroke);
+    }
+}
+
+// Outline of an ellipse with semi-axes along and across the angle (radians, clockwise in image coordinates).
+// The distance to the curve is estimated from the implicit equation and its gradient.
+pub fn draw_ellipse(
+    img: &mut RgbaImage,
+    center: (f32, f32),
+    semi_major: f32,
+    semi_minor: f32,
+    angle: f32,
+    stroke: &Stroke,
+) {
+    if !(is_finite_point(center) && semi_major.is_finite() && semi_minor.is_finite() && angle.is_finite()) {
+        return;
+    }
+    let (semi_major, semi_minor) = (semi_major.abs(), semi_minor.abs());
+    let (cos, sin) = (angle.cos(), angle.sin());
+    if semi_major <= 0.5 || semi_minor <= 0.5 {
+        // Degenerate ellipse, a segment along its longer axis
+        let (ux, uy) = if semi_major >= semi_minor { (cos, sin) } else { (-sin, cos) };
+        let extent = semi_major.max(semi_minor);
+        let start = (center.0 - ux * extent, center.1 - uy * extent);
+        let end = (center.0 + ux * extent, center.1 + uy * extent);
+        draw_line(img, start, end, stroke);
+        return;
+    }
+
+    let extent = semi_major.max(semi_minor);
+    let Some((x0, y0, x1, y1)) = clipped_box(
+        img,
+        (center.0 - extent, center.1 - extent),
+        (center.0 + extent, center.1 + extent),
+        stroke.width.max(0.0) / 2.0 + 1.0,
+    ) else {
+        return;
+    };
+    let (a2, b2) = (semi_major * semi_major, semi_minor * semi_minor);
+    for x in x0..=x1 {
+        for y in y0..=y1 {
+            let (px, py) = (x as f32 - center.0, y as f32 - center.1);
+            let u = px * cos + py * sin;
+            let v = -px * sin + py * cos;
+            let implicit = u * u / a2 + v * v / b2 - 1.0;
+            let gradient = ((2.0 * u / a2).powi(2) + (2.0 * v / b2).powi(2)).sqrt();
+            let distance = if gradient > 0.0 { implicit.abs() / gradient } else { extent };
+            blend_pixel(img, x, y, stroke.color, stroke_coverage(distance, stroke));
+        }
+    }
+}
+
+pub fn draw_circle(img: &mut RgbaImage, center: (f32, f32), radius: f32, stroke: &Stroke) {
+    draw_ellipse(img, center, radius, radius, 0.0, stroke);
+}
+
+pub fn draw_filled_circle(img: &mut RgbaImage, center: (f32, f32), radius: f32, color: Rgba<u8>, antialias: bool) {
+    if !radius.is_finite() {
+        return;
+    }
+    let radius = radius.abs();
+    let Some((x0, y0, x1, y1)) = clipped_box(img, center, center, radius + 1.0) else {
+        return;
+    };
+    for x in x0..=x1 {
+        for y in y0..=y1 {
+            let distance = ((x as f32 - center.0).powi(2) + (y as f32 - center.1).powi(2)).sqrt();
+            blend_pixel(img, x, y, color, disk_coverage(distance, radius, antialias));
+        }
+    }
+}
+
+// Rectangle blended with the given opacity, clipped to the image
+pub fn fill_rect(img: &mut RgbaImage, x: i32, y: i32, width: i32, height: i32, color: Rgba<u8>, opacity: f32) {
+    let x0 = x.max(0);
+    let y0 = y.max(0);
+    let x1 = x.saturating_add(width.max(0)).min(img.width() as i32);
+    let y1 = y.saturating_add(height.max(0)).min(img.height() as i32);
+    for px in x0..x1 {
+        for py in y0..y1 {
+            blend_pixel(img, px, py, color, opacity);
+        }
+    }
+}
+
+// Even-odd point in polygon test
+fn polygon_contains(points: &[(f32, f32)], point: (f32, f32)) -> bool {
+    let mut inside = false;
+    let mut previous = points[points.len() - 1];
+    for &current in points {
+        if (current.1 > point.1) != (previous.1 > point.1) {
+            let crossing_x = current.0 + (point.1 - current.1) * (previous.0 - current.0) / (previous.1 - current.1);
+            if point.0 < crossing_x {
+                inside = !inside;
+            }
+        }
+        previous = current;
+    }
+    inside
+}
+
+// Interior of a closed polygon under the even-odd rule. Anti-aliased edges come from 4x4 samples per pixel.
+pub fn fill_polygon(img: &mut RgbaImage, points: &[(f32, f32)], color: Rgba<u8>, antialias: bool) {
+    const SAMPLES: i32 = 4;
+    if points.len() < 3 || !points.iter().all(|&point| is_finite_point(point)) {
+        return;
+    }
+    let min = points.iter().fold((f32::MAX, f32::MAX), |acc, p| (acc.0.min(p.0), acc.1.min(p.1)));
+    let max = points.iter().fold((f32::MIN, f32::MIN), |acc, p| (acc.0.max(p.0), acc.1.max(p.1)));
+    let Some((x0, y0, x1, y1)) = clipped_box(img, min, max, 1.0) else {
+        return;
+    };
+    for x in x0..=x1 {
+        for y in y0..=y1 {
+            let coverage = if antialias {
+                let inside = (0..SAMPLES * SAMPLES)
+                    .filter(|&i| {
+                        let sx = x as f32 - 0.5 + ((i % SAMPLES) as f32 + 0.5) / SAMPLES as f32;
+                        let sy = y as f32 - 0.5 + ((i / SAMPLES) as f32 + 0.5) / SAMPLES as f32;
+                        polygon_contains(points, (sx, sy))
+                    })
+                    .count();
+                inside as f32 / (SAMPLES * SAMPLES) as f32
+            } else if polygon_contains(points, (x as f32, y as f32)) {
+                1.0
+            } else {
+                0.0
+            };
+            blend_pixel(img, x, y, color, coverage);
+        }
+    }
+}
+
+// Filled and / or stroked polygon, the fill is drawn first so the outline stays on top
+pub fn draw_polygon(img: &mut RgbaImage, points: &[(f32, f32)], fill: Option<Rgba<u8>>, stroke: Option<&Stroke>) {
+    if let Some(color) = fill {
+        let antialias = stroke.is_none_or(|stroke| stroke.antialias);
+        fill_polygon(img, points, color, antialias);
+    }
+    if let Some(stroke) = stroke {
+        draw_polyline(img, points, true, stroke);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    fn canvas() -> RgbaImage {
+        RgbaImage::from_pixel(20, 10, BLACK)
+    }
+
+    fn stroke(width: f32, antialias: bool) -> Stroke {
+        Stroke { width, color: WHITE, antialias }
+    }
+
+    fn painted(img: &RgbaImage) -> usize {
+        img.pixels().filter(|&&pixel| pixel != BLACK).count()
+    }
+
+    #[test]
+    fn blend_pixel_ignores_positions_off_the_image() {
+        let mut img = canvas();
+        for (x, y) in [(-1, 0), (0, -1), (20, 0), (0, 10), (i32::MAX, i32::MAX), (i32::MIN, 5)] {
+            blend_pixel(&mut img, x, y, WHITE, 1.0);
+        }
+        blend_pixel(&mut img, 19, 9, WHITE, f32::NAN);
+        assert_eq!(painted(&img), 0);
+
+        blend_pixel(&mut img, 19, 9, WHITE, 1.0);
+        assert_eq!(*img.get_pixel(19, 9), WHITE);
+    }
+
+    #[test]
+    fn line_across_the_border_is_clipped() {
+        for antialias in [true, false] {
+            let mut img = canvas();
+            draw_line(&mut img, (-50.0, 5.0), (70.0, 5.0), &stroke(1.0, antialias));
+            assert_eq!(*img.get_pixel(0, 5), WHITE);
+            assert_eq!(*img.get_pixel(19, 5), WHITE);
+
+            let mut img = canvas();
+            draw_line(&mut img, (15.0, 8.0), (25.0, 30.0), &stroke(3.0, antialias));
+            assert!(painted(&img) > 0);
+        }
+    }
+
+    #[test]
+    fn line_outside_of_the_image_draws_nothing() {
+        let mut img = canvas();
+        draw_line(&mut img, (-10.0, -10.0), (-10.0, 40.0), &stroke(2.0, true));
+        draw_line(&mut img, (30.0, 0.0), (60.0, 9.0), &stroke(2.0, true));
+        draw_line(&mut img, (-1e30, 5.0), (-1e29, 5.0), &stroke(1.0, true));
+        assert_eq!(painted(&img), 0);
+    }
+
+    #[test]
+    fn huge_and_non_finite_coordinates_do_not_panic() {
+        let mut img = canvas();
+        draw_line(&mut img, (-1e6, -1e6), (1e6, 1e6), &stroke(1.0, true));
+        assert!(painted(&img) > 0);
+
+        let mut img = canvas();
+        draw_line(&mut img, (f32::NAN, 0.0), (5.0, 5.0), &stroke(1.0, true));
+        draw_line(&mut img, (0.0, 0.0), (f32::INFINITY, 5.0), &stroke(1.0, true));
+        draw_line(&mut img, (0.0, 0.0), (5.0, 5.0), &stroke(f32::NAN, true));
+        draw_ellipse(&mut img, (5.0, 5.0), f32::INFINITY, 3.0, 0.0, &stroke(1.0, true));
+        draw_filled_circle(&mut img, (f32::NAN, 5.0), 3.0, WHITE, true);
+        fill_polygon(&mut img, &[(0.0, 0.0), (f32::NAN, 5.0), (5.0, 5.0)], WHITE, true);
+        draw_arrow(&mut img, (f32::NAN, 5.0), (5.0, 5.0), 4.0, &stroke(1.0, true));
+        assert_eq!(painted(&img), 0);
+    }
+
+    #[test]
+    fn ellipse_at_the_right_and_bottom_edges_is_clipped() {
+        for antialias in [true, false] {
+            let mut img = canvas();
+            draw_ellipse(&mut img, (19.0, 9.0), 8.0, 4.0, 0.7, &stroke(2.0, antialias));
+            assert!(painted(&img) > 0);
+
+            let mut img = canvas();
+            draw_ellipse(&mut img, (24.0, 12.0), 8.0, 4.0, 0.0, &stroke(1.0, antialias));
+            assert!(painted(&img) > 0);
+        }
+    }
+
+    #[test]
+    fn degenerate_ellipse_draws_a_segment() {
+        let mut img = canvas();
+        draw_ellipse(&mut img, (10.0, 5.0), 4.0, 0.0, 0.0, &stroke(1.0, false));
+        assert_eq!(*img.get_pixel(6, 5), WHITE);
+        assert_eq!(*img.get_pixel(14, 5), WHITE);
+        assert_eq!(*img.get_pixel(10, 3), BLACK);
+    }
+
+    #[test]
+    fn circles_at_the_corners_are_clipped() {
+        for center in [(0.0, 0.0), (19.0, 0.0), (0.0, 9.0), (19.0, 9.0)] {
+            let mut img = canvas();
+            draw_filled_circle(&mut img, center, 3.0, WHITE, true);
+            assert_eq!(*img.get_pixel(center.0 as u32, center.1 as u32), WHITE);
+
+            let mut img = canvas();
+            draw_circle(&mut img, center, 3.0, &stroke(1.0, false));
+            assert!(painted(&img) > 0);
+        }
+    }
+
+    #[test]
+    fn arrow_pointing_off_the_image_is_clipped() {
+        let mut img = canvas();
+        draw_arrow(&mut img, (10.0, 5.0), (25.0, 5.0), 6.0, &stroke(1.0, true));
+        assert_eq!(*img.get_pixel(19, 5), WHITE);
+
+        let mut img = canvas();
+        draw_arrow(&mut img, (5.0, 5.0), (19.0, 5.0), 4.0, &stroke(1.0, false));
+        // Both head legs end left of the tip, above and below the shaft
+        assert_ne!(*img.get_pixel(16, 3), BLACK);
+        assert_ne!(*img.get_pixel(16, 7), BLACK);
+    }
+
+    #[test]
+    fn rectangles_are_clipped() {
+        let mut img = canvas();
+        fill_rect(&mut img, -5, -5, 10, 10, WHITE, 1.0);
+        assert_eq!(painted(&img), 25);
+
+        let mut img = canvas();
+        fill_rect(&mut img, 15, 8, i32::MAX, i32::MAX, WHITE, 1.0);
+        assert_eq!(painted(&img), 10);
+
+        let mut img = canvas();
+        fill_rect(&mut img, 5, 5, -3, 4, WHITE, 1.0);
+        assert_eq!(painted(&img), 0);
+    }
+
+    #[test]
+    fn polygon_partly_outside_is_filled_inside_only() {
+        let square = [(9.5, -5.0), (30.0, -5.0), (30.0, 15.0), (9.5, 15.0)];
+        let mut img = canvas();
+        fill_polygon(&mut img, &square, WHITE, false);
+        assert_eq!(painted(&img), 100);
+
+        let mut img = canvas();
+        draw_polygon(&mut img, &square, Some(WHITE), Some(&stroke(1.0, true)));
+        assert_eq!(*img.get_pixel(19, 9), WHITE);
+        assert_eq!(*img.get_pixel(5, 5), BLACK);
+    }
+
+    #[test]
+    fn polygon_outline_only_leaves_the_interior() {
+        let triangle = [(2.0, 1.0), (18.0, 1.0), (10.0, 9.0)];
+        let mut img = canvas();
+        draw_polygon(&mut img, &triangle, None, Some(&stroke(1.0, false)));
+        assert_eq!(*img.get_pixel(10, 1), WHITE);
+        assert_eq!(*img.get_pixel(10, 4), BLACK);
+    }
+
+    #[test]
+    fn drawing_on_an_empty_image_does_not_panic() {
+        let mut img = RgbaImage::new(0, 0);
+        draw_line(&mut img, (0.0, 0.0), (5.0, 5.0), &stroke(1.0, true));
+        draw_ellipse(&mut img, (0.0, 0.0), 5.0, 3.0, 0.0, &stroke(1.0, true));
+        draw_filled_circle(&mut img, (0.0, 0.0), 5.0, WHITE, true);
+        fill_rect(&mut img, 0, 0, 5, 5, WHITE, 1.0);
+        fill_polygon(&mut img, &[(0.0, 0.0), (5.0, 0.0), (0.0, 5.0)], WHITE, true);
+        assert_eq!(img.len(), 0);
+    }
+}
@@ -0,0 +1,184 @@
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use crate::pixel::Pixel;
+use crate::storm::Storm;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EllipseMethod {
+    // Major axis towards the farthest core pixel, minor axis from the farthest pixel to that line
+    #[default]
+    FarthestPixel,
+    // Intensity weighted second-order image moments (covariance / PCA)
+    Moments,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EllipseFit {
+    pub center: Point2<f64>,
+    pub major_axis: f64,
+    pub minor_axis: f64,
+    pub angle: f64,
+    pub eccentricity: f64,
+}
+
+// Only core pixels reaching core_dbz take part in the fit
+pub fn fit_ellipse(storm: &Storm, method: EllipseMethod, core_dbz: u32) -> EllipseFit {
+    match method {
+        EllipseMethod::FarthestPixel => fit_farthest_pixel(storm, core_dbz),
+        EllipseMethod::Moments => fit_moments(storm, core_dbz),
+    }
+}
+
+fn fit_farthest_pixel(storm: &Storm, core_dbz: u32) -> EllipseFit {
+    let (major_axis, farthest_pixel) = longest_distance_from_center(storm, core_dbz);
+    let center = Point2::new(storm.intensity_center.x as f64, storm.intensity_center.y as f64);
+    let farthest_pixel_f64 = Point2::new(farthest_pixel.x as f64, farthest_pixel.y as f64);
+    let dx = farthest_pixel_f64.x - center.x;
+    let dy = farthest_pixel_f64.y - center.y;
+    let angle = dy.atan2(dx);
+
+    // Define the major axis line
+    let major_axis_line = (storm.intensity_center, farthest_pixel);
+    let minor_axis = farthest_distance_from_line(storm, major_axis_line, core_dbz);
+
+    EllipseFit {
+        center,
+        major_axis,
+        minor_axis,
+        angle,
+        eccentricity: calculate_eccentricity(major_axis, minor_axis),
+    }
+}
+
+fn fit_moments(storm: &Storm, core_dbz: u32) -> EllipseFit {
+    let core: Vec<&Pixel> = storm.pixels.iter().filter(|pixel| pixel.intensity >= core_dbz).collect();
+    let sum_weight: f64 = core.iter().map(|pixel| pixel.intensity as f64).sum();
+    if sum_weight == 0.0 {
+        let center = Point2::new(storm.intensity_center.x as f64, storm.intensity_center.y as f64);
+        return EllipseFit { center, major_axis: 0.0, minor_axis: 0.0, angle: 0.0, eccentricity: 0.0 };
+    }
+
+    let mean_x = core.iter().map(|pixel| pixel.x as f64 * pixel.intensity as f64).sum::<f64>() / sum_weight;
+    let mean_y = core.iter().map(|pixel| pixel.y as f64 * pixel.intensity as f64).sum::<f64>() / sum_weight;
+
+    let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+    for pixel in &core {
+        let weight = pixel.intensity as f64;
+        let dx = pixel.x as f64 - mean_x;
+        let dy = pixel.y as f64 - mean_y;
+        sxx += weight * dx * dx;
+        syy += weight * dy * dy;
+        sxy += weight * dx * dy;
+    }
+    // Each pixel covers a unit square, whose own variance is 1/12 along both axes
+    sxx = sxx / sum_weight + 1.0 / 12.0;
+    syy = syy / sum_weight + 1.0 / 12.0;
+    sxy /= sum_weight;
+
+    // Eigenvalues of the covariance matrix
+    let mean_var = (sxx + syy) / 2.0;
+    let spread = (((sxx - syy) / 2.0).powi(2) + sxy * sxy).sqrt();
+    let major_var = mean_var + spread;
+    let minor_var = (mean_var - spread).max(0.0);
+
+    // A uniformly filled ellipse with semi-axis a has variance a^2 / 4 along that axis
+    let major_axis = 2.0 * major_var.sqrt();
+    let minor_axis = 2.0 * minor_var.sqrt();
+    let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+
+    EllipseFit {
+        center: Point2::new(mean_x, mean_y),
+        major_axis,
+        minor_axis,
+        angle,
+        eccentricity: calculate_eccentricity(major_axis, minor_axis),
+    }
+}
+
+fn longest_distance_from_center(storm: &Storm, core_dbz: u32) -> (f64, Point2<i32>) {
+    let (max_distance, farthest_pixel) = storm.pixels.iter()
+        .filter_map(|pixel| {
+            if pixel.intensity >= core_dbz {
+                let dx = (pixel.x - storm.intensity_center.x) as f64;
+                let dy = (pixel.y - storm.intensity_center.y) as f64;
+                Some(((dx * dx + dy * dy).sqrt(), Point2::new(pixel.x, pixel.y)))
+            } else {
+                None
+            }
+        })
+        .fold((0.0, Point2::new(0, 0)), |(max_dist, max_pixel), (dist, pixel)| {
+            if dist > max_dist {
+                (dist, pixel)
+            } else {
+                (max_dist, max_pixel)
+            }
+        });
+    (max_distance, farthest_pixel)
+}
+
+fn farthest_distance_from_line(storm: &Storm, line: (Point2<i32>, Point2<i32>), core_dbz: u32) -> f64 {
+    let (start, end) = line;
+    let (x0, y0) = (start.x as f64, start.y as f64);
+    let (x1, y1) = (end.x as f64, end.y as f64);
+
+    storm.pixels.iter()
+        .filter_map(|pixel| {
+            if pixel.intensity >= core_dbz {
+                let (x, y) = (pixel.x as f64, pixel.y as f64);
+                let distance = ((y1 - y0) * x - (x1 - x0) * y + x1 * y0 - y1 * x0).abs() / ((y1 - y0).powi(2) + (x1 - x0).powi(2)).sqrt();
+                Some(distance)
+            } else {
+                None
+            }
+        })
+        .fold(0.0, f64::max)
+}
+
+fn calculate_eccentricity(major_axis: f64, minor_axis: f64) -> f64 {
+    if major_axis <= 0.0 {
+        return 0.0;
+    }
+    (1.0 - (minor_axis.powi(2) / major_axis.powi(2))).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block, storm_from_pixels};
+
+    fn storm(cells: &[(i32, i32, u32)]) -> Storm {
+        Storm { intensity_center: Point2::new(10, 2), ..storm_from_pixels(1, cells) }
+    }
+
+    // 21 x 5 block of core echo elongated along x, centered on (10, 2)
+    fn band() -> Vec<(i32, i32, u32)> {
+        block(0, 0, 21, 5, 50)
+    }
+
+    #[test]
+    fn moments_fit_is_not_rotated_by_a_single_outlier() {
+        let mut cells = band();
+        cells.push((10, 14, 50));
+        let fit = fit_ellipse(&storm(&cells), EllipseMethod::Moments, 45);
+        assert!(fit.angle.abs() < 0.05, "angle {}", fit.angle);
+        assert!(fit.major_axis > fit.minor_axis);
+
+        // The farthest pixel fit points its major axis at the outlier instead
+        let farthest = fit_ellipse(&storm(&cells), EllipseMethod::FarthestPixel, 45);
+        assert!((farthest.angle - std::f64::consts::FRAC_PI_2).abs() < 0.05, "angle {}", farthest.angle);
+    }
+
+    #[test]
+    fn only_pixels_reaching_core_dbz_take_part_in_the_fit() {
+        let mut cells = band();
+        // A weak trailing band along y is ignored at a 45 dBZ core
+        cells.extend((5..40).map(|y| (10, y, 40)));
+        let fit = fit_ellipse(&storm(&cells), EllipseMethod::Moments, 45);
+        assert!(fit.angle.abs() < 0.05, "angle {}", fit.angle);
+        assert!((fit.center.y - 2.0).abs() < 1e-9);
+
+        let all = fit_ellipse(&storm(&cells), EllipseMethod::Moments, 40);
+        assert!(all.center.y > 2.0);
+    }
+}
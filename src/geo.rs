@@ -0,0 +1,69 @@
+use nalgebra::Point2;
+use crate::config::StationConfig;
+use crate::storm::Storm;
+
+const KM_PER_DEGREE_LATITUDE: f64 = 111.32;
+
+// Local equirectangular projection around the station, adequate within the 230 km product range
+pub fn pixel_to_lat_lon(x: f64, y: f64, station: &StationConfig) -> (f64, f64) {
+    let radar_center = station.radar_center();
+    let east_km = (x - radar_center.x) * station.km_per_pixel;
+    let north_km = (radar_center.y - y) * station.km_per_pixel;
+    let latitude = station.latitude + north_km / KM_PER_DEGREE_LATITUDE;
+    let longitude = station.longitude + east_km / (KM_PER_DEGREE_LATITUDE * station.latitude.to_radians().cos());
+    (latitude, longitude)
+}
+
+pub fn lat_lon_to_pixel(latitude: f64, longitude: f64, station: &StationConfig) -> Point2<f64> {
+    let radar_center = station.radar_center();
+    let north_km = (latitude - station.latitude) * KM_PER_DEGREE_LATITUDE;
+    let east_km = (longitude - station.longitude) * KM_PER_DEGREE_LATITUDE * station.latitude.to_radians().cos();
+    Point2::new(radar_center.x + east_km / station.km_per_pixel, radar_center.y - north_km / station.km_per_pixel)
+}
+
+// Convex hull of the pixel corners of the storm as a closed ring of (latitude, longitude)
+pub fn storm_footprint(storm: &Storm, station: &StationConfig) -> Vec<(f64, f64)> {
+    let mut corners: Vec<(i32, i32)> = storm
+        .pixels
+        .iter()
+        .flat_map(|pixel| [(pixel.x, pixel.y), (pixel.x + 1, pixel.y), (pixel.x, pixel.y + 1), (pixel.x + 1, pixel.y + 1)])
+        .collect();
+    corners.sort_unstable();
+    corners.dedup();
+    let mut ring: Vec<(f64, f64)> = convex_hull(&corners)
+        .into_iter()
+        .map(|(x, y)| pixel_to_lat_lon(x as f64, y as f64, station))
+        .collect();
+    if let Some(&first) = ring.first() {
+        ring.push(first);
+    }
+    ring
+}
+
+// Monotone chain over sorted, deduplicated points, counter-clockwise in image coordinates
+fn convex_hull(points: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let cross = |o: (i32, i32), a: (i32, i32), b: (i32, i32)| {
+        (a.0 - o.0) as i64 * (b.1 - o.1) as i64 - (a.1 - o.1) as i64 * (b.0 - o.0) as i64
+    };
+    let mut hull: Vec<(i32, i32)> = Vec::with_capacity(points.len() + 1);
+    for pass in 0..2 {
+        let start = hull.len();
+        let mut chain = |point: (i32, i32)| {
+            while hull.len() >= start + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0 {
+                hull.pop();
+            }
+            hull.push(point);
+        };
+        if pass == 0 {
+            points.iter().copied().for_each(&mut chain);
+        } else {
+            points.iter().rev().copied().for_each(&mut chain);
+        }
+        // The last point of each chain starts the other one
+        hull.pop();
+    }
+    hull
+}
@@ -0,0 +1,60 @@
+use crate::pixel::{Palette, Pixel};
+
+// Decoded reflectivity of the radar area, 0 stands for no echo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflectivityGrid {
+    pub width: u32,
+    pub height: u32,
+    data: Vec<u32>,
+}
+
+impl ReflectivityGrid {
+    pub fn new(width: u32, height: u32) -> Self {
+        ReflectivityGrid {
+            width,
+            height,
+            data: vec![0; (width * height) as usize],
+        }
+    }
+
+    pub fn from_pixels(pixels: &[Pixel], radar_area: (u32, u32)) -> Self {
+        let mut grid = ReflectivityGrid::new(radar_area.0, radar_area.1);
+        for pixel in pixels {
+            grid.set(pixel.x, pixel.y, pixel.intensity);
+        }
+        grid
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    // Positions outside of the grid read as no echo
+    pub fn get(&self, x: i32, y: i32) -> u32 {
+        if self.contains(x, y) {
+            self.data[(y as u32 * self.width + x as u32) as usize]
+        } else {
+            0
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, intensity: u32) {
+        if self.contains(x, y) {
+            self.data[(y as u32 * self.width + x as u32) as usize] = intensity;
+        }
+    }
+
+    // Echo pixels in the same x-major order decode_pixels produces, colored from the palette
+    pub fn to_pixels(&self, palette: &Palette) -> Vec<Pixel> {
+        let mut pixels = Vec::new();
+        for x in 0..self.width as i32 {
+            for y in 0..self.height as i32 {
+                let intensity = self.get(x, y);
+                if let Some(color) = palette.color_of(intensity) {
+                    pixels.push(Pixel { x, y, color, intensity });
+                }
+            }
+        }
+        pixels
+    }
+}
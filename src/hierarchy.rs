@@ -0,0 +1,242 @@
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use crate::consts::*;
+use crate::pixel::Pixel;
+use crate::storm::{
+    acquire_maximum_reflectivity, calculate_azimuth_degrees, calculate_herb_center_distance,
+    calculate_weighted_center, group_adjacent_pixels,
+};
+
+// One rung of the reflectivity ladder, components smaller than min_size are dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdLevel {
+    pub threshold: u32,
+    pub min_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HierarchyParams {
+    pub enabled: bool,
+    // Ascending thresholds, each level is searched inside the components of the previous one
+    pub levels: Vec<ThresholdLevel>,
+    // Components at or above these thresholds are reported as cells and cores respectively
+    pub cell_threshold: u32,
+    pub core_threshold: u32,
+    // Pixel gap and scale follow the detection and station configuration
+    #[serde(skip, default = "default_adjacent_threshold")]
+    pub adjacent_threshold: i32,
+    #[serde(skip, default = "default_km_per_pixel")]
+    pub km_per_pixel: f64,
+}
+
+fn default_adjacent_threshold() -> i32 {
+    ADJACENT_THRESHOLD
+}
+
+fn default_km_per_pixel() -> f64 {
+    DISTANCE_RATIO
+}
+
+impl Default for HierarchyParams {
+    fn default() -> Self {
+        let levels = [(30, 40), (35, 30), (40, 20), (45, 15), (50, 10), (55, 6), (60, 4)]
+            .iter()
+            .map(|&(threshold, min_size)| ThresholdLevel { threshold, min_size })
+            .collect();
+        HierarchyParams {
+            enabled: false,
+            levels,
+            cell_threshold: 40,
+            core_threshold: 50,
+            adjacent_threshold: ADJACENT_THRESHOLD,
+            km_per_pixel: DISTANCE_RATIO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellKind {
+    Complex,
+    Cell,
+    Core,
+}
+
+impl CellKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CellKind::Complex => "complex",
+            CellKind::Cell => "cell",
+            CellKind::Core => "core",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StormCell {
+    pub cell_id: u32,
+    pub parent_id: Option<u32>,
+    pub children: Vec<u32>,
+    pub level: usize,
+    pub threshold: u32,
+    pub kind: CellKind,
+    pub intensity_center: Point2<i32>,
+    pub distance: f64,
+    pub direction: f64,
+    pub max_intensity: u32,
+    pub pixels: Vec<Pixel>,
+}
+
+#[derive(Debug, Default)]
+pub struct StormHierarchy {
+    // Cells ordered level by level, cell_id is the position in this list plus one
+    pub cells: Vec<StormCell>,
+}
+
+impl StormHierarchy {
+    pub fn get(&self, cell_id: u32) -> Option<&StormCell> {
+        cell_id.checked_sub(1).and_then(|i| self.cells.get(i as usize))
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &StormCell> {
+        self.cells.iter().filter(|cell| cell.parent_id.is_none())
+    }
+
+    pub fn children_of(&self, cell_id: u32) -> impl Iterator<Item = &StormCell> {
+        self.get(cell_id)
+            .map(|cell| cell.children.as_slice())
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(move |&child_id| self.get(child_id))
+    }
+
+    // All descendants of the given kind, e.g. the individual cells of a multicell complex
+    pub fn descendants_of_kind(&self, cell_id: u32, kind: CellKind) -> Vec<&StormCell> {
+        let mut result = Vec::new();
+        let mut stack: Vec<u32> = self.get(cell_id).map(|cell| cell.children.clone()).unwrap_or_default();
+        while let Some(id) = stack.pop() {
+            if let Some(cell) = self.get(id) {
+                if cell.kind == kind {
+                    result.push(cell);
+                }
+                stack.extend(cell.children.iter().copied());
+            }
+        }
+        result.sort_by_key(|cell| cell.cell_id);
+        result
+    }
+}
+
+pub fn identify_storm_hierarchy(pixel_list: &[Pixel], radar_center: &Point2<f64>, params: &HierarchyParams) -> StormHierarchy {
+    let mut hierarchy = StormHierarchy::default();
+    // Pixel sets of the previous level, paired with the id of the cell they came from
+    let mut parents: Vec<(Option<u32>, Vec<Pixel>)> = vec![(None, pixel_list.to_vec())];
+
+    for (level, threshold_level) in params.levels.iter().enumerate() {
+        let mut level_cells = Vec::new();
+        for (parent_id, parent_pixels) in &parents {
+            let candidates: Vec<Pixel> = parent_pixels
+                .iter()
+                .filter(|pixel| pixel.intensity >= threshold_level.threshold)
+                .copied()
+                .collect();
+            for component in group_adjacent_pixels(&candidates, params.adjacent_threshold) {
+                if component.len() < threshold_level.min_size {
+                    continue;
+                }
+                level_cells.push(build_cell(component, *parent_id, level, threshold_level.threshold, radar_center, params));
+            }
+        }
+
+        // Within a level, order cells by distance like merge_pixels does
+        level_cells.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+
+        parents.clear();
+        for mut cell in level_cells {
+            cell.cell_id = (hierarchy.cells.len() + 1) as u32;
+            if let Some(parent_id) = cell.parent_id {
+                hierarchy.cells[(parent_id - 1) as usize].children.push(cell.cell_id);
+            }
+            parents.push((Some(cell.cell_id), cell.pixels.clone()));
+            hierarchy.cells.push(cell);
+        }
+
+        if parents.is_empty() {
+            break;
+        }
+    }
+
+    hierarchy
+}
+
+fn build_cell(
+    pixels: Vec<Pixel>,
+    parent_id: Option<u32>,
+    level: usize,
+    threshold: u32,
+    radar_center: &Point2<f64>,
+    params: &HierarchyParams,
+) -> StormCell {
+    let intensity_center = calculate_weighted_center(&pixels, threshold);
+    let distance = calculate_herb_center_distance(&intensity_center, radar_center, params.km_per_pixel);
+    let center_f64 = Point2::new(intensity_center.x as f64, intensity_center.y as f64);
+    let direction = calculate_azimuth_degrees(&center_f64, radar_center);
+    let kind = if threshold >= params.core_threshold {
+        CellKind::Core
+    } else if threshold >= params.cell_threshold {
+        CellKind::Cell
+    } else {
+        CellKind::Complex
+    };
+
+    StormCell {
+        cell_id: 0, // Assigned once the level is sorted
+        parent_id,
+        children: Vec::new(),
+        level,
+        threshold,
+        kind,
+        intensity_center,
+        distance,
+        direction,
+        max_intensity: acquire_maximum_reflectivity(&pixels),
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn complex_reports_its_cells_with_their_parent() {
+        // A 35 dBZ band holding two separate 50 dBZ cells
+        let pixels: Vec<Pixel> = (0..30)
+            .flat_map(|x| (0..6).map(move |y| (x, y)))
+            .map(|(x, y)| {
+                let intensity = if (2..8).contains(&x) || (22..28).contains(&x) { 50 } else { 35 };
+                Pixel { x, y, color: Rgba([0, 0, 0, 255]), intensity }
+            })
+            .collect();
+        let params = HierarchyParams {
+            levels: vec![ThresholdLevel { threshold: 35, min_size: 20 }, ThresholdLevel { threshold: 50, min_size: 10 }],
+            ..HierarchyParams::default()
+        };
+        let hierarchy = identify_storm_hierarchy(&pixels, &Point2::new(15.0, 3.0), &params);
+
+        let roots: Vec<&StormCell> = hierarchy.roots().collect();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].kind, CellKind::Complex);
+        assert_eq!(roots[0].pixels.len(), 180);
+
+        let cores = hierarchy.descendants_of_kind(roots[0].cell_id, CellKind::Core);
+        assert_eq!(cores.len(), 2);
+        for core in cores {
+            assert_eq!(core.parent_id, Some(roots[0].cell_id));
+            assert_eq!(core.pixels.len(), 36);
+        }
+    }
+}
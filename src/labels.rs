@@ -0,0 +1,223 @@
+use imageproc::drawing::text_size;
+use rusttype::Scale;
+use std::collections::HashSet;
+use crate::render::{RenderStyle, FONT};
+use crate::storm::{DetectionParams, Storm};
+
+// Gap between the storm center and the label box, one entry per ring of candidates from near to far
+const LABEL_DISTANCES: [f32; 3] = [12.0, 26.0, 44.0];
+// Candidate directions in order of preference, the first matches the historic up-right placement
+const LABEL_DIRECTIONS: [(i32, i32); 8] = [(1, -1), (1, 1), (-1, -1), (-1, 1), (1, 0), (-1, 0), (0, -1), (0, 1)];
+const LABEL_PADDING: i32 = 2;
+
+const LABEL_OVERLAP_COST: f64 = 10_000.0;
+const MARKER_OVERLAP_COST: f64 = 500.0;
+const CORE_PIXEL_COST: f64 = 5.0;
+const RING_COST: f64 = 20.0;
+const DIRECTION_COST: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelBox {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl LabelBox {
+    fn grown(&self, margin: i32) -> LabelBox {
+        LabelBox {
+            x: self.x - margin,
+            y: self.y - margin,
+            width: self.width + 2 * margin,
+            height: self.height + 2 * margin,
+        }
+    }
+
+    fn intersects(&self, other: &LabelBox) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    fn inside(&self, bounds: (u32, u32)) -> bool {
+        self.x >= 0 && self.y >= 0 && self.x + self.width <= bounds.0 as i32 && self.y + self.height <= bounds.1 as i32
+    }
+
+    // Point of the box nearest to the given position, where a leader line ends
+    pub fn nearest_point(&self, point: (f32, f32)) -> (f32, f32) {
+        (
+            point.0.clamp(self.x as f32, (self.x + self.width) as f32),
+            point.1.clamp(self.y as f32, (self.y + self.height) as f32),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelPlacement {
+    pub storm_id: u32,
+    pub text: String,
+    // Top left of the text, text_box includes the padding used for background boxes
+    pub x: i32,
+    pub y: i32,
+    pub text_box: LabelBox,
+    pub anchor: (f32, f32),
+    // Labels moved off the default up-right candidate get a leader line back to the center
+    pub leader: bool,
+}
+
+// Greedy placement, strongest storms first. Every candidate box around the storm center is scored by
+// collisions with placed labels, center markers and core echoes; boxes reaching past the bounds are only
+// used when nothing else fits, and are then shifted back inside.
+pub fn place_labels(storms: &[Storm], params: &DetectionParams, style: &RenderStyle, bounds: (u32, u32)) -> Vec<LabelPlacement> {
+    let scale = Scale::uniform(style.label_font_size);
+    let core_pixels: HashSet<(i32, i32)> = storms
+        .iter()
+        .flat_map(|storm| &storm.pixels)
+        .filter(|pixel| pixel.intensity >= params.core_dbz)
+        .map(|pixel| (pixel.x, pixel.y))
+        .collect();
+    let marker_radius = style.center_radius.ceil() as i32 + 1;
+    let markers: Vec<LabelBox> = storms
+        .iter()
+        .map(|storm| LabelBox {
+            x: storm.intensity_center.x - marker_radius,
+            y: storm.intensity_center.y - marker_radius,
+            width: 2 * marker_radius + 1,
+            height: 2 * marker_radius + 1,
+        })
+        .collect();
+
+    let mut order: Vec<&Storm> = storms.iter().collect();
+    order.sort_by(|a, b| b.max_intensity.cmp(&a.max_intensity).then(a.storm_id.cmp(&b.storm_id)));
+
+    let mut placed: Vec<LabelPlacement> = Vec::new();
+    for storm in order {
+        let text = format!("#{}", storm.storm_id);
+        let (text_width, text_height) = text_size(scale, &FONT, &text);
+        let anchor = (storm.intensity_center.x as f32, storm.intensity_center.y as f32);
+
+        let mut best: Option<(f64, LabelBox, bool)> = None;
+        for (ring, &distance) in LABEL_DISTANCES.iter().enumerate() {
+            for (rank, &direction) in LABEL_DIRECTIONS.iter().enumerate() {
+                let text_box = candidate_box(anchor, direction, distance, text_width, text_height).grown(LABEL_PADDING);
+                if !text_box.inside(bounds) {
+                    continue;
+                }
+                let cost = placement_cost(&text_box, &placed, &markers, &core_pixels)
+                    + ring as f64 * RING_COST
+                    + rank as f64 * DIRECTION_COST;
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, text_box, ring > 0 || rank > 0));
+                }
+            }
+        }
+
+        let (text_box, leader) = match best {
+            Some((_, text_box, moved)) => (text_box, moved),
+            None => {
+                let preferred = candidate_box(anchor, LABEL_DIRECTIONS[0], LABEL_DISTANCES[0], text_width, text_height);
+                (shift_inside(preferred.grown(LABEL_PADDING), bounds), true)
+            }
+        };
+        placed.push(LabelPlacement {
+            storm_id: storm.storm_id,
+            text,
+            x: text_box.x + LABEL_PADDING,
+            y: text_box.y + LABEL_PADDING,
+            text_box,
+            anchor,
+            leader,
+        });
+    }
+
+    placed.sort_by_key(|placement| placement.storm_id);
+    placed
+}
+
+// Text box offset from the anchor along the direction, diagonals keep the same gap to the corner
+fn candidate_box(anchor: (f32, f32), direction: (i32, i32), distance: f32, width: i32, height: i32) -> LabelBox {
+    let (ux, uy) = direction;
+    let gap = if ux != 0 && uy != 0 { distance * std::f32::consts::FRAC_1_SQRT_2 } else { distance };
+    let x = anchor.0 + ux as f32 * gap + (ux - 1) as f32 * width as f32 / 2.0;
+    let y = anchor.1 + uy as f32 * gap + (uy - 1) as f32 * height as f32 / 2.0;
+    LabelBox {
+        x: x.round() as i32,
+        y: y.round() as i32,
+        width,
+        height,
+    }
+}
+
+fn placement_cost(text_box: &LabelBox, placed: &[LabelPlacement], markers: &[LabelBox], core_pixels: &HashSet<(i32, i32)>) -> f64 {
+    let label_overlaps = placed.iter().filter(|other| other.text_box.intersects(text_box)).count();
+    let marker_overlaps = markers.iter().filter(|marker| marker.intersects(text_box)).count();
+    let core_overlap = (text_box.x..text_box.x + text_box.width)
+        .flat_map(|x| (text_box.y..text_box.y + text_box.height).map(move |y| (x, y)))
+        .filter(|position| core_pixels.contains(position))
+        .count();
+    label_overlaps as f64 * LABEL_OVERLAP_COST + marker_overlaps as f64 * MARKER_OVERLAP_COST + core_overlap as f64 * CORE_PIXEL_COST
+}
+
+fn shift_inside(text_box: LabelBox, bounds: (u32, u32)) -> LabelBox {
+    let max_x = (bounds.0 as i32 - text_box.width).max(0);
+    let max_y = (bounds.1 as i32 - text_box.height).max(0);
+    LabelBox {
+        x: text_box.x.clamp(0, max_x),
+        y: text_box.y.clamp(0, max_y),
+        ..text_box
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::storm_from_pixels;
+
+    fn labels(centers: &[(i32, i32)], bounds: (u32, u32)) -> Vec<LabelPlacement> {
+        let storms: Vec<Storm> = centers
+            .iter()
+            .enumerate()
+            .map(|(index, &(x, y))| storm_from_pixels(index as u32 + 1, &[(x, y, 30)]))
+            .collect();
+        place_labels(&storms, &DetectionParams::default(), &RenderStyle::default(), bounds)
+    }
+
+    // Padded box of "#1" at the default up-right candidate
+    fn default_box(anchor: (f32, f32)) -> LabelBox {
+        let (width, height) = text_size(Scale::uniform(RenderStyle::default().label_font_size), &FONT, "#1");
+        candidate_box(anchor, LABEL_DIRECTIONS[0], LABEL_DISTANCES[0], width, height).grown(LABEL_PADDING)
+    }
+
+    #[test]
+    fn labels_of_storms_on_the_same_center_do_not_overlap() {
+        let placements = labels(&[(100, 100), (100, 100), (100, 100)], (400, 400));
+        assert_eq!(placements[0].text_box, default_box((100.0, 100.0)));
+        for (index, placement) in placements.iter().enumerate() {
+            assert!(placements[index + 1..].iter().all(|other| !other.text_box.intersects(&placement.text_box)), "#{}", placement.storm_id);
+        }
+    }
+
+    #[test]
+    fn labels_at_the_edge_are_kept_inside_the_radar_area() {
+        let placements = labels(&[(395, 5), (200, 395)], (400, 400));
+        assert!(placements.iter().all(|placement| placement.text_box.inside((400, 400))));
+
+        // No candidate fits an area barely larger than the label, the default one is shifted back inside
+        let preferred = default_box((0.0, 0.0));
+        let bounds = (preferred.width as u32 + 2, preferred.height as u32 + 2);
+        let placements = labels(&[(bounds.0 as i32 / 2, bounds.1 as i32 / 2)], bounds);
+        assert!(placements[0].text_box.inside(bounds) && placements[0].leader);
+        assert_eq!((placements[0].text_box.width, placements[0].text_box.height), (preferred.width, preferred.height));
+    }
+
+    #[test]
+    fn only_moved_labels_get_a_leader_line() {
+        let placements = labels(&[(100, 100), (100, 100), (300, 300), (395, 5)], (400, 400));
+        let leaders: Vec<bool> = placements.iter().map(|placement| placement.leader).collect();
+        assert_eq!(leaders, [false, true, false, true]);
+        assert_eq!(placements[2].text_box, default_box((300.0, 300.0)));
+        assert_ne!(placements[3].text_box, default_box((395.0, 5.0)));
+    }
+}
@@ -0,0 +1,253 @@
+use image::{DynamicImage, GenericImageView, Pixel as ImagePixel, Rgba, RgbaImage};
+use imageproc::drawing::draw_text_mut;
+use rusttype::Scale;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::draw::fill_rect;
+use crate::pixel::Palette;
+use crate::render::FONT;
+
+// Minimum share of panel background in a column for it to count as part of the legend panel
+const PANEL_COLUMN_FRACTION: f64 = 0.9;
+const LEGEND_MARGIN: i32 = 10;
+const LEGEND_TEXT_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LegendMode {
+    // Copy the legend panel of the input radar image
+    #[default]
+    Copy,
+    // Draw palette swatches with their dBZ levels
+    Generated,
+    // Keep the legend panel of the base image as it is
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LegendConfig {
+    pub mode: LegendMode,
+    pub swatch_width: u32,
+    pub swatch_height: u32,
+    pub font_size: f32,
+}
+
+impl Default for LegendConfig {
+    fn default() -> Self {
+        LegendConfig {
+            mode: LegendMode::default(),
+            swatch_width: 32,
+            swatch_height: 14,
+            font_size: 14.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LegendRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Free text shown above the generated swatches, e.g. station and frame time
+#[derive(Debug, Clone, Default)]
+pub struct LegendCaption {
+    pub lines: Vec<String>,
+}
+
+// Color scale of a generated legend, swatches from the top down below a unit title
+#[derive(Debug, Clone, Default)]
+pub struct LegendScale {
+    pub unit: String,
+    pub swatches: Vec<(Rgba<u8>, String)>,
+}
+
+impl LegendScale {
+    // One swatch per palette level, strongest on top
+    pub fn from_palette(palette: &Palette) -> Self {
+        LegendScale {
+            unit: String::from("dBZ"),
+            swatches: palette.levels.iter().rev().map(|level| (level.rgba(), level.dbz.to_string())).collect(),
+        }
+    }
+}
+
+// The legend panel lies to the right of the radar area. Its left edge is the first column past the radar area
+// that mostly shows the panel background, which is the dominant color of the rightmost column.
+pub fn detect_legend_region<I: GenericImageView<Pixel = Rgba<u8>>>(img: &I, radar_area: (u32, u32)) -> Option<LegendRegion> {
+    let (width, height) = img.dimensions();
+    if radar_area.0 >= width || height == 0 {
+        return None;
+    }
+
+    let panel_background = dominant_color((0..height).map(|y| img.get_pixel(width - 1, y)))?;
+    let left = (radar_area.0..width)
+        .find(|&x| {
+            let background_count = (0..height).filter(|&y| img.get_pixel(x, y) == panel_background).count();
+            background_count as f64 >= height as f64 * PANEL_COLUMN_FRACTION
+        })
+        .unwrap_or(radar_area.0);
+
+    Some(LegendRegion {
+        x: left,
+        y: 0,
+        width: width - left,
+        height,
+    })
+}
+
+// Paint the legend panel of the result image in memory, the base image file is never written
+pub fn compose_legend(
+    img: &mut RgbaImage,
+    source: &DynamicImage,
+    radar_area: (u32, u32),
+    palette: &Palette,
+    config: &LegendConfig,
+    caption: &LegendCaption,
+) {
+    match config.mode {
+        LegendMode::Copy => {
+            if let Some(region) = detect_legend_region(source, radar_area) {
+                copy_legend(source, img, &region);
+            }
+        }
+        LegendMode::Generated => {
+            if let Some(region) = detect_legend_region(img, radar_area) {
+                draw_generated_legend(img, &region, &LegendScale::from_palette(palette), config, caption);
+            }
+        }
+        LegendMode::None => {}
+    }
+}
+
+// Copy the region from the source, positions outside of either image are skipped
+pub fn copy_legend(source: &DynamicImage, target: &mut RgbaImage, region: &LegendRegion) {
+    let (source_width, source_height) = source.dimensions();
+    let (target_width, target_height) = target.dimensions();
+    let x_end = (region.x + region.width).min(source_width).min(target_width);
+    let y_end = (region.y + region.height).min(source_height).min(target_height);
+    for x in region.x..x_end {
+        for y in region.y..y_end {
+            target.put_pixel(x, y, source.get_pixel(x, y).to_rgba());
+        }
+    }
+}
+
+// Clear the region and draw the caption followed by the swatches of the scale, as far as they fit
+pub fn draw_generated_legend(
+    img: &mut RgbaImage,
+    region: &LegendRegion,
+    scale: &LegendScale,
+    config: &LegendConfig,
+    caption: &LegendCaption,
+) {
+    if region.width == 0 || region.height == 0 {
+        return;
+    }
+    let background = dominant_color(
+        (region.x..region.x + region.width)
+            .flat_map(|x| (region.y..region.y + region.height).map(move |y| (x, y)))
+            .filter(|&(x, y)| x < img.width() && y < img.height())
+            .map(|(x, y)| *img.get_pixel(x, y)),
+    )
+    .unwrap_or(Rgba([0, 0, 0, 255]));
+    fill_rect(img, region.x as i32, region.y as i32, region.width as i32, region.height as i32, background, 1.0);
+
+    let font_scale = Scale::uniform(config.font_size);
+    let line_height = (config.font_size * 1.3).ceil() as i32;
+    let left = region.x as i32 + LEGEND_MARGIN;
+    let mut y = region.y as i32 + LEGEND_MARGIN;
+
+    for line in &caption.lines {
+        draw_text_mut(img, LEGEND_TEXT_COLOR, left, y, font_scale, &FONT, line);
+        y += line_height;
+    }
+    if !caption.lines.is_empty() {
+        y += line_height;
+    }
+
+    draw_text_mut(img, LEGEND_TEXT_COLOR, left, y, font_scale, &FONT, &scale.unit);
+    y += line_height;
+
+    let swatch_height = config.swatch_height.max(1);
+    let label_x = left + config.swatch_width as i32 + LEGEND_MARGIN / 2;
+    let label_offset = (swatch_height as i32 - config.font_size.round() as i32) / 2;
+    for (color, label) in &scale.swatches {
+        if y + swatch_height as i32 > (region.y + region.height) as i32 {
+            break;
+        }
+        fill_rect(img, left, y, config.swatch_width.max(1) as i32, swatch_height as i32, *color, 1.0);
+        draw_text_mut(img, LEGEND_TEXT_COLOR, label_x, y + label_offset, font_scale, &FONT, label);
+        y += swatch_height as i32;
+    }
+}
+
+fn dominant_color(colors: impl Iterator<Item = Rgba<u8>>) -> Option<Rgba<u8>> {
+    let mut color_count: HashMap<Rgba<u8>, usize> = HashMap::new();
+    for color in colors {
+        *color_count.entry(color).or_insert(0) += 1;
+    }
+    color_count.into_iter().max_by_key(|&(_, count)| count).map(|(color, _)| color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADAR_AREA: (u32, u32) = (40, 80);
+    const PANEL: Rgba<u8> = Rgba([0, 0, 64, 255]);
+    const SWATCH: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    // Black radar area, a two pixel gray border and a blue legend panel from x = 42, with a red swatch in the source
+    fn image(with_swatch: bool) -> RgbaImage {
+        RgbaImage::from_fn(60, 80, |x, y| match x {
+            0..=39 => Rgba([0, 0, 0, 255]),
+            40..=41 => Rgba([128, 128, 128, 255]),
+            45..=49 if with_swatch && (5..10).contains(&y) => SWATCH,
+            _ => PANEL,
+        })
+    }
+
+    fn compose(mode: LegendMode) -> RgbaImage {
+        let mut img = image(false);
+        let config = LegendConfig { mode, ..LegendConfig::default() };
+        compose_legend(&mut img, &DynamicImage::ImageRgba8(image(true)), RADAR_AREA, &Palette::default(), &config, &LegendCaption::default());
+        img
+    }
+
+    fn radar_area_unchanged(img: &RgbaImage) -> bool {
+        (0..42).all(|x| (0..80).all(|y| img.get_pixel(x, y) == image(false).get_pixel(x, y)))
+    }
+
+    #[test]
+    fn panel_starts_at_the_first_background_column_right_of_the_radar_area() {
+        let region = detect_legend_region(&image(true), RADAR_AREA);
+        assert_eq!(region, Some(LegendRegion { x: 42, y: 0, width: 18, height: 80 }));
+        assert_eq!(detect_legend_region(&image(true), (60, 80)), None);
+    }
+
+    #[test]
+    fn copy_takes_the_panel_of_the_source() {
+        let img = compose(LegendMode::Copy);
+        assert_eq!(*img.get_pixel(47, 7), SWATCH);
+        assert_eq!(*img.get_pixel(47, 12), PANEL);
+        assert!(radar_area_unchanged(&img));
+    }
+
+    #[test]
+    fn generated_draws_the_palette_into_the_panel() {
+        let img = compose(LegendMode::Generated);
+        let strongest = Palette::default().levels.last().unwrap().rgba();
+        assert!((42..60).any(|x| (0..80).any(|y| *img.get_pixel(x, y) == strongest)));
+        assert!(!img.pixels().any(|&pixel| pixel == SWATCH));
+        assert!(radar_area_unchanged(&img));
+    }
+
+    #[test]
+    fn none_leaves_the_panel_untouched() {
+        assert_eq!(compose(LegendMode::None), image(false));
+    }
+}
@@ -1,4 +1,35 @@
+pub mod config;
 pub mod pixel;
 pub mod storm;
+pub mod ellipse;
+pub mod hierarchy;
+pub mod watershed;
+pub mod grid;
+pub mod morphology;
+pub mod overlay;
+pub mod labels;
+pub mod legend;
+pub mod draw;
+pub mod reference;
+pub mod render;
+pub mod report;
+pub mod svg;
+pub mod geo;
+pub mod tracking;
+pub mod animation;
+pub mod accumulation;
+pub mod precipitation;
+pub mod severe;
+pub mod stats;
+pub mod beam;
+pub mod poi;
+pub mod districts;
+pub mod cap;
+pub mod webhook;
+pub mod storage;
+pub mod pipeline;
 pub mod utils;
-pub mod consts;
\ No newline at end of file
+pub mod consts;
+
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,317 @@
+use serde::{Deserialize, Serialize};
+use crate::grid::ReflectivityGrid;
+use crate::pixel::{Palette, Pixel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MorphologyKind {
+    // Operate on the echo / no echo mask, surviving pixels keep their reflectivity
+    Binary,
+    // Operate on the reflectivity values with min / max filters
+    #[default]
+    Gray,
+}
+
+// Cleanup steps run in the order opening, closing, hole filling, small blob removal.
+// A radius or size of 0 disables the step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MorphologyOptions {
+    pub kind: MorphologyKind,
+    pub opening_radius: u32,
+    pub closing_radius: u32,
+    pub fill_holes: bool,
+    // Holes larger than this are real echo-free areas and stay open
+    pub max_hole_size: usize,
+    pub min_blob_size: usize,
+}
+
+impl Default for MorphologyOptions {
+    fn default() -> Self {
+        MorphologyOptions {
+            kind: MorphologyKind::default(),
+            opening_radius: 0,
+            closing_radius: 0,
+            fill_holes: false,
+            max_hole_size: 20,
+            min_blob_size: 0,
+        }
+    }
+}
+
+impl MorphologyOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.opening_radius > 0 || self.closing_radius > 0 || self.fills_holes() || self.min_blob_size > 0
+    }
+
+    fn fills_holes(&self) -> bool {
+        self.fill_holes && self.max_hole_size > 0
+    }
+}
+
+const NEIGHBORS_4: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+pub fn clean_pixels(pixel_list: &[Pixel], radar_area: (u32, u32), options: &MorphologyOptions, palette: &Palette) -> Vec<Pixel> {
+    if !options.is_enabled() {
+        return pixel_list.to_vec();
+    }
+    let grid = ReflectivityGrid::from_pixels(pixel_list, radar_area);
+    apply_morphology(&grid, options).to_pixels(palette)
+}
+
+pub fn apply_morphology(grid: &ReflectivityGrid, options: &MorphologyOptions) -> ReflectivityGrid {
+    let mut result = grid.clone();
+    if options.opening_radius > 0 {
+        result = match options.kind {
+            MorphologyKind::Gray => gray_opening(&result, options.opening_radius),
+            MorphologyKind::Binary => binary_opening(&result, options.opening_radius),
+        };
+    }
+    if options.closing_radius > 0 {
+        result = match options.kind {
+            MorphologyKind::Gray => gray_closing(&result, options.closing_radius),
+            MorphologyKind::Binary => binary_closing(&result, options.closing_radius),
+        };
+    }
+    if options.fills_holes() {
+        fill_holes(&mut result, options.max_hole_size);
+    }
+    if options.min_blob_size > 0 {
+        remove_small_blobs(&mut result, options.min_blob_size);
+    }
+    result
+}
+
+// Offsets of a disk shaped structuring element
+fn disk(radius: u32) -> Vec<(i32, i32)> {
+    let r = radius as i32;
+    let mut offsets = Vec::new();
+    for dx in -r..=r {
+        for dy in -r..=r {
+            if dx * dx + dy * dy <= r * r {
+                offsets.push((dx, dy));
+            }
+        }
+    }
+    offsets
+}
+
+// Minimum (erosion) or maximum (dilation) over the structuring element, ignoring positions off the grid
+fn rank_filter(grid: &ReflectivityGrid, radius: u32, erode: bool) -> ReflectivityGrid {
+    let offsets = disk(radius);
+    let mut result = ReflectivityGrid::new(grid.width, grid.height);
+    for x in 0..grid.width as i32 {
+        for y in 0..grid.height as i32 {
+            let values = offsets
+                .iter()
+                .filter(|&&(dx, dy)| grid.contains(x + dx, y + dy))
+                .map(|&(dx, dy)| grid.get(x + dx, y + dy));
+            let value = if erode { values.min() } else { values.max() };
+            result.set(x, y, value.unwrap_or(0));
+        }
+    }
+    result
+}
+
+fn gray_opening(grid: &ReflectivityGrid, radius: u32) -> ReflectivityGrid {
+    rank_filter(&rank_filter(grid, radius, true), radius, false)
+}
+
+fn gray_closing(grid: &ReflectivityGrid, radius: u32) -> ReflectivityGrid {
+    rank_filter(&rank_filter(grid, radius, false), radius, true)
+}
+
+fn echo_mask(grid: &ReflectivityGrid) -> ReflectivityGrid {
+    let mut mask = ReflectivityGrid::new(grid.width, grid.height);
+    for x in 0..grid.width as i32 {
+        for y in 0..grid.height as i32 {
+            if grid.get(x, y) > 0 {
+                mask.set(x, y, 1);
+            }
+        }
+    }
+    mask
+}
+
+fn binary_opening(grid: &ReflectivityGrid, radius: u32) -> ReflectivityGrid {
+    let opened_mask = gray_opening(&echo_mask(grid), radius);
+    let mut result = grid.clone();
+    for x in 0..grid.width as i32 {
+        for y in 0..grid.height as i32 {
+            if opened_mask.get(x, y) == 0 {
+                result.set(x, y, 0);
+            }
+        }
+    }
+    result
+}
+
+fn binary_closing(grid: &ReflectivityGrid, radius: u32) -> ReflectivityGrid {
+    let closed_mask = gray_closing(&echo_mask(grid), radius);
+    // Bridged pixels take the gray closing value, which is the weakest of the surrounding maxima
+    let closed_values = gray_closing(grid, radius);
+    let mut result = grid.clone();
+    for x in 0..grid.width as i32 {
+        for y in 0..grid.height as i32 {
+            if closed_mask.get(x, y) > 0 && grid.get(x, y) == 0 {
+                result.set(x, y, closed_values.get(x, y));
+            }
+        }
+    }
+    result
+}
+
+// Connected region of pixels matching the predicate, 4-connected for background and 8-connected for echoes
+fn flood_region(
+    grid: &ReflectivityGrid,
+    visited: &mut [bool],
+    start: (i32, i32),
+    eight_connected: bool,
+    predicate: impl Fn(u32) -> bool,
+) -> Vec<(i32, i32)> {
+    let width = grid.width as i32;
+    let mut region = Vec::new();
+    let mut stack = vec![start];
+    visited[(start.1 * width + start.0) as usize] = true;
+    while let Some((x, y)) = stack.pop() {
+        region.push((x, y));
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if (dx == 0 && dy == 0) || (!eight_connected && dx != 0 && dy != 0) {
+                    continue;
+                }
+                let (nx, ny) = (x + dx, y + dy);
+                if grid.contains(nx, ny) && !visited[(ny * width + nx) as usize] && predicate(grid.get(nx, ny)) {
+                    visited[(ny * width + nx) as usize] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+    }
+    region
+}
+
+// Fill echo-free regions that do not touch the grid border with the weakest surrounding reflectivity
+fn fill_holes(grid: &mut ReflectivityGrid, max_hole_size: usize) {
+    let width = grid.width as i32;
+    let mut visited = vec![false; (grid.width * grid.height) as usize];
+    for x in 0..grid.width as i32 {
+        for y in 0..grid.height as i32 {
+            if visited[(y * width + x) as usize] || grid.get(x, y) != 0 {
+                continue;
+            }
+            let region = flood_region(grid, &mut visited, (x, y), false, |value| value == 0);
+            let touches_border = region
+                .iter()
+                .any(|&(rx, ry)| rx == 0 || ry == 0 || rx == width - 1 || ry == grid.height as i32 - 1);
+            if touches_border || region.len() > max_hole_size {
+                continue;
+            }
+            let fill_value = region
+                .iter()
+                .flat_map(|&(rx, ry)| NEIGHBORS_4.iter().map(move |&(dx, dy)| (rx + dx, ry + dy)))
+                .map(|(nx, ny)| grid.get(nx, ny))
+                .filter(|&value| value > 0)
+                .min()
+                .unwrap_or(0);
+            for (rx, ry) in region {
+                grid.set(rx, ry, fill_value);
+            }
+        }
+    }
+}
+
+fn remove_small_blobs(grid: &mut ReflectivityGrid, min_blob_size: usize) {
+    let width = grid.width as i32;
+    let mut visited = vec![false; (grid.width * grid.height) as usize];
+    for x in 0..grid.width as i32 {
+        for y in 0..grid.height as i32 {
+            if visited[(y * width + x) as usize] || grid.get(x, y) == 0 {
+                continue;
+            }
+            let region = flood_region(grid, &mut visited, (x, y), true, |value| value > 0);
+            if region.len() < min_blob_size {
+                for (rx, ry) in region {
+                    grid.set(rx, ry, 0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(cells: &[(i32, i32, u32)]) -> ReflectivityGrid {
+        let mut grid = ReflectivityGrid::new(12, 12);
+        for &(x, y, value) in cells {
+            grid.set(x, y, value);
+        }
+        grid
+    }
+
+    fn block(x0: i32, y0: i32, x1: i32, y1: i32, value: u32) -> Vec<(i32, i32, u32)> {
+        (x0..=x1).flat_map(|x| (y0..=y1).map(move |y| (x, y, value))).collect()
+    }
+
+    fn echo_count(grid: &ReflectivityGrid) -> usize {
+        (0..grid.width as i32).flat_map(|x| (0..grid.height as i32).map(move |y| (x, y))).filter(|&(x, y)| grid.get(x, y) > 0).count()
+    }
+
+    #[test]
+    fn opening_removes_specks_and_keeps_blocks() {
+        let mut cells = block(2, 2, 7, 7, 40);
+        cells.push((10, 10, 50));
+        let options = MorphologyOptions { opening_radius: 1, ..MorphologyOptions::default() };
+        for kind in [MorphologyKind::Gray, MorphologyKind::Binary] {
+            let result = apply_morphology(&grid(&cells), &MorphologyOptions { kind, ..options });
+            assert_eq!(result.get(10, 10), 0);
+            assert_eq!(result.get(4, 4), 40);
+        }
+    }
+
+    #[test]
+    fn closing_bridges_a_one_pixel_gap() {
+        let mut cells = block(1, 3, 4, 6, 40);
+        cells.extend(block(6, 3, 9, 6, 50));
+        let options = MorphologyOptions { closing_radius: 1, ..MorphologyOptions::default() };
+        for kind in [MorphologyKind::Gray, MorphologyKind::Binary] {
+            let result = apply_morphology(&grid(&cells), &MorphologyOptions { kind, ..options });
+            assert_eq!(result.get(5, 4), 40);
+            assert_eq!(result.get(1, 3), 40);
+        }
+    }
+
+    #[test]
+    fn only_enclosed_holes_up_to_max_hole_size_are_filled() {
+        // A ring enclosing a 2 x 2 hole next to a ring enclosing a 4 x 4 hole
+        let ring = |x0: i32, y0: i32, x1: i32, y1: i32, value: u32| {
+            block(x0, y0, x1, y1, value).into_iter().filter(move |&(x, y, _)| x == x0 || x == x1 || y == y0 || y == y1)
+        };
+        let mut cells: Vec<(i32, i32, u32)> = ring(0, 0, 3, 3, 45).collect();
+        cells.extend(ring(5, 4, 10, 9, 35));
+        let options = MorphologyOptions { fill_holes: true, max_hole_size: 4, ..MorphologyOptions::default() };
+        let result = apply_morphology(&grid(&cells), &options);
+        assert_eq!(result.get(1, 1), 45);
+        assert_eq!(result.get(7, 6), 0);
+
+        let all = apply_morphology(&grid(&cells), &MorphologyOptions { max_hole_size: 16, ..options });
+        assert_eq!(all.get(7, 6), 35);
+        // The echo-free area outside the rings touches the border
+        assert_eq!(all.get(11, 0), 0);
+
+        // A size of 0 disables filling
+        assert!(!MorphologyOptions { max_hole_size: 0, ..options }.is_enabled());
+    }
+
+    #[test]
+    fn blobs_below_min_blob_size_are_removed() {
+        let mut cells = block(0, 0, 2, 2, 40);
+        cells.extend(block(8, 8, 9, 9, 50));
+        let options = MorphologyOptions { min_blob_size: 5, ..MorphologyOptions::default() };
+        let result = apply_morphology(&grid(&cells), &options);
+        assert_eq!(echo_count(&result), 9);
+        assert_eq!(result.get(8, 8), 0);
+    }
+}
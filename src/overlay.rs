@@ -0,0 +1,235 @@
+use image::{DynamicImage, GenericImageView, Pixel as ImagePixel, Rgba};
+use std::collections::HashMap;
+use crate::grid::ReflectivityGrid;
+use crate::pixel::{Palette, Pixel};
+
+// Static map overlay (coastlines, borders, labels, range rings) of the radar area
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayMask {
+    pub width: u32,
+    pub height: u32,
+    mask: Vec<bool>,
+}
+
+impl OverlayMask {
+    // Everything that differs from the dominant background color of the no-echo reference frame is overlay.
+    // The mask is grown by dilate_radius pixels to cover anti-aliased line borders.
+    pub fn from_base_image(base_image_path: &str, radar_area: (u32, u32), dilate_radius: u32) -> Self {
+        let base_img = image::open(base_image_path).expect("Failed to open base image");
+        OverlayMask::from_image(&base_img, radar_area, dilate_radius)
+    }
+
+    pub fn from_image(base_img: &DynamicImage, radar_area: (u32, u32), dilate_radius: u32) -> Self {
+        let (width, height) = base_img.dimensions();
+        let (radar_width, radar_height) = radar_area;
+        if radar_width > width || radar_height > height {
+            panic!("Radar area exceeds image dimensions");
+        }
+
+        let mut color_count: HashMap<Rgba<u8>, usize> = HashMap::new();
+        for x in 0..radar_width {
+            for y in 0..radar_height {
+                *color_count.entry(base_img.get_pixel(x, y).to_rgba()).or_insert(0) += 1;
+            }
+        }
+        let background = color_count
+            .into_iter()
+            .max_by_key(|&(_, count)| count)
+            .map(|(color, _)| color)
+            .unwrap_or(Rgba([0, 0, 0, 255]));
+
+        let mut overlay = OverlayMask {
+            width: radar_width,
+            height: radar_height,
+            mask: vec![false; (radar_width * radar_height) as usize],
+        };
+        for x in 0..radar_width {
+            for y in 0..radar_height {
+                if base_img.get_pixel(x, y).to_rgba() != background {
+                    overlay.set(x as i32, y as i32);
+                }
+            }
+        }
+
+        if dilate_radius > 0 {
+            overlay = overlay.dilate(dilate_radius as i32);
+        }
+        overlay
+    }
+
+    pub fn is_overlay(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+            && self.mask[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    pub fn overlay_count(&self) -> usize {
+        self.mask.iter().filter(|&&masked| masked).count()
+    }
+
+    fn set(&mut self, x: i32, y: i32) {
+        self.mask[(y as u32 * self.width + x as u32) as usize] = true;
+    }
+
+    fn dilate(&self, radius: i32) -> OverlayMask {
+        let mut dilated = self.clone();
+        for x in 0..self.width as i32 {
+            for y in 0..self.height as i32 {
+                if !self.is_overlay(x, y) {
+                    continue;
+                }
+                for dx in -radius..=radius {
+                    for dy in -radius..=radius {
+                        let (nx, ny) = (x + dx, y + dy);
+                        if nx >= 0 && ny >= 0 && (nx as u32) < self.width && (ny as u32) < self.height {
+                            dilated.set(nx, ny);
+                        }
+                    }
+                }
+            }
+        }
+        dilated
+    }
+}
+
+// Decode without the overlay pixels, then fill them back in from the surrounding echoes
+pub fn remove_overlay(
+    pixel_list: &[Pixel],
+    radar_area: (u32, u32),
+    overlay: &OverlayMask,
+    max_passes: u32,
+    palette: &Palette,
+) -> Vec<Pixel> {
+    let visible: Vec<Pixel> = pixel_list
+        .iter()
+        .filter(|pixel| !overlay.is_overlay(pixel.x, pixel.y))
+        .copied()
+        .collect();
+    let mut grid = ReflectivityGrid::from_pixels(&visible, radar_area);
+    fill_overlay_gaps(&mut grid, overlay, max_passes);
+    grid.to_pixels(palette)
+}
+
+// Interpolate overlay pixels from their known 8-neighbors, one ring of the overlay per pass.
+// A pixel becomes echo when most of its known neighbors are echoes, taking their mean snapped to the 5 dBZ palette step.
+pub fn fill_overlay_gaps(grid: &mut ReflectivityGrid, overlay: &OverlayMask, max_passes: u32) {
+    let mut known: Vec<bool> = (0..grid.height as i32)
+        .flat_map(|y| (0..grid.width as i32).map(move |x| (x, y)))
+        .map(|(x, y)| !overlay.is_overlay(x, y))
+        .collect();
+    let width = grid.width as i32;
+
+    for _ in 0..max_passes {
+        let mut filled = Vec::new();
+        for x in 0..grid.width as i32 {
+            for y in 0..grid.height as i32 {
+                if known[(y * width + x) as usize] {
+                    continue;
+                }
+                let mut echo_sum = 0;
+                let mut echo_count = 0;
+                let mut clear_count = 0;
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        let (nx, ny) = (x + dx, y + dy);
+                        if !grid.contains(nx, ny) || !known[(ny * width + nx) as usize] {
+                            continue;
+                        }
+                        match grid.get(nx, ny) {
+                            0 => clear_count += 1,
+                            value => {
+                                echo_sum += value;
+                                echo_count += 1;
+                            }
+                        }
+                    }
+                }
+                if echo_count + clear_count < 2 {
+                    continue;
+                }
+                let value = if echo_count > clear_count {
+                    ((echo_sum as f64 / echo_count as f64 / 5.0).round() * 5.0) as u32
+                } else {
+                    0
+                };
+                filled.push((x, y, value));
+            }
+        }
+
+        if filled.is_empty() {
+            break;
+        }
+        for (x, y, value) in filled {
+            grid.set(x, y, value);
+            known[(y * width + x) as usize] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    // 20 x 20 black base image crossed by a gray line at x = 10
+    fn base_image() -> DynamicImage {
+        let mut img = RgbaImage::from_pixel(20, 20, Rgba([0, 0, 0, 255]));
+        for y in 0..20 {
+            img.put_pixel(10, y, Rgba([128, 128, 128, 255]));
+        }
+        DynamicImage::ImageRgba8(img)
+    }
+
+    // Echo block over x 5..15 and y 5..15, 40 dBZ left of the line and 45 dBZ right of it
+    fn echoes() -> Vec<Pixel> {
+        let palette = Palette::default();
+        let mut grid = ReflectivityGrid::new(20, 20);
+        for x in 5..15 {
+            for y in 5..15 {
+                grid.set(x, y, if x < 10 { 40 } else { 45 });
+            }
+        }
+        grid.to_pixels(&palette)
+    }
+
+    fn intensity_at(pixels: &[Pixel], x: i32, y: i32) -> Option<u32> {
+        pixels.iter().find(|pixel| (pixel.x, pixel.y) == (x, y)).map(|pixel| pixel.intensity)
+    }
+
+    #[test]
+    fn only_pixels_off_the_background_color_are_masked() {
+        let mask = OverlayMask::from_image(&base_image(), (20, 20), 0);
+        assert_eq!(mask.overlay_count(), 20);
+        assert!(mask.is_overlay(10, 0) && mask.is_overlay(10, 19));
+        assert!(!mask.is_overlay(0, 0) && !mask.is_overlay(9, 5) && !mask.is_overlay(11, 5));
+    }
+
+    #[test]
+    fn dilate_radius_grows_the_mask() {
+        let mask = OverlayMask::from_image(&base_image(), (20, 20), 1);
+        assert_eq!(mask.overlay_count(), 60);
+        assert!(mask.is_overlay(9, 0) && mask.is_overlay(11, 19));
+        assert!(!mask.is_overlay(8, 0));
+    }
+
+    #[test]
+    fn line_through_an_echo_is_refilled_by_the_majority_of_its_neighbors() {
+        let mask = OverlayMask::from_image(&base_image(), (20, 20), 0);
+        let pixels = remove_overlay(&echoes(), (20, 20), &mask, 3, &Palette::default());
+        // The mean of 40 and 45 dBZ neighbors snaps to 45 dBZ
+        for y in 5..15 {
+            assert_eq!(intensity_at(&pixels, 10, y), Some(45), "y {}", y);
+        }
+        // Beside the echo most known neighbors are clear
+        assert_eq!(intensity_at(&pixels, 10, 4), None);
+        assert_eq!(intensity_at(&pixels, 10, 15), None);
+        assert_eq!(pixels.len(), 100);
+    }
+
+    #[test]
+    fn no_fill_passes_leave_the_gap_empty() {
+        let mask = OverlayMask::from_image(&base_image(), (20, 20), 0);
+        let pixels = remove_overlay(&echoes(), (20, 20), &mask, 0, &Palette::default());
+        assert!(pixels.iter().all(|pixel| pixel.x != 10));
+        assert_eq!(pixels.len(), 90);
+    }
+}
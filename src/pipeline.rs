@@ -0,0 +1,306 @@
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::accumulation::{draw_accumulation, Accumulation};
+use crate::animation::{draw_caption, draw_trails, write_animation, AnimationFormat};
+use crate::config::Config;
+use crate::districts::District;
+use crate::grid::ReflectivityGrid;
+use crate::hierarchy::{identify_storm_hierarchy, StormHierarchy};
+use crate::legend::{compose_legend, detect_legend_region, LegendCaption};
+use crate::morphology::clean_pixels;
+use crate::overlay::{remove_overlay, OverlayMask};
+use crate::pixel::{decode_pixels, Pixel};
+use crate::precipitation::{PrecipitationConfig, RainRateGrid};
+use crate::report::DetectionResult;
+use crate::render::render_storms;
+use crate::storm::{classify_storms, segment_pixels, Storm};
+use crate::svg::{render_svg, SvgBackground};
+use crate::tracking::{StormMotion, StormTracker};
+use crate::utils::parse_frame_time;
+
+#[derive(Debug)]
+pub struct Detection {
+    pub source: String,
+    pub storms: Vec<Storm>,
+    // Cleaned echoes of the whole radar area, storms or not
+    pub reflectivity: ReflectivityGrid,
+    pub duration: Duration,
+    // Track and motion of every storm once the frame was tracked, empty for a single frame
+    pub motion: Vec<StormMotion>,
+    // Nested complexes, cells and cores when the threshold ladder is enabled
+    pub hierarchy: Option<StormHierarchy>,
+}
+
+impl Detection {
+    pub fn to_result(&self, config: &Config, districts: &[District], include_pixels: bool) -> DetectionResult {
+        DetectionResult::new(&self.source, &self.storms, &self.motion, &self.reflectivity, self.duration, config, include_pixels)
+            .with_districts(&self.storms, districts, config.station.km_per_pixel)
+            .with_hierarchy(self.hierarchy.as_ref(), &config.station)
+    }
+
+    // Link the storms to the tracks of the previous frames, which must be passed in time order
+    pub fn track(&mut self, tracker: &mut StormTracker) {
+        let track_ids = tracker.update(parse_frame_time(&self.source), &self.storms);
+        self.motion = track_ids.into_iter().map(|track_id| tracker.motion(track_id)).collect();
+    }
+
+    pub fn rain_rate_grid(&self, config: &PrecipitationConfig) -> RainRateGrid {
+        RainRateGrid::from_reflectivity(&self.reflectivity, config)
+    }
+}
+
+pub fn detect_storms(image_path: &str, config: &Config) -> Result<Detection, Box<dyn Error>> {
+    let start = Instant::now();
+    let station = &config.station;
+    let radar_area = station.radar_area();
+    let pixels = decode_echoes(image_path, config)?;
+
+    // Storm analysis
+    let params = config.detection_params();
+    let mut storms = segment_pixels(&pixels, &station.radar_center(), &params);
+    classify_storms(&mut storms, &params);
+    let hierarchy = config
+        .hierarchy
+        .enabled
+        .then(|| identify_storm_hierarchy(&pixels, &station.radar_center(), &config.hierarchy_params()));
+
+    Ok(Detection {
+        source: image_path.to_string(),
+        storms,
+        reflectivity: ReflectivityGrid::from_pixels(&pixels, radar_area),
+        duration: start.elapsed(),
+        motion: Vec::new(),
+        hierarchy,
+    })
+}
+
+// Cleaned echoes of the radar area without the storm analysis, e.g. for rainfall accumulation
+pub fn decode_reflectivity(image_path: &str, config: &Config) -> Result<ReflectivityGrid, Box<dyn Error>> {
+    let pixels = decode_echoes(image_path, config)?;
+    Ok(ReflectivityGrid::from_pixels(&pixels, config.station.radar_area()))
+}
+
+// Accumulation map over the base image, with its window and color scale in the legend panel
+pub fn render_accumulation(accumulation: &Accumulation, config: &Config, output_path: &str) -> Result<(), Box<dyn Error>> {
+    let radar_area = config.station.radar_area();
+    let base_img = open_radar_image(&config.station.base_image, radar_area)?;
+    let legend_region = detect_legend_region(&base_img, radar_area);
+    let mut img = base_img.to_rgba8();
+    draw_accumulation(
+        &mut img,
+        accumulation,
+        &config.accumulation,
+        legend_region.as_ref().map(|region| (region, &config.legend)),
+    );
+    img.save(output_path).map_err(|e| format!("Failed to save image {}: {}", output_path, e))?;
+    Ok(())
+}
+
+// Echo pixels of the image after overlay removal and morphological cleanup
+fn decode_echoes(image_path: &str, config: &Config) -> Result<Vec<Pixel>, Box<dyn Error>> {
+    let station = &config.station;
+    let radar_area = station.radar_area();
+    let img = open_radar_image(image_path, radar_area)?;
+
+    // Get filtered echo pixels
+    let mut pixels = decode_pixels(&img, &config.palette, radar_area);
+
+    // Drop map overlay pixels found in the no-echo base image and fill them from neighboring echoes
+    if config.overlay.enabled {
+        let base_img = open_radar_image(&station.base_image, radar_area)?;
+        let overlay_mask = OverlayMask::from_image(&base_img, radar_area, config.overlay.dilate_radius);
+        pixels = remove_overlay(&pixels, radar_area, &overlay_mask, config.overlay.fill_passes, &config.palette);
+    }
+
+    // Echo mask cleanup
+    Ok(clean_pixels(&pixels, radar_area, &config.morphology, &config.palette))
+}
+
+pub fn render_detection(detection: &Detection, config: &Config, output_path: &str) -> Result<(), Box<dyn Error>> {
+    let mut img = compose_base_image(detection, config)?;
+    render_storms(
+        &mut img,
+        &detection.storms,
+        &config.station.radar_center(),
+        config.station.radar_area(),
+        &config.detection_params(),
+        &config.render,
+    );
+    img.save(output_path).map_err(|e| format!("Failed to save image {}: {}", output_path, e))?;
+    Ok(())
+}
+
+// Vector overlay over the base image with its composed legend embedded, or over a link to the plain base image
+pub fn render_detection_svg(detection: &Detection, config: &Config, output_path: &str, embed_base: bool) -> Result<(), Box<dyn Error>> {
+    let station = &config.station;
+    let base_img = compose_base_image(detection, config)?;
+    let base_href;
+    let background = if embed_base {
+        SvgBackground::Embedded(&base_img)
+    } else {
+        base_href = base_image_href(&station.base_image, output_path)?;
+        SvgBackground::Linked(&base_href)
+    };
+    let svg = render_svg(
+        &detection.storms,
+        &station.radar_center(),
+        station.radar_area(),
+        &config.detection_params(),
+        &config.render,
+        base_img.dimensions(),
+        background,
+    )?;
+    fs::write(output_path, svg).map_err(|e| format!("Failed to save svg {}: {}", output_path, e))?;
+    Ok(())
+}
+
+// Render every detection as one frame of an animation, in the given order. Trails follow the tracks the
+// detections were linked to with Detection::track, so they match the reported track ids; untracked
+// detections get none. The output format follows the file extension (.gif, .png or .apng).
+pub fn render_animation(detections: &[Detection], config: &Config, output_path: &str) -> Result<(), Box<dyn Error>> {
+    let format = AnimationFormat::from_path(output_path)
+        .ok_or_else(|| format!("Unsupported animation format of {}, expected .gif, .png or .apng", output_path))?;
+    let station = &config.station;
+    let params = config.detection_params();
+    // Centers of every track over the frames rendered so far
+    let mut track_centers: HashMap<u32, Vec<(f32, f32)>> = HashMap::new();
+
+    let mut frames = Vec::with_capacity(detections.len());
+    for detection in detections {
+        let mut img = compose_base_image(detection, config)?;
+        render_storms(&mut img, &detection.storms, &station.radar_center(), station.radar_area(), &params, &config.render);
+
+        for (storm, motion) in detection.storms.iter().zip(&detection.motion) {
+            let center = (storm.intensity_center.x as f32, storm.intensity_center.y as f32);
+            track_centers.entry(motion.track_id).or_default().push(center);
+        }
+        if config.animation.trails {
+            let trails: Vec<&[(f32, f32)]> = detection
+                .motion
+                .iter()
+                .filter_map(|motion| track_centers.get(&motion.track_id).map(Vec::as_slice))
+                .collect();
+            draw_trails(&mut img, &trails, &config.animation, &config.render);
+        }
+
+        let frame_time = parse_frame_time(&detection.source);
+        if config.animation.caption {
+            let when = match frame_time {
+                Some(frame_time) => frame_time.format("%Y-%m-%d %H:%M UTC").to_string(),
+                None => source_name(&detection.source),
+            };
+            draw_caption(&mut img, &format!("{} {}", station.code, when), &config.animation, &config.render);
+        }
+        frames.push(img);
+    }
+    write_animation(&frames, format, &config.animation, output_path)
+}
+
+// Link to the base image relative to the directory of the svg, the config path is relative to the working directory
+fn base_image_href(base_image: &str, svg_path: &str) -> Result<String, Box<dyn Error>> {
+    let base_image = fs::canonicalize(base_image).map_err(|e| format!("Failed to resolve base image {}: {}", base_image, e))?;
+    let svg_dir = match Path::new(svg_path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    let svg_dir = fs::canonicalize(&svg_dir).map_err(|e| format!("Failed to resolve svg directory {}: {}", svg_dir.display(), e))?;
+    Ok(relative_href(&base_image, &svg_dir))
+}
+
+// Relative uri reference from one absolute directory to an absolute file
+fn relative_href(target: &Path, from_dir: &Path) -> String {
+    let target: Vec<Component> = target.components().collect();
+    let from: Vec<Component> = from_dir.components().collect();
+    let common = target.iter().zip(&from).take_while(|(a, b)| a == b).count();
+    let mut segments: Vec<String> = vec![String::from(".."); from.len() - common];
+    segments.extend(target[common..].iter().map(|component| component.as_os_str().to_string_lossy().into_owned()));
+    segments
+        .iter()
+        .map(|segment| {
+            segment
+                .chars()
+                .map(|c| match c {
+                    ' ' | '%' | '#' | '?' => format!("%{:02X}", c as u32),
+                    c => c.to_string(),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn source_name(source: &str) -> String {
+    Path::new(source)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| source.to_string())
+}
+
+// Base image with the legend panel composed in memory, the base image file stays untouched
+fn compose_base_image(detection: &Detection, config: &Config) -> Result<RgbaImage, Box<dyn Error>> {
+    let station = &config.station;
+    let radar_area = station.radar_area();
+    let source_img = open_radar_image(&detection.source, radar_area)?;
+    let mut img = open_radar_image(&station.base_image, radar_area)?.to_rgba8();
+
+    let mut caption = LegendCaption {
+        lines: vec![format!("{} ({})", station.name, station.code)],
+    };
+    if let Some(frame_time) = parse_frame_time(&detection.source) {
+        caption.lines.push(frame_time.format("%Y-%m-%d %H:%M UTC").to_string());
+    }
+    compose_legend(&mut img, &source_img, radar_area, &config.palette, &config.legend, &caption);
+    Ok(img)
+}
+
+fn open_radar_image(image_path: &str, radar_area: (u32, u32)) -> Result<DynamicImage, Box<dyn Error>> {
+    let img = image::open(image_path).map_err(|e| format!("Failed to open image {}: {}", image_path, e))?;
+    let (width, height) = img.dimensions();
+    let (radar_width, radar_height) = radar_area;
+    if radar_width > width || radar_height > height {
+        return Err(format!("Radar area exceeds image dimensions of {}", image_path).into());
+    }
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use crate::test_support::detection_of;
+
+    #[test]
+    fn base_image_link_is_relative_to_the_svg_directory() {
+        let base = Path::new("/work/data/base/base image.png");
+        assert_eq!(relative_href(base, Path::new("/work/out/svg")), "../../data/base/base%20image.png");
+        assert_eq!(relative_href(base, Path::new("/work/data/base")), "base%20image.png");
+        assert_eq!(relative_href(base, Path::new("/work")), "data/base/base%20image.png");
+    }
+
+    #[test]
+    fn legend_is_composed_without_touching_the_base_image() {
+        let dir = std::env::temp_dir().join(format!("storm_detect_legend_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let panel = |x: u32| if x < 40 { Rgba([0, 0, 0, 255]) } else { Rgba([0, 0, 64, 255]) };
+        let base = RgbaImage::from_fn(60, 40, |x, _| panel(x));
+        let source = RgbaImage::from_fn(60, 40, |x, y| if x >= 45 && y < 10 { Rgba([255, 0, 0, 255]) } else { panel(x) });
+        let base_path = dir.join("base.png");
+        let source_path = dir.join("frame.png");
+        base.save(&base_path).unwrap();
+        source.save(&source_path).unwrap();
+        let base_bytes = fs::read(&base_path).unwrap();
+
+        let mut config = Config::default();
+        config.station.base_image = base_path.to_string_lossy().into_owned();
+        config.station.radar_area = [40, 40];
+        let img = compose_base_image(&detection_of(&source_path.to_string_lossy(), Vec::new(), Vec::new()), &config).unwrap();
+
+        assert_eq!(img, source);
+        assert_eq!(fs::read(&base_path).unwrap(), base_bytes);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
-use image::{GenericImageView, Pixel as ImagePixel, Rgba};
+use image::{DynamicImage, GenericImageView, Pixel as ImagePixel, Rgba};
+use serde::{Deserialize, Serialize};
 use crate::consts::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -17,22 +18,74 @@ fn range_match(color: &Rgba<u8>, target_color: &Rgba<u8>, range_width: u8) -> bo
         .all(|(&t, &c)| t.saturating_sub(range_width) <= c && c <= t.saturating_add(range_width))
 }
 
-fn acquire_intensity(pixel_color: &Rgba<u8>) -> u32 {
-    match pixel_color {
-        //c if range_match(c, &Rgba([0, 160, 224, 255]), 10) => 10,
-        c if range_match(c, &Rgba([0, 0, 246, 255]), 10) => 15,
-        c if range_match(c, &Rgba([0, 254, 0, 255]), 10) => 20,
-        c if range_match(c, &Rgba([0, 200, 0, 255]), 10) => 25,
-        c if range_match(c, &Rgba([0, 144, 0, 255]), 10) => 30,
-        c if range_match(c, &Rgba([254, 254, 0, 255]), 10) => 35,
-        c if range_match(c, &Rgba([230, 192, 0, 255]), 10) => 40,
-        c if range_match(c, &Rgba([254, 144, 0, 255]), 10) => 45,
-        c if range_match(c, &Rgba([254, 0, 0, 255]), 10) => 50,
-        c if range_match(c, &Rgba([166, 0, 0, 255]), 10) => 55,
-        c if range_match(c, &Rgba([100, 0, 0, 255]), 10) => 60,
-        c if range_match(c, &Rgba([254, 0, 254, 255]), 10) => 65,
-        c if range_match(c, &Rgba([152, 84, 200, 255]), 10) => 70,
-        _ => 0,
+// Legend colors of the reflectivity product and the dBZ level they stand for
+const INTENSITY_PALETTE: [(Rgba<u8>, u32); 12] = [
+    (Rgba([0, 0, 246, 255]), 15),
+    (Rgba([0, 254, 0, 255]), 20),
+    (Rgba([0, 200, 0, 255]), 25),
+    (Rgba([0, 144, 0, 255]), 30),
+    (Rgba([254, 254, 0, 255]), 35),
+    (Rgba([230, 192, 0, 255]), 40),
+    (Rgba([254, 144, 0, 255]), 45),
+    (Rgba([254, 0, 0, 255]), 50),
+    (Rgba([166, 0, 0, 255]), 55),
+    (Rgba([100, 0, 0, 255]), 60),
+    (Rgba([254, 0, 254, 255]), 65),
+    (Rgba([152, 84, 200, 255]), 70),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaletteLevel {
+    pub color: [u8; 3],
+    pub dbz: u32,
+}
+
+impl PaletteLevel {
+    pub fn rgba(&self) -> Rgba<u8> {
+        Rgba([self.color[0], self.color[1], self.color[2], 255])
+    }
+}
+
+// Decoding palette, a pixel belongs to a level when every channel is within delta of the level color
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Palette {
+    pub delta: u8,
+    pub levels: Vec<PaletteLevel>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            delta: DELTA,
+            levels: INTENSITY_PALETTE
+                .iter()
+                .map(|&(color, dbz)| PaletteLevel { color: [color[0], color[1], color[2]], dbz })
+                .collect(),
+        }
+    }
+}
+
+impl Palette {
+    pub fn intensity_of(&self, pixel_color: &Rgba<u8>) -> Option<u32> {
+        self.levels
+            .iter()
+            .find(|level| range_match(pixel_color, &level.rgba(), self.delta))
+            .map(|level| level.dbz)
+    }
+
+    // Legend color of the highest level not above the given intensity
+    pub fn color_of(&self, intensity: u32) -> Option<Rgba<u8>> {
+        self.levels
+            .iter()
+            .rev()
+            .find(|level| level.dbz <= intensity)
+            .map(|level| level.rgba())
+    }
+
+    pub fn max_dbz(&self) -> u32 {
+        self.levels.iter().map(|level| level.dbz).max().unwrap_or(0)
     }
 }
 
@@ -49,24 +102,30 @@ pub fn filter_pixels_with_color(
         panic!("Radar area exceeds image dimensions");
     }
 
+    // Restrict the default palette to the requested colors
+    let mut palette = Palette::default();
+    palette.levels.retain(|level| color_list.contains(&level.rgba()));
+    decode_pixels(&img, &palette, radar_area)
+}
+
+// Echo pixels of an already opened image, the radar area must fit inside the image
+pub fn decode_pixels(img: &DynamicImage, palette: &Palette, radar_area: (u32, u32)) -> Vec<Pixel> {
+    let (radar_width, radar_height) = radar_area;
     let mut filtered_result = Vec::new();
 
     for x in 0..radar_width {
         for y in 0..radar_height {
             let pixel_color = img.get_pixel(x, y).to_rgba();
-            for target_color in color_list {
-                if range_match(&pixel_color, target_color, DELTA) {
-                    let intensity = acquire_intensity(&pixel_color);
-                    filtered_result.push(Pixel {
-                        x: x as i32,
-                        y: y as i32,
-                        color: pixel_color,
-                        intensity,
-                    });
-                }
+            if let Some(intensity) = palette.intensity_of(&pixel_color) {
+                filtered_result.push(Pixel {
+                    x: x as i32,
+                    y: y as i32,
+                    color: pixel_color,
+                    intensity,
+                });
             }
         }
     }
 
     filtered_result
-}
\ No newline at end of file
+}
@@ -0,0 +1,229 @@
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use crate::config::StationConfig;
+use crate::geo::lat_lon_to_pixel;
+use crate::storm::{calculate_azimuth_degrees, Storm};
+use crate::tracking::StormMotion;
+use crate::utils::azimuth_to_direction;
+
+// A site to watch, located by latitude and longitude or by image pixel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PointOfInterest {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pixel: Option<[f64; 2]>,
+    pub radius_km: f64,
+}
+
+impl PointOfInterest {
+    // Image position, the pixel coordinates win over latitude and longitude
+    pub fn position(&self, station: &StationConfig) -> Option<Point2<f64>> {
+        match (self.pixel, self.latitude, self.longitude) {
+            (Some([x, y]), _, _) => Some(Point2::new(x, y)),
+            (None, Some(latitude), Some(longitude)) => Some(lat_lon_to_pixel(latitude, longitude, station)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoiConfig {
+    // Moving storms expected to reach a point within this time are flagged as arriving
+    pub lookahead_minutes: f64,
+    pub points: Vec<PointOfInterest>,
+}
+
+impl Default for PoiConfig {
+    fn default() -> Self {
+        PoiConfig {
+            lookahead_minutes: 60.0,
+            points: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PoiProximity {
+    pub poi: String,
+    pub storm_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_id: Option<u32>,
+    // From the storm center
+    pub distance_km: f64,
+    // From the nearest echo pixel of the storm
+    pub edge_distance_km: f64,
+    // Direction from the storm center to the point, clockwise from north
+    pub bearing_deg: f64,
+    pub compass: String,
+    // Some echo of the storm lies within the radius of the point
+    pub inside: bool,
+    // Minutes until the storm reaches the radius at its current motion, 0 when already inside
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_minutes: Option<f64>,
+    // Expected within lookahead_minutes, not set for storms already inside
+    pub arriving: bool,
+}
+
+impl PoiProximity {
+    pub fn is_alert(&self) -> bool {
+        self.inside || self.arriving
+    }
+}
+
+pub fn poi_proximity(
+    storm: &Storm,
+    motion: Option<&StormMotion>,
+    poi: &PointOfInterest,
+    position: &Point2<f64>,
+    config: &PoiConfig,
+    km_per_pixel: f64,
+) -> PoiProximity {
+    let center = Point2::new(storm.intensity_center.x as f64, storm.intensity_center.y as f64);
+    let distance_km = nalgebra::distance(&center, position) * km_per_pixel;
+    let edge_distance_km = storm
+        .pixels
+        .iter()
+        .map(|pixel| nalgebra::distance(&Point2::new(pixel.x as f64, pixel.y as f64), position) * km_per_pixel)
+        .fold(distance_km, f64::min);
+    let bearing_deg = calculate_azimuth_degrees(position, &center);
+    let inside = edge_distance_km <= poi.radius_km;
+
+    // The storm is moved as a whole, so its extent towards the point adds to the radius its center has to reach
+    let eta_minutes = if inside {
+        Some(0.0)
+    } else {
+        let reach_km = poi.radius_km + (distance_km - edge_distance_km);
+        motion
+            .and_then(|motion| motion.velocity)
+            .and_then(|velocity| hours_to_reach(&center, velocity, position, reach_km / km_per_pixel))
+            .map(|hours| hours * 60.0)
+    };
+    let arriving = !inside && eta_minutes.is_some_and(|eta| eta <= config.lookahead_minutes);
+
+    PoiProximity {
+        poi: poi.name.clone(),
+        storm_id: storm.storm_id,
+        track_id: motion.map(|motion| motion.track_id),
+        distance_km,
+        edge_distance_km,
+        bearing_deg,
+        compass: azimuth_to_direction(bearing_deg).to_string(),
+        inside,
+        eta_minutes,
+        arriving,
+    }
+}
+
+// Every storm against every point, points without a position are skipped. Motion is given per storm in input
+// order, or empty for an untracked frame.
+pub fn proximity_report(storms: &[Storm], motion: &[StormMotion], config: &PoiConfig, station: &StationConfig) -> Vec<PoiProximity> {
+    let mut report = Vec::new();
+    for poi in &config.points {
+        let Some(position) = poi.position(station) else {
+            continue;
+        };
+        for (index, storm) in storms.iter().enumerate() {
+            report.push(poi_proximity(storm, motion.get(index), poi, &position, config, station.km_per_pixel));
+        }
+    }
+    report
+}
+
+// First time in hours at which a point moving at velocity from start comes within radius of target,
+// None when it never does. All in pixels.
+fn hours_to_reach(start: &Point2<f64>, velocity: (f64, f64), target: &Point2<f64>, radius: f64) -> Option<f64> {
+    let (dx, dy) = (start.x - target.x, start.y - target.y);
+    let (vx, vy) = velocity;
+    let a = vx * vx + vy * vy;
+    let b = 2.0 * (dx * vx + dy * vy);
+    let c = dx * dx + dy * dy - radius * radius;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    // Both roots share a sign since c > 0, the first is the entry into the circle
+    let hours = (-b - discriminant.sqrt()) / (2.0 * a);
+    (hours >= 0.0).then_some(hours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block, storm_from_pixels};
+
+    // Five pixels eastwards from (100, 100), the intensity center on the west end
+    fn storm() -> Storm {
+        storm_from_pixels(3, &block(100, 100, 5, 1, 50))
+    }
+
+    fn point(radius_km: f64) -> PointOfInterest {
+        PointOfInterest {
+            name: String::from("Airport"),
+            latitude: None,
+            longitude: None,
+            pixel: Some([150.0, 100.0]),
+            radius_km,
+        }
+    }
+
+    fn proximity(velocity: Option<(f64, f64)>, radius_km: f64, lookahead_minutes: f64) -> PoiProximity {
+        let poi = point(radius_km);
+        let motion = velocity.map(|velocity| StormMotion { track_id: 7, velocity: Some(velocity) });
+        let config = PoiConfig { lookahead_minutes, points: Vec::new() };
+        poi_proximity(&storm(), motion.as_ref(), &poi, &Point2::new(150.0, 100.0), &config, 1.0)
+    }
+
+    #[test]
+    fn hours_to_reach_known_cases() {
+        let (start, target) = (Point2::new(0.0, 0.0), Point2::new(100.0, 0.0));
+        let cases = [
+            ("approaching", start, (45.0, 0.0), Some(2.0)),
+            ("already inside", Point2::new(95.0, 0.0), (45.0, 0.0), Some(0.0)),
+            ("moving away", start, (-45.0, 0.0), None),
+            ("passing by", start, (0.0, 45.0), None),
+            ("not moving", start, (0.0, 0.0), None),
+        ];
+        for (case, start, velocity, expected) in cases {
+            assert_eq!(hours_to_reach(&start, velocity, &target, 10.0), expected, "{}", case);
+        }
+    }
+
+    #[test]
+    fn storm_within_the_radius_is_inside() {
+        let report = proximity(None, 50.0, 60.0);
+        assert_eq!((report.distance_km, report.edge_distance_km), (50.0, 46.0));
+        assert!(report.inside && report.is_alert());
+        assert_eq!(report.eta_minutes, Some(0.0));
+        assert!(!report.arriving);
+        assert_eq!((report.bearing_deg, report.compass.as_str()), (90.0, "E"));
+    }
+
+    #[test]
+    fn approaching_storm_arrives_within_the_lookahead() {
+        // The east edge is 4 km ahead of the center, which has 36 km to go at 30 km/h
+        let report = proximity(Some((30.0, 0.0)), 10.0, 90.0);
+        assert!(!report.inside);
+        assert_eq!(report.track_id, Some(7));
+        assert!((report.eta_minutes.unwrap() - 72.0).abs() < 1e-9);
+        assert!(report.arriving);
+        assert!(!proximity(Some((30.0, 0.0)), 10.0, 60.0).arriving);
+    }
+
+    #[test]
+    fn receding_or_still_storms_have_no_eta() {
+        for velocity in [Some((-30.0, 0.0)), Some((0.0, 0.0)), None] {
+            let report = proximity(velocity, 10.0, 60.0);
+            assert_eq!(report.eta_minutes, None, "{:?}", velocity);
+            assert!(!report.is_alert());
+        }
+    }
+}
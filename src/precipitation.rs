@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use crate::grid::ReflectivityGrid;
+use crate::storm::Storm;
+
+// Z = a * R^b with Z in mm^6/m^3 and R in mm/h
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZrRelation {
+    // Stratiform rain, Z = 200 R^1.6
+    #[default]
+    MarshallPalmer,
+    // WSR-88D convective default, Z = 300 R^1.4
+    Convective,
+    // WSR-88D tropical, Z = 250 R^1.2
+    Tropical,
+    Custom { a: f64, b: f64 },
+}
+
+impl ZrRelation {
+    pub fn coefficients(&self) -> (f64, f64) {
+        match *self {
+            ZrRelation::MarshallPalmer => (200.0, 1.6),
+            ZrRelation::Convective => (300.0, 1.4),
+            ZrRelation::Tropical => (250.0, 1.2),
+            ZrRelation::Custom { a, b } => (a, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrecipitationConfig {
+    pub relation: ZrRelation,
+    // Reflectivity above the cap is mostly hail, which would inflate the rain rate, so it is clamped first
+    pub hail_cap_dbz: f64,
+}
+
+impl Default for PrecipitationConfig {
+    fn default() -> Self {
+        PrecipitationConfig {
+            relation: ZrRelation::default(),
+            hail_cap_dbz: 53.0,
+        }
+    }
+}
+
+impl PrecipitationConfig {
+    // Rain rate in mm/h of the given reflectivity, 0 dBZ stands for no echo and gives no rain
+    pub fn rain_rate(&self, dbz: f64) -> f64 {
+        if dbz <= 0.0 {
+            return 0.0;
+        }
+        let (a, b) = self.relation.coefficients();
+        let z = 10f64.powf(dbz.min(self.hail_cap_dbz) / 10.0);
+        (z / a).powf(1.0 / b)
+    }
+}
+
+// Rain rate in mm/h of every pixel of the radar area
+#[derive(Debug, Clone, PartialEq)]
+pub struct RainRateGrid {
+    pub width: u32,
+    pub height: u32,
+    data: Vec<f32>,
+}
+
+impl RainRateGrid {
+    // Reflectivity values are the lower bounds of their palette level, so rates are conservative
+    pub fn from_reflectivity(reflectivity: &ReflectivityGrid, config: &PrecipitationConfig) -> Self {
+        let (width, height) = (reflectivity.width, reflectivity.height);
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                data.push(config.rain_rate(reflectivity.get(x, y) as f64) as f32);
+            }
+        }
+        RainRateGrid { width, height, data }
+    }
+
+    // Positions outside of the grid read as no rain
+    pub fn get(&self, x: i32, y: i32) -> f32 {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            self.data[(y as u32 * self.width + x as u32) as usize]
+        } else {
+            0.0
+        }
+    }
+
+    // Row-major rates, row 0 is the top of the image
+    pub fn values(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn max(&self) -> f32 {
+        self.data.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct StormRainfall {
+    pub mean_rain_rate_mm_h: f64,
+    pub max_rain_rate_mm_h: f64,
+    // Water volume falling on the storm area per hour
+    pub areal_rainfall_m3_h: f64,
+}
+
+pub fn storm_rainfall(storm: &Storm, config: &PrecipitationConfig, km_per_pixel: f64) -> StormRainfall {
+    if storm.pixels.is_empty() {
+        return StormRainfall::default();
+    }
+    let rates: Vec<f64> = storm.pixels.iter().map(|pixel| config.rain_rate(pixel.intensity as f64)).collect();
+    let total: f64 = rates.iter().sum();
+    // mm/h over one pixel of km_per_pixel^2 km^2: 1e-3 m * 1e6 m^2 per km^2
+    let pixel_area_m2 = km_per_pixel * km_per_pixel * 1e6;
+    StormRainfall {
+        mean_rain_rate_mm_h: total / rates.len() as f64,
+        max_rain_rate_mm_h: rates.iter().copied().fold(0.0, f64::max),
+        areal_rainfall_m3_h: total * 1e-3 * pixel_area_m2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::storm_from_pixels;
+
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-3 * expected.abs().max(1.0)
+    }
+
+    #[test]
+    fn rain_rate_follows_the_zr_relation() {
+        let cases = [
+            (ZrRelation::MarshallPalmer, 40.0, 11.5307),
+            (ZrRelation::MarshallPalmer, 30.0, 2.7344),
+            (ZrRelation::Convective, 40.0, 12.2397),
+            (ZrRelation::Tropical, 40.0, 21.6297),
+            (ZrRelation::Custom { a: 200.0, b: 1.6 }, 50.0, 48.6246),
+        ];
+        for (relation, dbz, expected) in cases {
+            let config = PrecipitationConfig { relation, ..PrecipitationConfig::default() };
+            let rate = config.rain_rate(dbz);
+            assert!(close(rate, expected), "{:?} at {} dBZ: {}", relation, dbz, rate);
+        }
+    }
+
+    #[test]
+    fn reflectivity_is_clamped_at_the_hail_cap() {
+        let config = PrecipitationConfig::default();
+        assert!(close(config.rain_rate(53.0), 74.8783));
+        assert_eq!(config.rain_rate(65.0), config.rain_rate(53.0));
+        assert_eq!(config.rain_rate(0.0), 0.0);
+    }
+
+    #[test]
+    fn areal_rainfall_integrates_the_storm_pixels() {
+        let storm = storm_from_pixels(1, &[(0, 0, 40), (1, 0, 40), (2, 0, 30)]);
+        let rainfall = storm_rainfall(&storm, &PrecipitationConfig::default(), 2.0);
+        let total = 2.0 * 11.5307 + 2.7344;
+        assert!(close(rainfall.mean_rain_rate_mm_h, total / 3.0));
+        assert!(close(rainfall.max_rain_rate_mm_h, 11.5307));
+        // 1 mm/h over a 2 km x 2 km pixel is 4000 m^3/h
+        assert!(close(rainfall.areal_rainfall_m3_h, total * 4000.0));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::render::RenderStyle;
+
+// Range rings, azimuth spokes and the scale bar around the radar center, laid out once for both the png and the svg.
+// Label positions are the centers of the label texts.
+
+const SCALE_BAR_MARGIN: f32 = 12.0;
+const SCALE_BAR_TICK: f32 = 4.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeRing {
+    pub distance_km: f64,
+    pub radius: f32,
+    pub label: String,
+    pub label_center: (f32, f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AzimuthSpoke {
+    // Degrees clockwise from north
+    pub azimuth: f64,
+    pub end: (f32, f32),
+    pub label: String,
+    pub label_center: (f32, f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaleBar {
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+    // Half height of the end ticks
+    pub tick: f32,
+    pub label: String,
+    pub label_center: (f32, f32),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReferenceGrid {
+    pub center: (f32, f32),
+    pub rings: Vec<RangeRing>,
+    pub spokes: Vec<AzimuthSpoke>,
+    pub scale_bar: Option<ScaleBar>,
+}
+
+// Rings and spokes reach up to the nearest edge of the radar area, so every ring is drawn complete.
+// Only the parts enabled in the render layers are laid out.
+pub fn reference_grid(center: (f32, f32), radar_area: (u32, u32), km_per_pixel: f64, style: &RenderStyle) -> ReferenceGrid {
+    let layers = &style.layers;
+    let max_radius = center
+        .0
+        .min(center.1)
+        .min(radar_area.0 as f32 - 1.0 - center.0)
+        .min(radar_area.1 as f32 - 1.0 - center.1);
+    if !(max_radius > 0.0 && km_per_pixel > 0.0) {
+        return ReferenceGrid {
+            center,
+            ..ReferenceGrid::default()
+        };
+    }
+    let label_inset = style.grid_font_size;
+
+    let mut rings = Vec::new();
+    if layers.range_rings && style.ring_interval_km > 0.0 {
+        // Ring labels sit just inside their ring, halfway between the first two spokes
+        let label_azimuth = (style.spoke_interval_deg / 2.0).to_radians();
+        for step in 1.. {
+            let distance_km = step as f64 * style.ring_interval_km;
+            let radius = (distance_km / km_per_pixel) as f32;
+            if radius > max_radius {
+                break;
+            }
+            rings.push(RangeRing {
+                distance_km,
+                radius,
+                label: format_number(distance_km),
+                label_center: polar(center, label_azimuth, radius - label_inset * 0.6),
+            });
+        }
+    }
+
+    let mut spokes = Vec::new();
+    if layers.azimuth_spokes && style.spoke_interval_deg > 0.0 {
+        let count = (360.0 / style.spoke_interval_deg - 1e-9).ceil() as usize;
+        for step in 0..count {
+            let azimuth = step as f64 * style.spoke_interval_deg;
+            let angle = azimuth.to_radians();
+            spokes.push(AzimuthSpoke {
+                azimuth,
+                end: polar(center, angle, max_radius),
+                label: format!("{}°", format_number(azimuth)),
+                label_center: polar(center, angle, max_radius - label_inset),
+            });
+        }
+    }
+
+    let scale_bar = (layers.scale_bar && style.scale_bar_km > 0.0).then(|| {
+        let length = (style.scale_bar_km / km_per_pixel) as f32;
+        let y = radar_area.1 as f32 - 1.0 - SCALE_BAR_MARGIN;
+        ScaleBar {
+            start: (SCALE_BAR_MARGIN, y),
+            end: (SCALE_BAR_MARGIN + length, y),
+            tick: SCALE_BAR_TICK,
+            label: format!("{} km", format_number(style.scale_bar_km)),
+            label_center: (SCALE_BAR_MARGIN + length / 2.0, y - SCALE_BAR_TICK - style.grid_font_size * 0.6),
+        }
+    });
+
+    ReferenceGrid {
+        center,
+        rings,
+        spokes,
+        scale_bar,
+    }
+}
+
+// Point at the given distance in pixels from the center, angle in radians clockwise from north
+fn polar(center: (f32, f32), angle: f64, distance: f32) -> (f32, f32) {
+    (
+        center.0 + distance * angle.sin() as f32,
+        center.1 - distance * angle.cos() as f32,
+    )
+}
+
+// Whole numbers without decimals, others with one
+fn format_number(value: f64) -> String {
+    if (value - value.round()).abs() < 1e-6 {
+        format!("{}", value.round() as i64)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::RenderLayers;
+
+    fn style() -> RenderStyle {
+        RenderStyle {
+            layers: RenderLayers { range_rings: true, azimuth_spokes: true, scale_bar: true, ..RenderLayers::default() },
+            ..RenderStyle::default()
+        }
+    }
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn grid_is_scaled_by_the_pixel_size() {
+        // Half a km per pixel, the nearest edge of the radar area is 200 pixels or 100 km away
+        let grid = reference_grid((200.0, 200.0), (401, 401), 0.5, &style());
+
+        let rings: Vec<(f64, f32, &str)> = grid.rings.iter().map(|ring| (ring.distance_km, ring.radius, ring.label.as_str())).collect();
+        assert_eq!(rings, [(50.0, 100.0, "50"), (100.0, 200.0, "100")]);
+
+        assert_eq!(grid.spokes.len(), 12);
+        for (step, spoke) in grid.spokes.iter().enumerate() {
+            assert_eq!(spoke.azimuth, step as f64 * 30.0);
+            assert_eq!(spoke.label, format!("{}°", step * 30));
+        }
+        assert!(close(grid.spokes[0].end, (200.0, 0.0)));
+        assert!(close(grid.spokes[3].end, (400.0, 200.0)));
+        assert!(close(grid.spokes[6].end, (200.0, 400.0)));
+
+        let bar = grid.scale_bar.unwrap();
+        assert_eq!(bar.end.0 - bar.start.0, 100.0);
+        assert_eq!(bar.start, (SCALE_BAR_MARGIN, 400.0 - SCALE_BAR_MARGIN));
+        assert_eq!(bar.label, "50 km");
+    }
+
+    #[test]
+    fn fractional_distances_keep_one_decimal() {
+        let style = RenderStyle { ring_interval_km: 12.5, spoke_interval_deg: 45.0, scale_bar_km: 7.5, ..style() };
+        let grid = reference_grid((200.0, 200.0), (401, 401), 1.0, &style);
+        assert_eq!(grid.rings[0].label, "12.5");
+        assert_eq!(grid.rings.len(), 16);
+        assert_eq!(grid.spokes.len(), 8);
+        assert_eq!(grid.scale_bar.map(|bar| bar.label), Some(String::from("7.5 km")));
+    }
+
+    #[test]
+    fn disabled_layers_and_unknown_scales_lay_out_nothing() {
+        let empty = |grid: ReferenceGrid| grid.rings.is_empty() && grid.spokes.is_empty() && grid.scale_bar.is_none();
+        assert!(empty(reference_grid((200.0, 200.0), (401, 401), 0.5, &RenderStyle::default())));
+        assert!(empty(reference_grid((200.0, 200.0), (401, 401), 0.0, &style())));
+    }
+}
@@ -0,0 +1,396 @@
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+use nalgebra::Point2;
+use once_cell::sync::Lazy;
+use rusttype::{point, Font, Scale};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::consts::*;
+use crate::draw::{blend_pixel, draw_circle, draw_ellipse, draw_filled_circle, draw_line, fill_rect, Stroke};
+use crate::ellipse::fit_ellipse;
+use crate::labels::place_labels;
+use crate::reference::{reference_grid, ReferenceGrid};
+use crate::storm::{has_major_core, DetectionParams, Storm};
+
+// Embedded label font, parsed on first use
+pub(crate) static FONT: Lazy<Font<'static>> = Lazy::new(|| {
+    let font_data: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+    Font::try_from_bytes(font_data).expect("Failed to load font")
+});
+
+const HALO_OFFSETS: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+// Which parts of the analysis are drawn over the base image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderLayers {
+    pub echoes: bool,
+    pub ellipses: bool,
+    pub centers: bool,
+    pub labels: bool,
+    pub radial_lines: bool,
+    // Reference overlays, off by default as most base images already carry a grid
+    pub range_rings: bool,
+    pub azimuth_spokes: bool,
+    pub scale_bar: bool,
+}
+
+impl Default for RenderLayers {
+    fn default() -> Self {
+        RenderLayers {
+            echoes: true,
+            ellipses: true,
+            centers: true,
+            labels: true,
+            radial_lines: true,
+            range_rings: false,
+            azimuth_spokes: false,
+            scale_bar: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderStyle {
+    pub antialias: bool,
+    pub layers: RenderLayers,
+    pub line_width: f32,
+    pub ellipse_width: f32,
+    pub center_radius: f32,
+    pub label_font_size: f32,
+    pub label_color: [u8; 3],
+    // Outline around the label glyphs and a translucent box behind the label, both in label_halo_color
+    pub label_halo: bool,
+    pub label_background: bool,
+    pub label_halo_color: [u8; 3],
+    pub label_background_opacity: f32,
+    pub center_color: [u8; 3],
+    pub line_color: [u8; 3],
+    pub ellipse_color: [u8; 3],
+    // Ellipse color by storm type, e.g. "multicell", types without an entry use ellipse_color
+    pub type_colors: BTreeMap<String, [u8; 3]>,
+    // Range rings, azimuth spokes and scale bar, distances in km with the pixel scale of the station
+    pub grid_color: [u8; 3],
+    pub grid_width: f32,
+    pub grid_font_size: f32,
+    pub ring_interval_km: f64,
+    pub spoke_interval_deg: f64,
+    pub scale_bar_km: f64,
+}
+
+impl Default for RenderStyle {
+    fn default() -> Self {
+        RenderStyle {
+            antialias: true,
+            layers: RenderLayers::default(),
+            line_width: 1.0,
+            ellipse_width: 1.5,
+            center_radius: 2.0,
+            label_font_size: 18.0,
+            label_color: [255, 255, 255],
+            label_halo: false,
+            label_background: false,
+            label_halo_color: [0, 0, 0],
+            label_background_opacity: 0.6,
+            center_color: rgb(&INTENSITY_CENTER_COLOR),
+            line_color: rgb(&CONNECTION_LINE_COLOR),
+            ellipse_color: rgb(&ELLIPSE_COLOR),
+            type_colors: BTreeMap::new(),
+            grid_color: [200, 200, 200],
+            grid_width: 1.0,
+            grid_font_size: 12.0,
+            ring_interval_km: 50.0,
+            spoke_interval_deg: 30.0,
+            scale_bar_km: 50.0,
+        }
+    }
+}
+
+impl RenderStyle {
+    pub fn ellipse_color_of(&self, storm_type: &str) -> Rgba<u8> {
+        rgba(self.type_colors.get(storm_type).unwrap_or(&self.ellipse_color))
+    }
+
+    pub fn line_stroke(&self) -> Stroke {
+        Stroke {
+            width: self.line_width,
+            color: rgba(&self.line_color),
+            antialias: self.antialias,
+        }
+    }
+
+    pub fn leader_stroke(&self) -> Stroke {
+        Stroke {
+            width: 1.0,
+            color: rgba(&self.label_color),
+            antialias: self.antialias,
+        }
+    }
+
+    pub fn grid_stroke(&self) -> Stroke {
+        Stroke {
+            width: self.grid_width,
+            color: rgba(&self.grid_color),
+            antialias: self.antialias,
+        }
+    }
+
+    pub fn ellipse_stroke(&self, storm_type: &str) -> Stroke {
+        Stroke {
+            width: self.ellipse_width,
+            color: self.ellipse_color_of(storm_type),
+            antialias: self.antialias,
+        }
+    }
+}
+
+pub(crate) fn rgba(color: &[u8; 3]) -> Rgba<u8> {
+    Rgba([color[0], color[1], color[2], 255])
+}
+
+fn rgb(color: &Rgba<u8>) -> [u8; 3] {
+    [color[0], color[1], color[2]]
+}
+
+// Draw already classified storms over the image, layer by layer so lines never hide echoes of other storms.
+// Labels are kept inside the radar area.
+pub fn render_storms(
+    img: &mut RgbaImage,
+    storms: &[Storm],
+    radar_center: &Point2<f64>,
+    radar_area: (u32, u32),
+    params: &DetectionParams,
+    style: &RenderStyle,
+) {
+    let layers = &style.layers;
+    let center = (radar_center.x as f32, radar_center.y as f32);
+
+    // The reference grid goes underneath everything else
+    draw_reference_grid(img, &reference_grid(center, radar_area, params.km_per_pixel, style), style);
+
+    if layers.echoes {
+        for pixel in storms.iter().flat_map(|storm| &storm.pixels) {
+            blend_pixel(img, pixel.x, pixel.y, pixel.color, 1.0);
+        }
+    }
+
+    if layers.radial_lines {
+        let stroke = style.line_stroke();
+        for storm in storms {
+            let storm_center = (storm.intensity_center.x as f32, storm.intensity_center.y as f32);
+            draw_line(img, center, storm_center, &stroke);
+        }
+    }
+
+    if layers.ellipses {
+        for storm in storms.iter().filter(|storm| has_major_core(storm, params)) {
+            let ellipse = fit_ellipse(storm, params.ellipse_method, params.core_dbz);
+            draw_ellipse(
+                img,
+                (ellipse.center.x as f32, ellipse.center.y as f32),
+                ellipse.major_axis as f32,
+                ellipse.minor_axis as f32,
+                ellipse.angle as f32,
+                &style.ellipse_stroke(&storm.storm_type),
+            );
+        }
+    }
+
+    if layers.centers {
+        let color = rgba(&style.center_color);
+        for storm in storms {
+            let storm_center = (storm.intensity_center.x as f32, storm.intensity_center.y as f32);
+            draw_filled_circle(img, storm_center, style.center_radius, color, style.antialias);
+        }
+    }
+
+    if layers.labels {
+        let scale = Scale::uniform(style.label_font_size);
+        let color = rgba(&style.label_color);
+        let halo_color = rgba(&style.label_halo_color);
+        for label in place_labels(storms, params, style, radar_area) {
+            let text_box = &label.text_box;
+            if style.label_background {
+                fill_rect(img, text_box.x, text_box.y, text_box.width, text_box.height, halo_color, style.label_background_opacity);
+            }
+            if label.leader {
+                draw_line(img, label.anchor, text_box.nearest_point(label.anchor), &style.leader_stroke());
+            }
+            draw_label_text(img, label.x, label.y, &label.text, scale, color, style);
+        }
+    }
+}
+
+fn draw_reference_grid(img: &mut RgbaImage, grid: &ReferenceGrid, style: &RenderStyle) {
+    let stroke = style.grid_stroke();
+    let scale = Scale::uniform(style.grid_font_size);
+    let color = stroke.color;
+    let mut labels: Vec<(&str, (f32, f32))> = Vec::new();
+
+    for ring in &grid.rings {
+        draw_circle(img, grid.center, ring.radius, &stroke);
+        labels.push((&ring.label, ring.label_center));
+    }
+    for spoke in &grid.spokes {
+        draw_line(img, grid.center, spoke.end, &stroke);
+        labels.push((&spoke.label, spoke.label_center));
+    }
+    if let Some(bar) = &grid.scale_bar {
+        draw_line(img, bar.start, bar.end, &stroke);
+        for (x, y) in [bar.start, bar.end] {
+            draw_line(img, (x, y - bar.tick), (x, y + bar.tick), &stroke);
+        }
+        labels.push((&bar.label, bar.label_center));
+    }
+
+    for (text, (center_x, center_y)) in labels {
+        let (width, height) = text_size(scale, &FONT, text);
+        let x = (center_x - width as f32 / 2.0).round() as i32;
+        let y = (center_y - height as f32 / 2.0).round() as i32;
+        draw_label_text(img, x, y, text, scale, color, style);
+    }
+}
+
+// Text with the optional halo of the render style, x and y are the top left of the text
+fn draw_label_text(img: &mut RgbaImage, x: i32, y: i32, text: &str, scale: Scale, color: Rgba<u8>, style: &RenderStyle) {
+    if style.label_halo {
+        let halo_color = rgba(&style.label_halo_color);
+        for (dx, dy) in HALO_OFFSETS {
+            draw_text(img, halo_color, x + dx, y + dy, scale, text, style.antialias);
+        }
+    }
+    draw_text(img, color, x, y, scale, text, style.antialias);
+}
+
+// Without antialiasing the glyph coverage is cut at one half, so the text only uses its own color
+fn draw_text(img: &mut RgbaImage, color: Rgba<u8>, x: i32, y: i32, scale: Scale, text: &str, antialias: bool) {
+    if antialias {
+        draw_text_mut(img, color, x, y, scale, &FONT, text);
+        return;
+    }
+    let ascent = FONT.v_metrics(scale).ascent;
+    for glyph in FONT.layout(text, scale, point(0.0, ascent)) {
+        if let Some(bounds) = glyph.pixel_bounding_box() {
+            glyph.draw(|glyph_x, glyph_y, coverage| {
+                if coverage >= 0.5 {
+                    blend_pixel(img, x + bounds.min.x + glyph_x as i32, y + bounds.min.y + glyph_y as i32, color, 1.0);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::pixel::Palette;
+    use crate::test_support::{block, storm_from_pixels};
+
+    type Layer = fn(&mut RenderLayers) -> &mut bool;
+
+    const LAYERS: [Layer; 8] = [
+        |layers| &mut layers.echoes,
+        |layers| &mut layers.ellipses,
+        |layers| &mut layers.centers,
+        |layers| &mut layers.labels,
+        |layers| &mut layers.radial_lines,
+        |layers| &mut layers.range_rings,
+        |layers| &mut layers.azimuth_spokes,
+        |layers| &mut layers.scale_bar,
+    ];
+
+    const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    // Storm with a major core in palette colors, north-east of the radar center
+    fn storm(storm_type: &str) -> Storm {
+        let color = Palette::default().color_of(55).unwrap();
+        let mut storm = storm_from_pixels(1, &block(140, 40, 8, 8, 55));
+        storm.pixels.iter_mut().for_each(|pixel| pixel.color = color);
+        Storm { storm_type: storm_type.to_string(), ..storm }
+    }
+
+    fn layers(enabled: bool) -> RenderLayers {
+        let mut layers = RenderLayers::default();
+        for layer in LAYERS {
+            *layer(&mut layers) = enabled;
+        }
+        layers
+    }
+
+    fn render(storms: &[Storm], style: &RenderStyle) -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(200, 200, BACKGROUND);
+        let params = DetectionParams { km_per_pixel: 1.0, ..DetectionParams::default() };
+        render_storms(&mut img, storms, &Point2::new(100.0, 100.0), (200, 200), &params, style);
+        img
+    }
+
+    fn drawn(img: &RgbaImage) -> HashSet<(u32, u32)> {
+        img.enumerate_pixels().filter(|(_, _, &pixel)| pixel != BACKGROUND).map(|(x, y, _)| (x, y)).collect()
+    }
+
+    #[test]
+    fn disabled_layers_leave_their_pixels_unchanged() {
+        let storms = [storm("")];
+        let only: Vec<HashSet<(u32, u32)>> = LAYERS
+            .iter()
+            .map(|layer| {
+                let mut style = RenderStyle { layers: layers(false), ..RenderStyle::default() };
+                *layer(&mut style.layers) = true;
+                drawn(&render(&storms, &style))
+            })
+            .collect();
+        assert!(drawn(&render(&storms, &RenderStyle { layers: layers(false), ..RenderStyle::default() })).is_empty());
+
+        for (index, layer) in LAYERS.iter().enumerate() {
+            assert!(!only[index].is_empty(), "layer {} draws nothing", index);
+            let mut style = RenderStyle { layers: layers(true), ..RenderStyle::default() };
+            *layer(&mut style.layers) = false;
+            let without = drawn(&render(&storms, &style));
+            let own: Vec<&(u32, u32)> = only[index]
+                .iter()
+                .filter(|position| only.iter().enumerate().all(|(other, pixels)| other == index || !pixels.contains(position)))
+                .collect();
+            assert!(!own.is_empty() && own.iter().all(|position| !without.contains(position)), "layer {}", index);
+        }
+    }
+
+    #[test]
+    fn aliased_rendering_only_uses_the_style_and_palette_colors() {
+        let style = RenderStyle { layers: layers(true), antialias: false, label_halo: true, ..RenderStyle::default() };
+        let img = render(&[storm("")], &style);
+        let allowed: HashSet<Rgba<u8>> = [
+            BACKGROUND,
+            Palette::default().color_of(55).unwrap(),
+            rgba(&style.line_color),
+            rgba(&style.center_color),
+            rgba(&style.ellipse_color),
+            rgba(&style.label_color),
+            rgba(&style.label_halo_color),
+            rgba(&style.grid_color),
+        ]
+        .into_iter()
+        .collect();
+        let used: HashSet<Rgba<u8>> = img.pixels().copied().collect();
+        assert!(used.is_subset(&allowed), "{:?}", used.difference(&allowed).collect::<Vec<_>>());
+
+        let smooth = render(&[storm("")], &RenderStyle { antialias: true, ..style });
+        assert!(!smooth.pixels().all(|pixel| allowed.contains(pixel)));
+    }
+
+    #[test]
+    fn ellipses_take_the_color_of_their_storm_type() {
+        let magenta = [255, 0, 255];
+        let style = RenderStyle {
+            layers: RenderLayers { ellipses: true, ..layers(false) },
+            type_colors: BTreeMap::from([(String::from("multicell"), magenta)]),
+            ..RenderStyle::default()
+        };
+        let colors = |storm_type: &str| -> HashSet<Rgba<u8>> { render(&[storm(storm_type)], &style).pixels().copied().collect() };
+        assert!(colors("multicell").contains(&rgba(&magenta)));
+        assert!(!colors("multicell").contains(&rgba(&style.ellipse_color)));
+        assert!(colors("single cell").contains(&rgba(&style.ellipse_color)));
+        assert!(!colors("single cell").contains(&rgba(&magenta)));
+    }
+}
@@ -0,0 +1,497 @@
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use serde_json::json;
+use std::error::Error;
+use std::io::Write;
+use std::time::Duration;
+use crate::beam::{beam_geometry, elevation_from_name, BeamGeometry};
+use crate::config::{Config, StationConfig};
+use crate::districts::{district_summaries, District, DistrictSummary};
+use crate::geo::pixel_to_lat_lon;
+use crate::grid::ReflectivityGrid;
+use crate::hierarchy::{CellKind, StormHierarchy};
+use crate::pixel::Pixel;
+use crate::poi::{proximity_report, PoiProximity};
+use crate::precipitation::storm_rainfall;
+use crate::severe::severe_indicators;
+use crate::stats::{frame_histogram, intensity_stats, LevelCount};
+use crate::storm::Storm;
+use crate::tracking::StormMotion;
+use crate::utils::{azimuth_to_direction, parse_frame_time};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    // One pretty printed document per frame
+    Json,
+    // One compact document per frame and line, for appending frames to a stream
+    Ndjson,
+    // One row per storm, raw pixels and histograms are never included
+    Csv,
+    // FeatureCollection with a point feature at every storm center
+    GeoJson,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PixelReport {
+    pub x: i32,
+    pub y: i32,
+    pub intensity: u32,
+}
+
+impl From<&Pixel> for PixelReport {
+    fn from(pixel: &Pixel) -> Self {
+        PixelReport {
+            x: pixel.x,
+            y: pixel.y,
+            intensity: pixel.intensity,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StormReport {
+    pub storm_id: u32,
+    // Track and motion within a sequence of frames, absent for a single frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_kmh: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading_deg: Option<f64>,
+    pub center_x: i32,
+    pub center_y: i32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub distance_km: f64,
+    pub direction_deg: f64,
+    pub compass: String,
+    pub max_intensity: u32,
+    pub storm_type: String,
+    pub pixel_count: usize,
+    pub mean_rain_rate_mm_h: f64,
+    pub max_rain_rate_mm_h: f64,
+    pub areal_rainfall_m3_h: f64,
+    pub area_55_km2: f64,
+    pub fraction_55: f64,
+    pub area_60_km2: f64,
+    pub fraction_60: f64,
+    pub hail_probability: f64,
+    pub severe_core: bool,
+    pub mean_dbz: f64,
+    pub median_dbz: u32,
+    pub p90_dbz: u32,
+    pub weighted_area: f64,
+    pub histogram: Vec<LevelCount>,
+    // Radar geometry at the storm center, only with the beam model enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beam: Option<BeamGeometry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels: Option<Vec<PixelReport>>,
+}
+
+impl StormReport {
+    pub fn from_storm(
+        storm: &Storm,
+        config: &Config,
+        motion: Option<&StormMotion>,
+        elevation_deg: Option<f64>,
+        include_pixels: bool,
+    ) -> Self {
+        let station = &config.station;
+        let rainfall = storm_rainfall(storm, &config.precipitation, station.km_per_pixel);
+        let severe = severe_indicators(storm, &config.severe, station.km_per_pixel);
+        let stats = intensity_stats(&storm.pixels, &config.palette, station.km_per_pixel);
+        let beam = elevation_deg.map(|elevation_deg| beam_geometry(storm.distance, elevation_deg, station.altitude_m, &config.beam));
+        let (latitude, longitude) = pixel_to_lat_lon(storm.intensity_center.x as f64, storm.intensity_center.y as f64, station);
+        StormReport {
+            storm_id: storm.storm_id,
+            track_id: motion.map(|motion| motion.track_id),
+            speed_kmh: motion.and_then(|motion| motion.speed_kmh(station.km_per_pixel)),
+            heading_deg: motion.and_then(StormMotion::heading_deg),
+            center_x: storm.intensity_center.x,
+            center_y: storm.intensity_center.y,
+            latitude,
+            longitude,
+            distance_km: storm.distance,
+            direction_deg: storm.direction,
+            compass: azimuth_to_direction(storm.direction).to_string(),
+            max_intensity: storm.max_intensity,
+            storm_type: storm.storm_type.clone(),
+            pixel_count: storm.pixels.len(),
+            mean_rain_rate_mm_h: rainfall.mean_rain_rate_mm_h,
+            max_rain_rate_mm_h: rainfall.max_rain_rate_mm_h,
+            areal_rainfall_m3_h: rainfall.areal_rainfall_m3_h,
+            area_55_km2: severe.area_55_km2,
+            fraction_55: severe.fraction_55,
+            area_60_km2: severe.area_60_km2,
+            fraction_60: severe.fraction_60,
+            hail_probability: severe.hail_probability,
+            severe_core: severe.severe_core,
+            mean_dbz: stats.mean_dbz,
+            median_dbz: stats.median_dbz,
+            p90_dbz: stats.p90_dbz,
+            weighted_area: stats.weighted_area,
+            histogram: stats.histogram,
+            beam,
+            pixels: include_pixels.then(|| storm.pixels.iter().map(PixelReport::from).collect()),
+        }
+    }
+}
+
+// One complex, cell or core of the threshold ladder
+#[derive(Debug, Clone, Serialize)]
+pub struct CellReport {
+    pub cell_id: u32,
+    pub parent_id: Option<u32>,
+    pub children: Vec<u32>,
+    pub kind: CellKind,
+    pub level: usize,
+    pub threshold: u32,
+    pub center_x: i32,
+    pub center_y: i32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub distance_km: f64,
+    pub direction_deg: f64,
+    pub compass: String,
+    pub max_intensity: u32,
+    pub pixel_count: usize,
+    pub area_km2: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectionResult {
+    pub station: String,
+    pub station_code: String,
+    pub station_latitude: f64,
+    pub station_longitude: f64,
+    pub source: String,
+    pub frame_time: Option<DateTime<Utc>>,
+    pub processing_time: DateTime<Local>,
+    pub processing_duration_ms: f64,
+    // Scan elevation used by the beam model, from the image name or the configured default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elevation_deg: Option<f64>,
+    pub storm_count: usize,
+    // Echo pixels of the whole frame per palette level
+    pub histogram: Vec<LevelCount>,
+    pub storms: Vec<StormReport>,
+    // Every storm against every configured point of interest
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub poi: Vec<PoiProximity>,
+    // Storm footprints within every district of the districts file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub districts: Vec<DistrictSummary>,
+    // Complexes, cells and cores of the threshold ladder, parents before their children
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cells: Vec<CellReport>,
+}
+
+impl DetectionResult {
+    pub fn new(
+        source: &str,
+        storms: &[Storm],
+        motion: &[StormMotion],
+        reflectivity: &ReflectivityGrid,
+        processing_duration: Duration,
+        config: &Config,
+        include_pixels: bool,
+    ) -> Self {
+        let station = &config.station;
+        let elevation_deg = config
+            .beam
+            .enabled
+            .then(|| elevation_from_name(source, &config.beam).unwrap_or(config.beam.default_elevation_deg));
+        DetectionResult {
+            station: station.name.clone(),
+            station_code: station.code.clone(),
+            station_latitude: station.latitude,
+            station_longitude: station.longitude,
+            source: source.to_string(),
+            frame_time: parse_frame_time(source),
+            processing_time: Local::now(),
+            processing_duration_ms: processing_duration.as_secs_f64() * 1000.0,
+            elevation_deg,
+            storm_count: storms.len(),
+            histogram: frame_histogram(reflectivity, &config.palette),
+            storms: storms
+                .iter()
+                .enumerate()
+                .map(|(index, storm)| StormReport::from_storm(storm, config, motion.get(index), elevation_deg, include_pixels))
+                .collect(),
+            poi: proximity_report(storms, motion, &config.poi, station),
+            districts: Vec::new(),
+            cells: Vec::new(),
+        }
+    }
+
+    pub fn with_districts(mut self, storms: &[Storm], districts: &[District], km_per_pixel: f64) -> Self {
+        self.districts = district_summaries(storms, districts, km_per_pixel);
+        self
+    }
+
+    pub fn with_hierarchy(mut self, hierarchy: Option<&StormHierarchy>, station: &StationConfig) -> Self {
+        let Some(hierarchy) = hierarchy else {
+            return self;
+        };
+        self.cells = hierarchy
+            .cells
+            .iter()
+            .map(|cell| {
+                let (latitude, longitude) = pixel_to_lat_lon(cell.intensity_center.x as f64, cell.intensity_center.y as f64, station);
+                CellReport {
+                    cell_id: cell.cell_id,
+                    parent_id: cell.parent_id,
+                    children: cell.children.clone(),
+                    kind: cell.kind,
+                    level: cell.level,
+                    threshold: cell.threshold,
+                    center_x: cell.intensity_center.x,
+                    center_y: cell.intensity_center.y,
+                    latitude,
+                    longitude,
+                    distance_km: cell.distance,
+                    direction_deg: cell.direction,
+                    compass: azimuth_to_direction(cell.direction).to_string(),
+                    max_intensity: cell.max_intensity,
+                    pixel_count: cell.pixels.len(),
+                    area_km2: cell.pixels.len() as f64 * station.km_per_pixel * station.km_per_pixel,
+                }
+            })
+            .collect();
+        self
+    }
+}
+
+// Flat CSV row, frame fields are repeated on every storm and histograms are left out.
+// The csv writer cannot serialize flattened structs, so the StormReport fields are listed here.
+#[derive(Serialize)]
+struct StormCsvRow<'a> {
+    station: &'a str,
+    station_code: &'a str,
+    source: &'a str,
+    frame_time: Option<DateTime<Utc>>,
+    processing_time: DateTime<Local>,
+    processing_duration_ms: f64,
+    storm_id: u32,
+    track_id: Option<u32>,
+    speed_kmh: Option<f64>,
+    heading_deg: Option<f64>,
+    center_x: i32,
+    center_y: i32,
+    latitude: f64,
+    longitude: f64,
+    distance_km: f64,
+    direction_deg: f64,
+    compass: &'a str,
+    max_intensity: u32,
+    storm_type: &'a str,
+    pixel_count: usize,
+    mean_rain_rate_mm_h: f64,
+    max_rain_rate_mm_h: f64,
+    areal_rainfall_m3_h: f64,
+    area_55_km2: f64,
+    fraction_55: f64,
+    area_60_km2: f64,
+    fraction_60: f64,
+    hail_probability: f64,
+    severe_core: bool,
+    mean_dbz: f64,
+    median_dbz: u32,
+    p90_dbz: u32,
+    weighted_area: f64,
+    beam_height_km: Option<f64>,
+    slant_range_km: Option<f64>,
+    ground_range_km: Option<f64>,
+    beyond_reliable_range: Option<bool>,
+}
+
+impl<'a> From<(&'a DetectionResult, &'a StormReport)> for StormCsvRow<'a> {
+    fn from((result, storm): (&'a DetectionResult, &'a StormReport)) -> Self {
+        StormCsvRow {
+            station: &result.station,
+            station_code: &result.station_code,
+            source: &result.source,
+            frame_time: result.frame_time,
+            processing_time: result.processing_time,
+            processing_duration_ms: result.processing_duration_ms,
+            storm_id: storm.storm_id,
+            track_id: storm.track_id,
+            speed_kmh: storm.speed_kmh,
+            heading_deg: storm.heading_deg,
+            center_x: storm.center_x,
+            center_y: storm.center_y,
+            latitude: storm.latitude,
+            longitude: storm.longitude,
+            distance_km: storm.distance_km,
+            direction_deg: storm.direction_deg,
+            compass: &storm.compass,
+            max_intensity: storm.max_intensity,
+            storm_type: &storm.storm_type,
+            pixel_count: storm.pixel_count,
+            mean_rain_rate_mm_h: storm.mean_rain_rate_mm_h,
+            max_rain_rate_mm_h: storm.max_rain_rate_mm_h,
+            areal_rainfall_m3_h: storm.areal_rainfall_m3_h,
+            area_55_km2: storm.area_55_km2,
+            fraction_55: storm.fraction_55,
+            area_60_km2: storm.area_60_km2,
+            fraction_60: storm.fraction_60,
+            hail_probability: storm.hail_probability,
+            severe_core: storm.severe_core,
+            mean_dbz: storm.mean_dbz,
+            median_dbz: storm.median_dbz,
+            p90_dbz: storm.p90_dbz,
+            weighted_area: storm.weighted_area,
+            beam_height_km: storm.beam.map(|beam| beam.beam_height_km),
+            slant_range_km: storm.beam.map(|beam| beam.slant_range_km),
+            ground_range_km: storm.beam.map(|beam| beam.ground_range_km),
+            beyond_reliable_range: storm.beam.map(|beam| beam.beyond_reliable_range),
+        }
+    }
+}
+
+pub fn write_report<W: Write>(result: &DetectionResult, format: ReportFormat, writer: W) -> Result<(), Box<dyn Error>> {
+    match format {
+        ReportFormat::Json => write_json(result, writer),
+        ReportFormat::Ndjson => write_ndjson(std::slice::from_ref(result), writer),
+        ReportFormat::Csv => write_csv(std::slice::from_ref(result), writer, true),
+        ReportFormat::GeoJson => write_geojson(std::slice::from_ref(result), writer),
+    }
+}
+
+pub fn write_json<W: Write>(result: &DetectionResult, mut writer: W) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(&mut writer, result)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+// Several frames as one pretty printed json array
+pub fn write_json_array<W: Write>(results: &[DetectionResult], mut writer: W) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(&mut writer, results)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+pub fn write_ndjson<W: Write>(results: &[DetectionResult], mut writer: W) -> Result<(), Box<dyn Error>> {
+    for result in results {
+        serde_json::to_writer(&mut writer, result)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// The header can be left out when appending frames to an existing file
+pub fn write_csv<W: Write>(results: &[DetectionResult], writer: W, with_header: bool) -> Result<(), Box<dyn Error>> {
+    let mut csv_writer = csv::WriterBuilder::new().has_headers(with_header).from_writer(writer);
+    for result in results {
+        for storm in &result.storms {
+            csv_writer.serialize(StormCsvRow::from((result, storm)))?;
+        }
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+pub fn write_geojson<W: Write>(results: &[DetectionResult], mut writer: W) -> Result<(), Box<dyn Error>> {
+    let mut features = Vec::new();
+    for result in results {
+        for storm in &result.storms {
+            let mut properties = serde_json::to_value(storm)?;
+            if let Some(properties) = properties.as_object_mut() {
+                properties.remove("pixels");
+                properties.remove("latitude");
+                properties.remove("longitude");
+                properties.insert("station".to_string(), json!(result.station));
+                properties.insert("source".to_string(), json!(result.source));
+                properties.insert("frame_time".to_string(), json!(result.frame_time));
+            }
+            features.push(json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": [storm.longitude, storm.latitude] },
+                "properties": properties,
+            }));
+        }
+    }
+    let mut collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    if let Some(result) = results.first() {
+        collection["station"] = json!({
+            "name": result.station,
+            "code": result.station_code,
+            "coordinates": [result.station_longitude, result.station_latitude],
+        });
+    }
+    serde_json::to_writer_pretty(&mut writer, &collection)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block, storm_from_pixels};
+
+    const SOURCE: &str = "Z_RADR_I_Z9200_202404241348_P_DOR_SA_R_10_230_15.200.png";
+
+    fn result(source: &str, include_pixels: bool) -> DetectionResult {
+        let mut pixels = block(100, 100, 4, 3, 40);
+        pixels[5].2 = 55;
+        let storm = storm_from_pixels(1, &pixels);
+        DetectionResult::new(source, &[storm], &[], &ReflectivityGrid::new(10, 10), Duration::ZERO, &Config::default(), include_pixels)
+    }
+
+    #[test]
+    fn csv_has_a_header_and_one_row_per_storm() {
+        let mut output = Vec::new();
+        write_csv(&[result(SOURCE, true)], &mut output, true).unwrap();
+        let mut reader = csv::Reader::from_reader(output.as_slice());
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(&headers.iter().take(7).collect::<Vec<_>>(), &["station", "station_code", "source", "frame_time", "processing_time", "processing_duration_ms", "storm_id"]);
+        assert_eq!(headers.iter().next_back(), Some("beyond_reliable_range"));
+        assert!(!headers.iter().any(|header| header == "histogram" || header == "pixels"));
+
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 1);
+        let column = |name: &str| rows[0].get(headers.iter().position(|header| header == name).unwrap()).unwrap().to_string();
+        assert_eq!(column("source"), SOURCE);
+        assert_eq!(column("frame_time"), "2024-04-24T13:48:00Z");
+        assert_eq!((column("storm_id"), column("max_intensity"), column("pixel_count")), ("1".into(), "55".into(), "12".into()));
+
+        let mut appended = Vec::new();
+        write_csv(&[result(SOURCE, true)], &mut appended, false).unwrap();
+        assert_eq!(String::from_utf8(appended).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn ndjson_writes_one_line_per_frame() {
+        let mut output = Vec::new();
+        write_ndjson(&[result("first.png", false), result("second.png", false)], &mut output).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0]["source"].as_str(), lines[1]["source"].as_str()), (Some("first.png"), Some("second.png")));
+        assert_eq!(lines[1]["storms"][0]["storm_id"], 1);
+    }
+
+    #[test]
+    fn pixels_are_only_reported_on_request() {
+        let without: serde_json::Value = serde_json::to_value(result(SOURCE, false)).unwrap();
+        assert!(without["storms"][0].get("pixels").is_none());
+        let with: serde_json::Value = serde_json::to_value(result(SOURCE, true)).unwrap();
+        assert_eq!(with["storms"][0]["pixels"].as_array().map(Vec::len), Some(12));
+        assert_eq!(with["storms"][0]["pixels"][0], json!({ "x": 100, "y": 100, "intensity": 40 }));
+    }
+
+    #[test]
+    fn geojson_places_a_point_at_every_storm_center() {
+        let result = result(SOURCE, true);
+        let mut output = Vec::new();
+        write_geojson(std::slice::from_ref(&result), &mut output).unwrap();
+        let collection: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let feature = &collection["features"][0];
+        assert_eq!(feature["geometry"]["coordinates"], json!([result.storms[0].longitude, result.storms[0].latitude]));
+        assert_eq!(feature["properties"]["source"], SOURCE);
+        assert!(feature["properties"].get("pixels").is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use crate::storm::Storm;
+
+// Reflectivity levels of the fixed area statistics, 55 dBZ is the usual hail threshold and 60 dBZ marks large hail
+pub const HAIL_DBZ: u32 = 55;
+pub const LARGE_HAIL_DBZ: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SevereConfig {
+    // A storm has a severe core when at least severe_core_min_area_km2 reach severe_core_dbz
+    pub severe_core_dbz: u32,
+    pub severe_core_min_area_km2: f64,
+    // The hail probability proxy rises linearly from 0 to 1 between these maximum reflectivities
+    pub hail_probability_min_dbz: f64,
+    pub hail_probability_max_dbz: f64,
+}
+
+impl Default for SevereConfig {
+    fn default() -> Self {
+        SevereConfig {
+            severe_core_dbz: 55,
+            severe_core_min_area_km2: 10.0,
+            hail_probability_min_dbz: 45.0,
+            hail_probability_max_dbz: 65.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct SevereIndicators {
+    pub area_55_km2: f64,
+    pub fraction_55: f64,
+    pub area_60_km2: f64,
+    pub fraction_60: f64,
+    // Heuristic from the composite reflectivity maximum, not a calibrated probability
+    pub hail_probability: f64,
+    pub severe_core: bool,
+}
+
+pub fn severe_indicators(storm: &Storm, config: &SevereConfig, km_per_pixel: f64) -> SevereIndicators {
+    let pixel_area = km_per_pixel * km_per_pixel;
+    let total = storm.pixels.len();
+    let count_at = |dbz: u32| storm.pixels.iter().filter(|pixel| pixel.intensity >= dbz).count();
+    let fraction = |count: usize| if total > 0 { count as f64 / total as f64 } else { 0.0 };
+    let (count_55, count_60) = (count_at(HAIL_DBZ), count_at(LARGE_HAIL_DBZ));
+    let core_count = count_at(config.severe_core_dbz);
+
+    let span = config.hail_probability_max_dbz - config.hail_probability_min_dbz;
+    let hail_probability = if span > 0.0 {
+        ((storm.max_intensity as f64 - config.hail_probability_min_dbz) / span).clamp(0.0, 1.0)
+    } else if storm.max_intensity as f64 >= config.hail_probability_max_dbz {
+        1.0
+    } else {
+        0.0
+    };
+
+    SevereIndicators {
+        area_55_km2: count_55 as f64 * pixel_area,
+        fraction_55: fraction(count_55),
+        area_60_km2: count_60 as f64 * pixel_area,
+        fraction_60: fraction(count_60),
+        hail_probability,
+        severe_core: core_count > 0 && core_count as f64 * pixel_area >= config.severe_core_min_area_km2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::storm_from_pixels;
+
+    // One pixel per intensity along the top row
+    fn storm(intensities: &[u32]) -> Storm {
+        let pixels: Vec<(i32, i32, u32)> = intensities.iter().enumerate().map(|(x, &intensity)| (x as i32, 0, intensity)).collect();
+        storm_from_pixels(1, &pixels)
+    }
+
+    #[test]
+    fn hail_probability_ramps_over_the_maximum_reflectivity() {
+        let config = SevereConfig::default();
+        let cases = [(40, 0.0), (45, 0.0), (50, 0.25), (55, 0.5), (65, 1.0), (70, 1.0)];
+        for (max_intensity, expected) in cases {
+            let indicators = severe_indicators(&storm(&[max_intensity]), &config, 1.0);
+            assert!((indicators.hail_probability - expected).abs() < 1e-9, "{} dBZ: {}", max_intensity, indicators.hail_probability);
+        }
+
+        // Without a ramp the probability steps at the maximum
+        let step = SevereConfig { hail_probability_min_dbz: 60.0, hail_probability_max_dbz: 60.0, ..config };
+        assert_eq!(severe_indicators(&storm(&[55]), &step, 1.0).hail_probability, 0.0);
+        assert_eq!(severe_indicators(&storm(&[60]), &step, 1.0).hail_probability, 1.0);
+    }
+
+    #[test]
+    fn hail_areas_and_fractions_count_the_pixels_at_55_and_60_dbz() {
+        let indicators = severe_indicators(&storm(&[50, 55, 55, 60]), &SevereConfig::default(), 2.0);
+        assert_eq!(indicators.area_55_km2, 12.0);
+        assert_eq!(indicators.fraction_55, 0.75);
+        assert_eq!(indicators.area_60_km2, 4.0);
+        assert_eq!(indicators.fraction_60, 0.25);
+
+        let empty = severe_indicators(&storm(&[]), &SevereConfig::default(), 2.0);
+        assert_eq!((empty.area_55_km2, empty.fraction_55, empty.severe_core), (0.0, 0.0, false));
+    }
+
+    #[test]
+    fn severe_core_needs_its_minimum_area() {
+        let pixels = [50, 55, 55, 60];
+        // Three pixels of 4 km2 reach 55 dBZ
+        let config = SevereConfig { severe_core_min_area_km2: 12.0, ..SevereConfig::default() };
+        assert!(severe_indicators(&storm(&pixels), &config, 2.0).severe_core);
+        let config = SevereConfig { severe_core_min_area_km2: 12.5, ..SevereConfig::default() };
+        assert!(!severe_indicators(&storm(&pixels), &config, 2.0).severe_core);
+        let config = SevereConfig { severe_core_dbz: 65, severe_core_min_area_km2: 0.0, ..SevereConfig::default() };
+        assert!(!severe_indicators(&storm(&pixels), &config, 2.0).severe_core);
+    }
+}
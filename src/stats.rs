@@ -0,0 +1,118 @@
+use serde::Serialize;
+use crate::grid::ReflectivityGrid;
+use crate::pixel::{Palette, Pixel};
+
+// Pixel count of one palette level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LevelCount {
+    pub dbz: u32,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct IntensityStats {
+    // Every palette level in ascending order, levels without pixels included
+    pub histogram: Vec<LevelCount>,
+    // Taken over the dBZ values, not the linear reflectivity
+    pub mean_dbz: f64,
+    pub median_dbz: u32,
+    pub p90_dbz: u32,
+    // Sum of pixel area times dBZ, in km2 dBZ
+    pub weighted_area: f64,
+}
+
+pub fn level_histogram(intensities: impl Iterator<Item = u32>, palette: &Palette) -> Vec<LevelCount> {
+    let mut histogram: Vec<LevelCount> = palette.levels.iter().map(|level| LevelCount { dbz: level.dbz, count: 0 }).collect();
+    for intensity in intensities {
+        if let Some(level) = histogram.iter_mut().find(|level| level.dbz == intensity) {
+            level.count += 1;
+        }
+    }
+    histogram
+}
+
+// Histogram of every echo of the frame, storms or not
+pub fn frame_histogram(reflectivity: &ReflectivityGrid, palette: &Palette) -> Vec<LevelCount> {
+    let intensities = (0..reflectivity.height as i32)
+        .flat_map(|y| (0..reflectivity.width as i32).map(move |x| (x, y)))
+        .map(|(x, y)| reflectivity.get(x, y))
+        .filter(|&intensity| intensity > 0);
+    level_histogram(intensities, palette)
+}
+
+pub fn intensity_stats(pixels: &[Pixel], palette: &Palette, km_per_pixel: f64) -> IntensityStats {
+    let histogram = level_histogram(pixels.iter().map(|pixel| pixel.intensity), palette);
+    if pixels.is_empty() {
+        return IntensityStats {
+            histogram,
+            ..IntensityStats::default()
+        };
+    }
+    let mut intensities: Vec<u32> = pixels.iter().map(|pixel| pixel.intensity).collect();
+    intensities.sort_unstable();
+    let total: f64 = intensities.iter().map(|&intensity| intensity as f64).sum();
+    IntensityStats {
+        histogram,
+        mean_dbz: total / intensities.len() as f64,
+        median_dbz: percentile(&intensities, 0.5),
+        p90_dbz: percentile(&intensities, 0.9),
+        weighted_area: total * km_per_pixel * km_per_pixel,
+    }
+}
+
+// Nearest rank percentile of sorted values
+fn percentile(sorted: &[u32], fraction: f64) -> u32 {
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn pixels(intensities: &[u32]) -> Vec<Pixel> {
+        intensities
+            .iter()
+            .enumerate()
+            .map(|(x, &intensity)| Pixel { x: x as i32, y: 0, color: Rgba([0, 0, 0, 255]), intensity })
+            .collect()
+    }
+
+    #[test]
+    fn percentile_takes_the_nearest_rank() {
+        let sorted: Vec<u32> = (1..=10).map(|i| i * 10).collect();
+        let cases = [(0.0, 10), (0.1, 10), (0.15, 20), (0.5, 50), (0.9, 90), (0.95, 100), (1.0, 100)];
+        for (fraction, expected) in cases {
+            assert_eq!(percentile(&sorted, fraction), expected, "percentile {}", fraction);
+        }
+        assert_eq!(percentile(&[35], 0.9), 35);
+    }
+
+    #[test]
+    fn storm_stats_from_known_intensities() {
+        let stats = intensity_stats(&pixels(&[60, 40, 30, 50, 40]), &Palette::default(), 2.0);
+        assert_eq!(stats.mean_dbz, 44.0);
+        assert_eq!(stats.median_dbz, 40);
+        assert_eq!(stats.p90_dbz, 60);
+        // 220 dBZ over pixels of 4 km2
+        assert_eq!(stats.weighted_area, 880.0);
+
+        let count = |dbz: u32| stats.histogram.iter().find(|level| level.dbz == dbz).map(|level| level.count);
+        assert_eq!(stats.histogram.len(), Palette::default().levels.len());
+        assert_eq!((count(30), count(35), count(40), count(60)), (Some(1), Some(0), Some(2), Some(1)));
+    }
+
+    #[test]
+    fn empty_storm_keeps_the_empty_histogram() {
+        let stats = intensity_stats(&[], &Palette::default(), 2.0);
+        assert_eq!((stats.mean_dbz, stats.median_dbz, stats.weighted_area), (0.0, 0, 0.0));
+        assert!(stats.histogram.iter().all(|level| level.count == 0));
+    }
+
+    #[test]
+    fn intensities_off_the_palette_are_not_counted() {
+        let histogram = level_histogram([40, 42, 40].into_iter(), &Palette::default());
+        assert_eq!(histogram.iter().map(|level| level.count).sum::<usize>(), 2);
+    }
+}
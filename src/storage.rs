@@ -0,0 +1,409 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use crate::config::Config;
+use crate::pipeline::Detection;
+#[cfg(feature = "sqlite")]
+use {
+    crate::geo::{pixel_to_lat_lon, storm_footprint},
+    crate::severe::severe_indicators,
+    crate::stats::intensity_stats,
+    crate::precipitation::storm_rainfall,
+    crate::utils::parse_frame_time,
+    chrono::{DateTime, NaiveDate, Utc},
+    rusqlite::{params, Connection, OptionalExtension},
+    serde_json::json,
+    sha2::{Digest, Sha256},
+};
+
+// Schema of the database, created on open. Times are UTC text as yyyy-mm-ddThh:mm:ssZ, so they sort and compare as text
+// and the day of a frame is substr(frame_time, 1, 10).
+//
+// frames: one row per processed image; reprocessing the same image file replaces its row and storms
+//   station_code, station_name   station of the configuration the frame was processed with
+//   frame_time                   scan time from the image name, NULL when the name has none
+//   source                       canonical absolute path of the image, so ./data/x.png and data/x.png are one frame
+//   sha256                       hex digest of the image file
+//   processing_ms                detection time
+//   processed_at                 time the row was written
+//   storm_count
+// storms: one row per storm of a frame
+//   frame_id                     frames.id, storms are deleted with their frame
+//   storm_id, track_id           id within the frame, track across frames when the run tracked storms
+//   storm_type, max_intensity (dBZ), pixel_count, area_km2
+//   center_x, center_y           intensity center in image pixels
+//   latitude, longitude          of the intensity center
+//   distance_km, direction_deg   from the radar
+//   mean_dbz, area_55_km2, hail_probability, severe_core, mean_rain_rate_mm_h, areal_rainfall_m3_h
+//   speed_kmh, heading_deg       motion of tracked storms, NULL otherwise
+//   outline                      GeoJSON Polygon of the convex hull of the footprint, [longitude, latitude]
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS frames (
+    id INTEGER PRIMARY KEY,
+    station_code TEXT NOT NULL,
+    station_name TEXT NOT NULL,
+    frame_time TEXT,
+    source TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    processing_ms REAL NOT NULL,
+    processed_at TEXT NOT NULL,
+    storm_count INTEGER NOT NULL,
+    UNIQUE (station_code, source)
+);
+CREATE INDEX IF NOT EXISTS frames_station_time ON frames (station_code, frame_time);
+CREATE TABLE IF NOT EXISTS storms (
+    id INTEGER PRIMARY KEY,
+    frame_id INTEGER NOT NULL REFERENCES frames (id) ON DELETE CASCADE,
+    storm_id INTEGER NOT NULL,
+    track_id INTEGER,
+    storm_type TEXT NOT NULL,
+    max_intensity INTEGER NOT NULL,
+    pixel_count INTEGER NOT NULL,
+    area_km2 REAL NOT NULL,
+    center_x INTEGER NOT NULL,
+    center_y INTEGER NOT NULL,
+    latitude REAL NOT NULL,
+    longitude REAL NOT NULL,
+    distance_km REAL NOT NULL,
+    direction_deg REAL NOT NULL,
+    mean_dbz REAL NOT NULL,
+    area_55_km2 REAL NOT NULL,
+    hail_probability REAL NOT NULL,
+    severe_core INTEGER NOT NULL,
+    mean_rain_rate_mm_h REAL NOT NULL,
+    areal_rainfall_m3_h REAL NOT NULL,
+    speed_kmh REAL,
+    heading_deg REAL,
+    outline TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS storms_frame ON storms (frame_id);
+";
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageConfig {
+    // SQLite database file, empty for no storage. Needs a build with the sqlite feature
+    pub database: String,
+}
+
+// Storm row joined with its frame
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredStorm {
+    pub frame_time: Option<DateTime<Utc>>,
+    pub source: String,
+    pub storm_id: u32,
+    pub track_id: Option<u32>,
+    pub storm_type: String,
+    pub max_intensity: u32,
+    pub area_km2: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub distance_km: f64,
+    pub direction_deg: f64,
+    pub outline: String,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyMaximum {
+    pub day: NaiveDate,
+    pub frame_count: usize,
+    // Strongest storm of the day, the larger one on equal intensity
+    pub strongest: StoredStorm,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct Storage {
+    connection: Connection,
+}
+
+// Without the sqlite feature no storage can be opened, see Storage::from_config
+#[cfg(not(feature = "sqlite"))]
+#[derive(Debug)]
+pub enum Storage {}
+
+#[cfg(not(feature = "sqlite"))]
+impl Storage {
+    pub fn from_config(config: &Config) -> Result<Option<Storage>, Box<dyn Error>> {
+        if config.storage.database.is_empty() {
+            return Ok(None);
+        }
+        Err("storage.database needs a build with the sqlite feature".into())
+    }
+
+    pub fn store_detection(&mut self, _detection: &Detection, _config: &Config) -> Result<i64, Box<dyn Error>> {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Storage {
+    // The configured database, None when storage is off
+    pub fn from_config(config: &Config) -> Result<Option<Storage>, Box<dyn Error>> {
+        if config.storage.database.is_empty() {
+            return Ok(None);
+        }
+        Storage::open(&config.storage.database).map(Some)
+    }
+
+    pub fn open(path: &str) -> Result<Storage, Box<dyn Error>> {
+        let connection = Connection::open(path).map_err(|e| format!("Failed to open database {}: {}", path, e))?;
+        Storage::with_connection(connection)
+    }
+
+    pub fn open_in_memory() -> Result<Storage, Box<dyn Error>> {
+        Storage::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Storage, Box<dyn Error>> {
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Storage { connection })
+    }
+
+    // Write the frame and its storms, hashing the image file, and return the frame id
+    pub fn store_detection(&mut self, detection: &Detection, config: &Config) -> Result<i64, Box<dyn Error>> {
+        let image = std::fs::read(&detection.source).map_err(|e| format!("Failed to read {}: {}", detection.source, e))?;
+        let source = std::fs::canonicalize(&detection.source).map_err(|e| format!("Failed to resolve {}: {}", detection.source, e))?;
+        let source = source.to_string_lossy();
+        let sha256: String = Sha256::digest(&image).iter().map(|byte| format!("{:02x}", byte)).collect();
+        let station = &config.station;
+        let km_per_pixel = station.km_per_pixel;
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM frames WHERE station_code = ?1 AND source = ?2", params![station.code, source])?;
+        transaction.execute(
+            "INSERT INTO frames (station_code, station_name, frame_time, source, sha256, processing_ms, processed_at, storm_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                station.code,
+                station.name,
+                parse_frame_time(&detection.source).map(db_time),
+                source,
+                sha256,
+                detection.duration.as_secs_f64() * 1000.0,
+                db_time(Utc::now()),
+                detection.storms.len() as i64,
+            ],
+        )?;
+        let frame_id = transaction.last_insert_rowid();
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO storms (frame_id, storm_id, track_id, storm_type, max_intensity, pixel_count, area_km2, center_x, center_y,
+                 latitude, longitude, distance_km, direction_deg, mean_dbz, area_55_km2, hail_probability, severe_core,
+                 mean_rain_rate_mm_h, areal_rainfall_m3_h, speed_kmh, heading_deg, outline)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
+            )?;
+            for (index, storm) in detection.storms.iter().enumerate() {
+                let motion = detection.motion.get(index);
+                let center = storm.intensity_center;
+                let (latitude, longitude) = pixel_to_lat_lon(center.x as f64, center.y as f64, station);
+                let severe = severe_indicators(storm, &config.severe, km_per_pixel);
+                let stats = intensity_stats(&storm.pixels, &config.palette, km_per_pixel);
+                let rainfall = storm_rainfall(storm, &config.precipitation, km_per_pixel);
+                let ring: Vec<[f64; 2]> = storm_footprint(storm, station).into_iter().map(|(latitude, longitude)| [longitude, latitude]).collect();
+                let outline = json!({ "type": "Polygon", "coordinates": [ring] }).to_string();
+                insert.execute(params![
+                    frame_id,
+                    storm.storm_id,
+                    motion.map(|motion| motion.track_id),
+                    storm.storm_type,
+                    storm.max_intensity,
+                    storm.pixels.len() as i64,
+                    storm.pixels.len() as f64 * km_per_pixel * km_per_pixel,
+                    center.x,
+                    center.y,
+                    latitude,
+                    longitude,
+                    storm.distance,
+                    storm.direction,
+                    stats.mean_dbz,
+                    severe.area_55_km2,
+                    severe.hail_probability,
+                    severe.severe_core,
+                    rainfall.mean_rain_rate_mm_h,
+                    rainfall.areal_rainfall_m3_h,
+                    motion.and_then(|motion| motion.speed_kmh(km_per_pixel)),
+                    motion.and_then(|motion| motion.heading_deg()),
+                    outline,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(frame_id)
+    }
+
+    // Storms of the station with a frame time in [start, end), in time order
+    pub fn storms_between(&self, station_code: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<StoredStorm>, Box<dyn Error>> {
+        let mut query = self.connection.prepare(&format!(
+            "{} WHERE f.station_code = ?1 AND f.frame_time >= ?2 AND f.frame_time < ?3 ORDER BY f.frame_time, s.storm_id",
+            STORED_STORM_SELECT
+        ))?;
+        let rows = query.query_map(params![station_code, db_time(start), db_time(end)], stored_storm)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    // Strongest storm of every day with frames of the station, by UTC day
+    pub fn strongest_per_day(&self, station_code: &str) -> Result<Vec<DailyMaximum>, Box<dyn Error>> {
+        let mut days = self.connection.prepare(
+            "SELECT substr(frame_time, 1, 10), COUNT(*) FROM frames
+             WHERE station_code = ?1 AND frame_time IS NOT NULL GROUP BY 1 ORDER BY 1",
+        )?;
+        let days: Vec<(String, i64)> = days.query_map(params![station_code], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+        let mut strongest = self.connection.prepare(&format!(
+            "{} WHERE f.station_code = ?1 AND substr(f.frame_time, 1, 10) = ?2
+             ORDER BY s.max_intensity DESC, s.area_km2 DESC, f.frame_time LIMIT 1",
+            STORED_STORM_SELECT
+        ))?;
+
+        let mut maxima = Vec::new();
+        for (day, frame_count) in days {
+            let Some(storm) = strongest.query_row(params![station_code, day], stored_storm).optional()? else {
+                continue;
+            };
+            maxima.push(DailyMaximum {
+                day: NaiveDate::parse_from_str(&day, "%Y-%m-%d")?,
+                frame_count: frame_count as usize,
+                strongest: storm,
+            });
+        }
+        Ok(maxima)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+const STORED_STORM_SELECT: &str = "SELECT f.frame_time, f.source, s.storm_id, s.track_id, s.storm_type, s.max_intensity, s.area_km2,
+    s.latitude, s.longitude, s.distance_km, s.direction_deg, s.outline FROM storms s JOIN frames f ON f.id = s.frame_id";
+
+#[cfg(feature = "sqlite")]
+fn stored_storm(row: &rusqlite::Row) -> rusqlite::Result<StoredStorm> {
+    let frame_time: Option<String> = row.get(0)?;
+    Ok(StoredStorm {
+        frame_time: frame_time.and_then(|time| DateTime::parse_from_rfc3339(&time).ok()).map(|time| time.with_timezone(&Utc)),
+        source: row.get(1)?,
+        storm_id: row.get(2)?,
+        track_id: row.get(3)?,
+        storm_type: row.get(4)?,
+        max_intensity: row.get(5)?,
+        area_km2: row.get(6)?,
+        latitude: row.get(7)?,
+        longitude: row.get(8)?,
+        distance_km: row.get(9)?,
+        direction_deg: row.get(10)?,
+        outline: row.get(11)?,
+    })
+}
+
+#[cfg(feature = "sqlite")]
+fn db_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::storm::Storm;
+    use crate::test_support::{block, detection_of, storm_from_pixels};
+    use chrono::TimeZone;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // Scratch directory of one test, store_detection hashes and resolves real files
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("storm_detect_storage_{}_{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn frame_file(dir: &Path, time: &str) -> String {
+        let path = dir.join(format!("Z_RADR_I_Z9200_{}_P_DOR_SA_R_10_230_15.200.png", time));
+        fs::write(&path, time).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // A row of size pixels from (310, 310), every pixel at max_intensity
+    fn storm(storm_id: u32, max_intensity: u32, size: i32) -> Storm {
+        storm_from_pixels(storm_id, &block(310, 310, size, 1, max_intensity))
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 4, day, hour, minute, 0).unwrap()
+    }
+
+    fn count(storage: &Storage, table: &str) -> i64 {
+        storage.connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn reprocessing_an_image_replaces_its_frame() {
+        let dir = scratch("replace");
+        let config = Config::default();
+        let mut storage = Storage::open_in_memory().unwrap();
+        let source = frame_file(&dir, "202404241348");
+        storage.store_detection(&detection_of(&source, vec![storm(1, 50, 3), storm(2, 55, 3)], Vec::new()), &config).unwrap();
+
+        // The same file under another spelling of its path
+        let respelled = dir.join(".").join(Path::new(&source).file_name().unwrap());
+        storage.store_detection(&detection_of(respelled.to_str().unwrap(), vec![storm(1, 60, 3)], Vec::new()), &config).unwrap();
+        assert_eq!((count(&storage, "frames"), count(&storage, "storms")), (1, 1));
+        let storms = storage.storms_between(&config.station.code, at(24, 0, 0), at(25, 0, 0)).unwrap();
+        assert_eq!(storms[0].max_intensity, 60);
+        assert_eq!(storms[0].source, fs::canonicalize(&source).unwrap().to_string_lossy());
+
+        // Another file is another frame
+        storage.store_detection(&detection_of(&frame_file(&dir, "202404241354"), vec![storm(1, 45, 3)], Vec::new()), &config).unwrap();
+        assert_eq!((count(&storage, "frames"), count(&storage, "storms")), (2, 2));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn storms_between_includes_the_start_and_excludes_the_end() {
+        let dir = scratch("between");
+        let config = Config::default();
+        let mut storage = Storage::open_in_memory().unwrap();
+        for (time, max_intensity) in [("202404241354", 50), ("202404241348", 45), ("202404241400", 55)] {
+            storage.store_detection(&detection_of(&frame_file(&dir, time), vec![storm(1, max_intensity, 3)], Vec::new()), &config).unwrap();
+        }
+
+        let storms = storage.storms_between(&config.station.code, at(24, 13, 48), at(24, 14, 0)).unwrap();
+        let found: Vec<(Option<DateTime<Utc>>, u32)> = storms.iter().map(|storm| (storm.frame_time, storm.max_intensity)).collect();
+        assert_eq!(found, [(Some(at(24, 13, 48)), 45), (Some(at(24, 13, 54)), 50)]);
+        assert!(storage.storms_between("Z9999", at(24, 0, 0), at(25, 0, 0)).unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn strongest_per_day_picks_the_maximum_then_the_larger_storm() {
+        let dir = scratch("daily");
+        let config = Config::default();
+        let mut storage = Storage::open_in_memory().unwrap();
+        let frames = [
+            ("202404232350", vec![storm(1, 60, 3)]),
+            ("202404240010", vec![storm(1, 50, 3), storm(2, 60, 3)]),
+            ("202404240020", vec![storm(1, 60, 8)]),
+            ("202404241200", vec![storm(1, 45, 20)]),
+        ];
+        for (time, storms) in frames {
+            storage.store_detection(&detection_of(&frame_file(&dir, time), storms, Vec::new()), &config).unwrap();
+        }
+        // Frames without a time in their name belong to no day
+        let untimed = dir.join("untimed.png");
+        fs::write(&untimed, "untimed").unwrap();
+        storage.store_detection(&detection_of(untimed.to_str().unwrap(), vec![storm(1, 70, 3)], Vec::new()), &config).unwrap();
+
+        let maxima = storage.strongest_per_day(&config.station.code).unwrap();
+        let days: Vec<(NaiveDate, usize, u32, Option<DateTime<Utc>>)> = maxima
+            .iter()
+            .map(|daily| (daily.day, daily.frame_count, daily.strongest.max_intensity, daily.strongest.frame_time))
+            .collect();
+        assert_eq!(
+            days,
+            [
+                (NaiveDate::from_ymd_opt(2024, 4, 23).unwrap(), 1, 60, Some(at(23, 23, 50))),
+                (NaiveDate::from_ymd_opt(2024, 4, 24).unwrap(), 3, 60, Some(at(24, 0, 20))),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
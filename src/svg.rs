@@ -0,0 +1,401 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::{ImageOutputFormat, Rgba, RgbaImage};
+use nalgebra::Point2;
+use rusttype::Scale;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::io::Cursor;
+use crate::consts::*;
+use crate::labels::place_labels;
+use crate::reference::{reference_grid, ReferenceGrid};
+use crate::render::{rgba, RenderStyle, FONT};
+use crate::ellipse::fit_ellipse;
+use crate::storm::{has_major_core, DetectionParams, Storm};
+
+// Image shown underneath the vector layers
+#[derive(Debug, Clone, Copy)]
+pub enum SvgBackground<'a> {
+    // PNG encoded into a data uri, the file is self-contained
+    Embedded(&'a RgbaImage),
+    // Referenced by href, which viewers resolve relative to the svg file. The plain base image is shown,
+    // without the legend panel composed for the embedded background
+    Linked(&'a str),
+    None,
+}
+
+// Every layer carries a class, so pages embedding the svg can restyle it with their own css.
+// Colors and widths of the render style are presentation attributes, which such css overrides.
+const SVG_STYLE: &str = ".storm-outline { fill-opacity: 0.15; }
+.storm-ellipse { fill: none; }
+.storm-label, .reference-label { font-family: 'DejaVu Sans', sans-serif; }
+.reference-label { text-anchor: middle; dominant-baseline: central; }
+.range-ring { fill: none; }";
+
+pub fn render_svg(
+    storms: &[Storm],
+    radar_center: &Point2<f64>,
+    radar_area: (u32, u32),
+    params: &DetectionParams,
+    style: &RenderStyle,
+    size: (u32, u32),
+    background: SvgBackground,
+) -> Result<String, Box<dyn Error>> {
+    let (width, height) = size;
+    let mut svg = String::new();
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height
+    )?;
+    writeln!(svg, "<style>\n{}\n</style>", SVG_STYLE)?;
+
+    let href = match background {
+        SvgBackground::Embedded(img) => {
+            let mut png = Vec::new();
+            img.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+            Some(format!("data:image/png;base64,{}", STANDARD.encode(&png)))
+        }
+        SvgBackground::Linked(path) => Some(escape_xml(path)),
+        SvgBackground::None => None,
+    };
+    if let Some(href) = href {
+        writeln!(
+            svg,
+            r#"<image id="base" x="0" y="0" width="{}" height="{}" href="{}" xlink:href="{}"/>"#,
+            width, height, href, href
+        )?;
+    }
+
+    // Pixel (x, y) covers the unit square from (x, y) to (x + 1, y + 1), points sit at the square centers
+    let (center_x, center_y) = (radar_center.x + 0.5, radar_center.y + 0.5);
+    let grid = reference_grid((radar_center.x as f32, radar_center.y as f32), radar_area, params.km_per_pixel, style);
+    write_reference_grid(&mut svg, &grid, style)?;
+
+    writeln!(svg, r#"<g id="storms">"#)?;
+    for storm in storms {
+        let storm_x = storm.intensity_center.x as f64 + 0.5;
+        let storm_y = storm.intensity_center.y as f64 + 0.5;
+        writeln!(
+            svg,
+            r#"<g id="storm-{}" class="storm" data-max-intensity="{}" data-type="{}">"#,
+            storm.storm_id,
+            storm.max_intensity,
+            escape_xml(&storm.storm_type)
+        )?;
+        if style.layers.echoes {
+            writeln!(
+                svg,
+                r#"<path class="storm-outline" fill="{c}" stroke="{c}" stroke-width="1" fill-rule="evenodd" d="{d}"/>"#,
+                c = svg_color(&storm_color(storm)),
+                d = outline_path(storm)
+            )?;
+        }
+
+        if style.layers.radial_lines {
+            writeln!(
+                svg,
+                r#"<line class="radial-line" stroke="{}" stroke-width="{}" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/>"#,
+                svg_color(&rgba(&style.line_color)),
+                style.line_width,
+                center_x,
+                center_y,
+                storm_x,
+                storm_y
+            )?;
+        }
+
+        if style.layers.ellipses && has_major_core(storm, params) {
+            let ellipse = fit_ellipse(storm, params.ellipse_method, params.core_dbz);
+            let (ellipse_x, ellipse_y) = (ellipse.center.x + 0.5, ellipse.center.y + 0.5);
+            writeln!(
+                svg,
+                r#"<ellipse class="storm-ellipse" stroke="{}" stroke-width="{}" cx="{:.2}" cy="{:.2}" rx="{:.2}" ry="{:.2}" transform="rotate({:.2} {:.2} {:.2})"/>"#,
+                svg_color(&style.ellipse_color_of(&storm.storm_type)),
+                style.ellipse_width,
+                ellipse_x,
+                ellipse_y,
+                ellipse.major_axis,
+                ellipse.minor_axis,
+                ellipse.angle.to_degrees(),
+                ellipse_x,
+                ellipse_y
+            )?;
+        }
+
+        if style.layers.centers {
+            writeln!(
+                svg,
+                r#"<circle class="storm-center" fill="{}" cx="{:.1}" cy="{:.1}" r="{}"/>"#,
+                svg_color(&rgba(&style.center_color)),
+                storm_x,
+                storm_y,
+                style.center_radius
+            )?;
+        }
+        writeln!(svg, "</g>")?;
+    }
+    writeln!(svg, "</g>")?;
+
+    if style.layers.labels {
+        write_labels(&mut svg, storms, radar_area, params, style)?;
+    }
+    writeln!(svg, "</svg>")?;
+    Ok(svg)
+}
+
+fn write_reference_grid(svg: &mut String, grid: &ReferenceGrid, style: &RenderStyle) -> Result<(), Box<dyn Error>> {
+    if grid.rings.is_empty() && grid.spokes.is_empty() && grid.scale_bar.is_none() {
+        return Ok(());
+    }
+    let color = svg_color(&rgba(&style.grid_color));
+    let (center_x, center_y) = (grid.center.0 + 0.5, grid.center.1 + 0.5);
+    let mut labels: Vec<(&str, &str, (f32, f32))> = Vec::new();
+    writeln!(svg, r#"<g id="reference" stroke="{}" stroke-width="{}">"#, color, style.grid_width)?;
+    for ring in &grid.rings {
+        writeln!(
+            svg,
+            r#"<circle class="range-ring" data-km="{}" cx="{:.1}" cy="{:.1}" r="{:.2}"/>"#,
+            ring.distance_km, center_x, center_y, ring.radius
+        )?;
+        labels.push(("range-label", &ring.label, ring.label_center));
+    }
+    for spoke in &grid.spokes {
+        writeln!(
+            svg,
+            r#"<line class="azimuth-spoke" data-azimuth="{}" x1="{:.1}" y1="{:.1}" x2="{:.2}" y2="{:.2}"/>"#,
+            spoke.azimuth,
+            center_x,
+            center_y,
+            spoke.end.0 + 0.5,
+            spoke.end.1 + 0.5
+        )?;
+        labels.push(("azimuth-label", &spoke.label, spoke.label_center));
+    }
+    if let Some(bar) = &grid.scale_bar {
+        let (x1, x2, y) = (bar.start.0 + 0.5, bar.end.0 + 0.5, bar.start.1 + 0.5);
+        writeln!(
+            svg,
+            r#"<path class="scale-bar" fill="none" d="M{x1:.2} {top:.2} L{x1:.2} {bottom:.2} M{x1:.2} {y:.2} L{x2:.2} {y:.2} M{x2:.2} {top:.2} L{x2:.2} {bottom:.2}"/>"#,
+            x1 = x1,
+            x2 = x2,
+            y = y,
+            top = y - bar.tick,
+            bottom = y + bar.tick
+        )?;
+        labels.push(("scale-label", &bar.label, bar.label_center));
+    }
+
+    let halo = if style.label_halo {
+        format!(r#" stroke="{}" stroke-width="2" paint-order="stroke""#, svg_color(&rgba(&style.label_halo_color)))
+    } else {
+        String::from(r#" stroke="none""#)
+    };
+    for (class, text, (x, y)) in labels {
+        writeln!(
+            svg,
+            r#"<text class="reference-label {}" fill="{}"{} font-size="{}" x="{:.1}" y="{:.1}">{}</text>"#,
+            class,
+            color,
+            halo,
+            style.grid_font_size,
+            x + 0.5,
+            y + 0.5,
+            escape_xml(text)
+        )?;
+    }
+    writeln!(svg, "</g>")?;
+    Ok(())
+}
+
+// Same placement as the raster labels, text y is the baseline in svg
+fn write_labels(
+    svg: &mut String,
+    storms: &[Storm],
+    radar_area: (u32, u32),
+    params: &DetectionParams,
+    style: &RenderStyle,
+) -> Result<(), Box<dyn Error>> {
+    let ascent = FONT.v_metrics(Scale::uniform(style.label_font_size)).ascent;
+    let color = svg_color(&rgba(&style.label_color));
+    let halo_color = svg_color(&rgba(&style.label_halo_color));
+    writeln!(svg, r#"<g id="labels">"#)?;
+    for label in place_labels(storms, params, style, radar_area) {
+        let text_box = &label.text_box;
+        if style.label_background {
+            writeln!(
+                svg,
+                r#"<rect class="label-background" fill="{}" fill-opacity="{}" x="{}" y="{}" width="{}" height="{}"/>"#,
+                halo_color, style.label_background_opacity, text_box.x, text_box.y, text_box.width, text_box.height
+            )?;
+        }
+        if label.leader {
+            let (end_x, end_y) = text_box.nearest_point(label.anchor);
+            writeln!(
+                svg,
+                r#"<line class="label-leader" stroke="{}" stroke-width="1" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/>"#,
+                color,
+                label.anchor.0 + 0.5,
+                label.anchor.1 + 0.5,
+                end_x,
+                end_y
+            )?;
+        }
+        let halo = if style.label_halo {
+            format!(r#" stroke="{}" stroke-width="2" paint-order="stroke""#, halo_color)
+        } else {
+            String::new()
+        };
+        writeln!(
+            svg,
+            r#"<text class="storm-label" fill="{}"{} font-size="{}" x="{}" y="{:.1}">{}</text>"#,
+            color,
+            halo,
+            style.label_font_size,
+            label.x,
+            label.y as f32 + ascent,
+            escape_xml(&label.text)
+        )?;
+    }
+    writeln!(svg, "</g>")?;
+    Ok(())
+}
+
+// Palette color of the strongest pixel of the storm
+fn storm_color(storm: &Storm) -> Rgba<u8> {
+    storm
+        .pixels
+        .iter()
+        .max_by_key(|pixel| pixel.intensity)
+        .map(|pixel| pixel.color)
+        .unwrap_or(ELLIPSE_COLOR)
+}
+
+// Boundary of the storm pixels as closed loops along the pixel edges, holes included.
+// Every boundary edge is used exactly once, so the even-odd fill rule reproduces the pixel set.
+fn outline_path(storm: &Storm) -> String {
+    let cells: HashSet<(i32, i32)> = storm.pixels.iter().map(|pixel| (pixel.x, pixel.y)).collect();
+    let mut edges: BTreeMap<(i32, i32), Vec<(i32, i32)>> = BTreeMap::new();
+    for &(x, y) in &cells {
+        let sides = [
+            ((0, -1), (x, y), (x + 1, y)),
+            ((1, 0), (x + 1, y), (x + 1, y + 1)),
+            ((0, 1), (x + 1, y + 1), (x, y + 1)),
+            ((-1, 0), (x, y + 1), (x, y)),
+        ];
+        for ((dx, dy), start, end) in sides {
+            if !cells.contains(&(x + dx, y + dy)) {
+                edges.entry(start).or_default().push(end);
+            }
+        }
+    }
+    for ends in edges.values_mut() {
+        ends.sort_unstable();
+    }
+
+    let mut path = String::new();
+    while let Some((&start, _)) = edges.iter().find(|(_, ends)| !ends.is_empty()) {
+        let mut corners = vec![start];
+        let mut current = start;
+        while let Some(next) = edges.get_mut(&current).and_then(|ends| ends.pop()) {
+            current = next;
+            if current == start {
+                break;
+            }
+            corners.push(current);
+        }
+        let corners = drop_collinear(&corners);
+        for (i, (x, y)) in corners.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            let _ = write!(path, "{}{} {} ", command, x, y);
+        }
+        path.push('Z');
+    }
+    path
+}
+
+// Corners of a closed loop without the points lying on a straight run
+fn drop_collinear(corners: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let count = corners.len();
+    if count < 3 {
+        return corners.to_vec();
+    }
+    (0..count)
+        .filter(|&i| {
+            let (px, py) = corners[(i + count - 1) % count];
+            let (x, y) = corners[i];
+            let (nx, ny) = corners[(i + 1) % count];
+            (x - px) * (ny - y) - (y - py) * (nx - x) != 0
+        })
+        .map(|i| corners[i])
+        .collect()
+}
+
+fn svg_color(color: &Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block, storm_from_pixels};
+
+    fn svg(storms: &[Storm], background: SvgBackground) -> String {
+        render_svg(storms, &Point2::new(50.0, 50.0), (100, 100), &DetectionParams::default(), &RenderStyle::default(), (100, 100), background).unwrap()
+    }
+
+    fn points(subpath: &str) -> usize {
+        subpath.matches(['M', 'L']).count()
+    }
+
+    #[test]
+    fn storm_with_a_hole_is_outlined_by_two_even_odd_subpaths() {
+        let mut ring = block(10, 10, 3, 3, 40);
+        ring.retain(|&(x, y, _)| (x, y) != (11, 11));
+        let storm = storm_from_pixels(1, &ring);
+        let path = outline_path(&storm);
+        let subpaths: Vec<&str> = path.split_terminator('Z').collect();
+        assert_eq!(subpaths.len(), 2);
+        assert!(subpaths.iter().all(|subpath| points(subpath) == 4), "{}", path);
+        assert!(svg(&[storm], SvgBackground::None).contains(&format!(r#"fill-rule="evenodd" d="{}""#, path)));
+    }
+
+    #[test]
+    fn collinear_corners_are_dropped() {
+        let path = outline_path(&storm_from_pixels(1, &block(10, 10, 3, 1, 40)));
+        assert_eq!(points(&path), 4, "{}", path);
+        for corner in ["10 10", "13 10", "13 11", "10 11"] {
+            assert!(path.contains(corner), "{} in {}", corner, path);
+        }
+        assert_eq!(drop_collinear(&[(0, 0), (1, 0), (2, 0), (2, 1), (0, 1)]), [(0, 0), (2, 0), (2, 1), (0, 1)]);
+    }
+
+    #[test]
+    fn background_is_embedded_or_linked() {
+        let img = RgbaImage::new(4, 4);
+        assert!(svg(&[], SvgBackground::Embedded(&img)).contains(r#"href="data:image/png;base64,"#));
+        let linked = svg(&[], SvgBackground::Linked("../base/base.png"));
+        assert!(linked.contains(r#"href="../base/base.png" xlink:href="../base/base.png""#));
+        assert!(!svg(&[], SvgBackground::None).contains("<image"));
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(escape_xml(r#"<a & "b">"#), "&lt;a &amp; &quot;b&quot;&gt;");
+        let storm = Storm { storm_type: String::from("cell <a&b>"), ..storm_from_pixels(7, &block(40, 40, 2, 2, 40)) };
+        let svg = svg(&[storm], SvgBackground::Linked("a&b.png"));
+        assert!(svg.contains(r#"data-type="cell &lt;a&amp;b&gt;""#));
+        assert!(svg.contains(r#"href="a&amp;b.png""#));
+        assert!(svg.contains(">#7</text>"));
+    }
+}
@@ -0,0 +1,38 @@
+// Builders shared by the unit tests
+use image::Rgba;
+use nalgebra::Point2;
+use crate::grid::ReflectivityGrid;
+use crate::pipeline::Detection;
+use crate::pixel::Pixel;
+use crate::storm::Storm;
+use crate::tracking::StormMotion;
+
+// Storm of the given (x, y, dBZ) echoes, centered on the first one and as strong as the strongest one
+pub fn storm_from_pixels(storm_id: u32, pixels: &[(i32, i32, u32)]) -> Storm {
+    Storm {
+        storm_id,
+        intensity_center: pixels.first().map(|&(x, y, _)| Point2::new(x, y)).unwrap_or(Point2::new(0, 0)),
+        distance: 0.0,
+        direction: 0.0,
+        storm_type: String::new(),
+        max_intensity: pixels.iter().map(|&(_, _, intensity)| intensity).max().unwrap_or(0),
+        pixels: pixels.iter().map(|&(x, y, intensity)| Pixel { x, y, color: Rgba([0, 0, 0, 255]), intensity }).collect(),
+    }
+}
+
+// Echoes of a width x height rectangle from (x, y), all at intensity
+pub fn block(x: i32, y: i32, width: i32, height: i32, intensity: u32) -> Vec<(i32, i32, u32)> {
+    (x..x + width).flat_map(|x| (y..y + height).map(move |y| (x, y, intensity))).collect()
+}
+
+// Detection of a frame with no echoes outside of the storms, motion empty for an untracked frame
+pub fn detection_of(source: &str, storms: Vec<Storm>, motion: Vec<StormMotion>) -> Detection {
+    Detection {
+        source: source.to_string(),
+        storms,
+        reflectivity: ReflectivityGrid::from_pixels(&[], (10, 10)),
+        duration: std::time::Duration::ZERO,
+        motion,
+        hierarchy: None,
+    }
+}
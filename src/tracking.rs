@@ -0,0 +1,263 @@
+use chrono::{DateTime, Utc};
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use crate::storm::Storm;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackingConfig {
+    // Fastest plausible storm motion, bounds the matching distance between timed frames
+    pub max_speed_kmh: f64,
+    // Matching distance between frames without a known time, and the least one allowed between timed frames
+    pub max_distance_km: f64,
+    // Frames a track may go unmatched before it ends
+    pub max_missed_frames: usize,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        TrackingConfig {
+            max_speed_kmh: 100.0,
+            max_distance_km: 15.0,
+            max_missed_frames: 1,
+        }
+    }
+}
+
+// Track of a storm and its motion at one frame of a tracked sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StormMotion {
+    pub track_id: u32,
+    // Pixels per hour, see Track::velocity
+    pub velocity: Option<(f64, f64)>,
+}
+
+impl StormMotion {
+    pub fn speed_kmh(&self, km_per_pixel: f64) -> Option<f64> {
+        self.velocity.map(|(vx, vy)| vx.hypot(vy) * km_per_pixel)
+    }
+
+    // Direction the storm moves towards, clockwise from north, None when it does not move
+    pub fn heading_deg(&self) -> Option<f64> {
+        let (vx, vy) = self.velocity?;
+        if vx == 0.0 && vy == 0.0 {
+            return None;
+        }
+        Some(vx.atan2(-vy).to_degrees().rem_euclid(360.0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    pub time: Option<DateTime<Utc>>,
+    // Index of the frame within the tracked sequence
+    pub frame: usize,
+    pub storm_id: u32,
+    pub center: Point2<i32>,
+    pub max_intensity: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub track_id: u32,
+    pub points: Vec<TrackPoint>,
+    missed: usize,
+}
+
+impl Track {
+    pub fn last(&self) -> &TrackPoint {
+        &self.points[self.points.len() - 1]
+    }
+
+    // Motion between the last two timed points in pixels per hour, None for tracks without such points
+    pub fn velocity(&self) -> Option<(f64, f64)> {
+        let count = self.points.len();
+        if count < 2 {
+            return None;
+        }
+        let (previous, last) = (&self.points[count - 2], &self.points[count - 1]);
+        let hours = (last.time? - previous.time?).num_seconds() as f64 / 3600.0;
+        if hours <= 0.0 {
+            return None;
+        }
+        Some((
+            (last.center.x - previous.center.x) as f64 / hours,
+            (last.center.y - previous.center.y) as f64 / hours,
+        ))
+    }
+
+    // Expected position at the given time, the last position when the motion or either time is unknown
+    pub fn predicted_center(&self, time: Option<DateTime<Utc>>) -> Point2<f64> {
+        let last = self.last();
+        let position = Point2::new(last.center.x as f64, last.center.y as f64);
+        match (self.velocity(), last.time, time) {
+            (Some((vx, vy)), Some(last_time), Some(time)) => {
+                let hours = (time - last_time).num_seconds() as f64 / 3600.0;
+                Point2::new(position.x + vx * hours, position.y + vy * hours)
+            }
+            _ => position,
+        }
+    }
+}
+
+// Links storms of consecutive frames into tracks by nearest predicted centroid
+#[derive(Debug, Clone)]
+pub struct StormTracker {
+    config: TrackingConfig,
+    km_per_pixel: f64,
+    tracks: Vec<Track>,
+    next_track_id: u32,
+    frame: usize,
+}
+
+impl StormTracker {
+    pub fn new(config: &TrackingConfig, km_per_pixel: f64) -> Self {
+        StormTracker {
+            config: *config,
+            km_per_pixel,
+            tracks: Vec::new(),
+            next_track_id: 1,
+            frame: 0,
+        }
+    }
+
+    // Add the storms of the next frame and return the track id of every storm in input order.
+    // Closest pairs of live track and storm are matched first, storms left over start new tracks.
+    pub fn update(&mut self, time: Option<DateTime<Utc>>, storms: &[Storm]) -> Vec<u32> {
+        let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+        for (track_index, track) in self.tracks.iter().enumerate() {
+            let max_distance = self.max_distance_px(track.last().time, time);
+            let predicted = track.predicted_center(time);
+            for (storm_index, storm) in storms.iter().enumerate() {
+                let center = Point2::new(storm.intensity_center.x as f64, storm.intensity_center.y as f64);
+                let distance = nalgebra::distance(&predicted, &center);
+                if distance <= max_distance {
+                    candidates.push((distance, track_index, storm_index));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        let mut storm_tracks: Vec<Option<usize>> = vec![None; storms.len()];
+        let mut matched_tracks = vec![false; self.tracks.len()];
+        for (_, track_index, storm_index) in candidates {
+            if matched_tracks[track_index] || storm_tracks[storm_index].is_some() {
+                continue;
+            }
+            matched_tracks[track_index] = true;
+            storm_tracks[storm_index] = Some(track_index);
+        }
+        for (track, matched) in self.tracks.iter_mut().zip(&matched_tracks) {
+            if !matched {
+                track.missed += 1;
+            }
+        }
+
+        let frame = self.frame;
+        self.frame += 1;
+        let track_ids = storms
+            .iter()
+            .zip(storm_tracks)
+            .map(|(storm, track_index)| {
+                let point = TrackPoint {
+                    time,
+                    frame,
+                    storm_id: storm.storm_id,
+                    center: storm.intensity_center,
+                    max_intensity: storm.max_intensity,
+                };
+                match track_index {
+                    Some(track_index) => {
+                        let track = &mut self.tracks[track_index];
+                        track.points.push(point);
+                        track.missed = 0;
+                        track.track_id
+                    }
+                    None => {
+                        let track_id = self.next_track_id;
+                        self.next_track_id += 1;
+                        self.tracks.push(Track {
+                            track_id,
+                            points: vec![point],
+                            missed: 0,
+                        });
+                        track_id
+                    }
+                }
+            })
+            .collect();
+
+        // Ended tracks can never be matched again, dropping them keeps long runs bounded
+        let max_missed_frames = self.config.max_missed_frames;
+        self.tracks.retain(|track| track.missed <= max_missed_frames);
+        track_ids
+    }
+
+    // Live tracks, ended ones are dropped
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn track(&self, track_id: u32) -> Option<&Track> {
+        self.tracks.iter().find(|track| track.track_id == track_id)
+    }
+
+    pub fn motion(&self, track_id: u32) -> StormMotion {
+        StormMotion {
+            track_id,
+            velocity: self.track(track_id).and_then(Track::velocity),
+        }
+    }
+
+    // Allowed distance between the predicted and the observed position, from the time gap when both times are known
+    fn max_distance_px(&self, last_time: Option<DateTime<Utc>>, time: Option<DateTime<Utc>>) -> f64 {
+        let distance_km = match (last_time, time) {
+            (Some(last_time), Some(time)) if time > last_time => {
+                let hours = (time - last_time).num_seconds() as f64 / 3600.0;
+                (self.config.max_speed_kmh * hours).max(self.config.max_distance_km)
+            }
+            _ => self.config.max_distance_km,
+        };
+        distance_km / self.km_per_pixel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::storm_from_pixels;
+
+    fn storm(storm_id: u32, x: i32, y: i32) -> Storm {
+        storm_from_pixels(storm_id, &[(x, y, 50)])
+    }
+
+    fn tracker(max_missed_frames: usize) -> StormTracker {
+        let config = TrackingConfig { max_missed_frames, ..TrackingConfig::default() };
+        StormTracker::new(&config, 1.0)
+    }
+
+    #[test]
+    fn nearest_storms_continue_their_tracks() {
+        let mut tracker = tracker(1);
+        assert_eq!(tracker.update(None, &[storm(1, 10, 10), storm(2, 100, 100)]), vec![1, 2]);
+        assert_eq!(tracker.update(None, &[storm(1, 98, 103), storm(2, 13, 9)]), vec![2, 1]);
+        assert_eq!(tracker.track(1).unwrap().points.len(), 2);
+    }
+
+    #[test]
+    fn tracks_missed_too_often_are_dropped() {
+        let mut tracker = tracker(1);
+        tracker.update(None, &[storm(1, 10, 10), storm(2, 100, 100)]);
+        // Track 1 misses one frame and is kept, then misses a second one and ends
+        tracker.update(None, &[storm(1, 100, 100)]);
+        assert_eq!(tracker.tracks().len(), 2);
+        assert_eq!(tracker.update(None, &[storm(1, 10, 10), storm(2, 100, 100)]), vec![1, 2]);
+        tracker.update(None, &[storm(1, 100, 100)]);
+        tracker.update(None, &[storm(1, 100, 100)]);
+        assert_eq!(tracker.tracks().iter().map(|track| track.track_id).collect::<Vec<_>>(), vec![2]);
+        assert!(tracker.track(1).is_none());
+
+        // A storm at the old position starts a new track
+        assert_eq!(tracker.update(None, &[storm(1, 10, 10), storm(2, 100, 100)]), vec![3, 2]);
+    }
+}
@@ -2,89 +2,171 @@ use reqwest::blocking::get;
 use reqwest::StatusCode;
 use std::fs::File;
 use std::io::copy;
-use chrono::{Local, prelude::*};
-use image::{GenericImageView, Pixel as ImagePixel, Rgba};
-use crate::consts::*;
+use chrono::{Duration, Local, prelude::*};
+use crate::config::{Config, StationConfig};
+use crate::districts::{district_summaries, District};
+use crate::hierarchy::{StormCell, StormHierarchy};
+use crate::poi::proximity_report;
+use crate::severe::severe_indicators;
 use crate::storm::Storm;
+use crate::tracking::StormMotion;
 
-pub fn generate_url() -> String {
+pub fn generate_url(station: &StationConfig) -> String {
     // Get the current UTC time
     let local: DateTime<Utc> = Utc::now();
     println!("utc time: {}", local);
 
-    // Extract components
-    let mut year:i32 = local.year()as i32;
-    let mut month:i32 = local.month() as i32;
-    let mut day:i32 = local.day() as i32;
-    let mut hour:i32 = local.hour() as i32;
-    let mut minute: i32 = (local.minute() as i32/ 6) * 6 - 12; // Ensure minute is non-negative
-    // Adjust hour, day, month, and year if minute is negative
-    if minute < 0 {
-        minute += 60;
-        hour -= 1;
-        if hour < 0 {
-            hour += 24;
-            day -= 1;
-            if day < 1 {
-                // Go to previous month
-                month -= 1;
-                if month < 1 {
-                    month = 12;
-                    year -= 1;
-                }
-                // Determine the last day of the previous month
-                day = Utc.ymd(year, month as u32, 1).num_days_from_ce() as i32;
-            }
-        }
-    }
+    // Latest available frame: round down to the 6 minute scan interval, then step back two scans
+    let scan_time = local - Duration::minutes((local.minute() % 6) as i64 + 12);
+    let entire_url = generate_url_for(&scan_time, station);
+    println!("latest radar image ulr: {}", entire_url);
+    entire_url
+}
+
+// Image url of the scan at the given UTC time, rounded down to the 6 minute scan interval
+pub fn generate_url_for(scan_time: &DateTime<Utc>, station: &StationConfig) -> String {
+    let year = scan_time.year();
+    let month = scan_time.month();
+    let day = scan_time.day();
+    let hour = scan_time.hour();
+    let minute = scan_time.minute() / 6 * 6;
 
     // Format the components into a string
     let formatted_time_former = format!("{:04}{:02}{:02}", year, month, day);
     let formatted_time_latter = format!("{:04}{:02}{:02}{:02}{:02}", year, month, day, hour, minute);
-    let url_head = URL_HEAD;
-    let url_middle = URL_MIDDLE;
-    let url_end = URL_END;
-    let entire_url = format!("{}{}{}{}{}", url_head, formatted_time_former, url_middle, formatted_time_latter, url_end);
-    println!("latest radar image ulr: {}", entire_url);
-    entire_url
+    let url_head = &station.url_head;
+    let url_middle = &station.url_middle;
+    let url_end = &station.url_end;
+    format!("{}{}{}{}{}", url_head, formatted_time_former, url_middle, formatted_time_latter, url_end)
 }
 
 
-pub fn print_storms(storm_list: &[Storm]) {
+// Frame time encoded in radar image names as a UTC yyyyMMddHHmm field, e.g. Z_RADR_I_Z9200_202404241348_P_...
+pub fn parse_frame_time(image_path: &str) -> Option<DateTime<Utc>> {
+    let file_name = std::path::Path::new(image_path).file_name()?.to_str()?;
+    file_name
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|field| field.len() == 12)
+        .find_map(|field| NaiveDateTime::parse_from_str(field, "%Y%m%d%H%M").ok())
+        .map(|time| time.and_utc())
+}
+
+pub fn print_storms(storm_list: &[Storm], config: &Config) {
     let local = Local::now();
-    println!("Observe Station: GuangZhou");
+    let km_per_pixel = config.station.km_per_pixel;
+    println!("Observe Station: {}", config.station.name);
     println!("Process Time: {}", local);
     println!("Storm number in active: {}", storm_list.len());
     // Print header
-    println!("{:<8} {:<15} {:<10} {:<20} {:<10}", "ID", "Distance (km)", "Compass", "Max Intensity (dBZ)", "Type");
+    println!(
+        "{:<8} {:<15} {:<10} {:<20} {:<14} {:<10} {:<8} {:<10}",
+        "ID", "Distance (km)", "Compass", "Max Intensity (dBZ)", "Type", ">=55 (km2)", "Hail", "Severe"
+    );
 
     // Print storm data
     for storm in storm_list {
+        let severe = severe_indicators(storm, &config.severe, km_per_pixel);
         println!(
-            "{:<8} {:<15.2} {:<10} {:<20} {:<10}",
+            "{:<8} {:<15.2} {:<10} {:<20} {:<14} {:<10.1} {:<8} {:<10}",
             storm.storm_id,
             storm.distance,
             azimuth_to_direction(storm.direction),
             storm.max_intensity,
-            storm.storm_type
+            storm.storm_type,
+            severe.area_55_km2,
+            format!("{:.0}%", severe.hail_probability * 100.0),
+            if severe.severe_core { "CORE" } else { "" }
+        );
+    }
+}
+
+// Storms inside the radius of a point of interest or expected there soon, nothing when there are none
+pub fn print_poi_alerts(storm_list: &[Storm], motion: &[StormMotion], config: &Config) {
+    let alerts: Vec<_> = proximity_report(storm_list, motion, &config.poi, &config.station)
+        .into_iter()
+        .filter(|proximity| proximity.is_alert())
+        .collect();
+    if alerts.is_empty() {
+        return;
+    }
+    println!("Point of interest alerts: {}", alerts.len());
+    println!("{:<20} {:<8} {:<15} {:<12} {:<10} {:<10}", "POI", "Storm", "Distance (km)", "Bearing", "Status", "ETA (min)");
+    for alert in &alerts {
+        println!(
+            "{:<20} {:<8} {:<15.2} {:<12} {:<10} {:<10}",
+            alert.poi,
+            alert.storm_id,
+            alert.edge_distance_km,
+            format!("{:.0} {}", alert.bearing_deg, alert.compass),
+            if alert.inside { "INSIDE" } else { "ARRIVING" },
+            alert.eta_minutes.filter(|_| alert.arriving).map(|eta| format!("{:.0}", eta)).unwrap_or_default()
+        );
+    }
+}
+
+// One line per district with the storms over it, nothing without districts
+pub fn print_district_summary(storm_list: &[Storm], districts: &[District], config: &Config) {
+    if districts.is_empty() {
+        return;
+    }
+    println!("Districts: {}", districts.len());
+    println!("{:<24} {:<8} {:<14} {:<20} {:<20}", "District", "Storms", "Area (km2)", "Max Intensity (dBZ)", "Storm IDs");
+    for summary in district_summaries(storm_list, districts, config.station.km_per_pixel) {
+        let storm_ids: Vec<String> = summary.storms.iter().map(|storm| storm.storm_id.to_string()).collect();
+        println!(
+            "{:<24} {:<8} {:<14.1} {:<20} {:<20}",
+            summary.district,
+            summary.storm_count,
+            summary.overlap_km2,
+            if summary.storm_count > 0 { summary.max_dbz.to_string() } else { String::from("-") },
+            storm_ids.join(",")
         );
     }
 }
 
+pub fn print_storm_hierarchy(hierarchy: &StormHierarchy) {
+    println!("Storm complexes: {}", hierarchy.roots().count());
+    // Print header
+    println!(
+        "{:<18} {:<8} {:<10} {:<12} {:<15} {:<10} {:<20} {:<8}",
+        "ID", "Parent", "Kind", "Threshold", "Distance (km)", "Compass", "Max Intensity (dBZ)", "Pixels"
+    );
+
+    for root in hierarchy.roots() {
+        print_storm_cell(hierarchy, root, 0);
+    }
+}
+
+fn print_storm_cell(hierarchy: &StormHierarchy, cell: &StormCell, depth: usize) {
+    let id = format!("{}#{}", "  ".repeat(depth), cell.cell_id);
+    println!(
+        "{:<18} {:<8} {:<10} {:<12} {:<15.2} {:<10} {:<20} {:<8}",
+        id,
+        cell.parent_id.map(|parent_id| format!("#{}", parent_id)).unwrap_or_else(|| String::from("-")),
+        cell.kind.as_str(),
+        format!(">= {}", cell.threshold),
+        cell.distance,
+        azimuth_to_direction(cell.direction),
+        cell.max_intensity,
+        cell.pixels.len()
+    );
+    for child in hierarchy.children_of(cell.cell_id) {
+        print_storm_cell(hierarchy, child, depth + 1);
+    }
+}
 
-pub fn download_radar_image(image_url:&String, local_image_path:&str)
+pub fn download_radar_image(image_url: &str, local_image_path: &str)
 {
-    match download_image(&image_url, local_image_path) {
+    match download_image(image_url, local_image_path) {
         Ok(_) => println!("Image downloaded successfully."),
         Err(e) => {
             eprintln!("Error downloading image: {}", e);
-            return;
         }
     }
 }
 
 
-fn download_image(url: &str, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn download_image(url: &str, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let response = get(url)?;
 
     if response.status() != StatusCode::OK {
@@ -99,7 +181,7 @@ fn download_image(url: &str, file_path: &str) -> Result<(), Box<dyn std::error::
 }
 
 
-fn azimuth_to_direction(azimuth: f64) -> &'static str {
+pub(crate) fn azimuth_to_direction(azimuth: f64) -> &'static str {
     match azimuth {
         a if (0.0..=22.5).contains(&a) || (337.5..=360.0).contains(&a) => "N",
         a if (22.5..=67.5).contains(&a) => "NE",
@@ -112,25 +194,3 @@ fn azimuth_to_direction(azimuth: f64) -> &'static str {
         _ => "Unknown",
     }
 }
-
-
-pub fn copy_legend(radar_img_path: &str, base_img_path: &str) {
-    let radar_img = image::open(radar_img_path).expect("Failed to open radar image");
-    let mut base_img = image::open(base_img_path).expect("Failed to open base image").to_rgba8();;
-
-    let (radar_width, radar_height) = radar_img.dimensions();
-    let (base_width, base_height) = base_img.dimensions();
-
-    if radar_width != base_width || radar_height != base_height {
-        panic!("Image dimensions do not match");
-    }
-
-    for x in radar_height..radar_width {
-        for y in 0..radar_height {
-            let pixel_color = radar_img.get_pixel(x, y).to_rgba();
-            base_img.put_pixel(x, y, pixel_color);
-        }
-    }
-
-    base_img.save(base_img_path).expect("Failed to save legend image");
-}
\ No newline at end of file
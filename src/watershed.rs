@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatershedParams {
+    // A peak must rise this many dBZ above the saddle joining it to a higher peak
+    pub min_prominence: u32,
+    // Peaks closer than this many pixels are kept in one cell
+    pub min_separation: f64,
+}
+
+impl Default for WatershedParams {
+    fn default() -> Self {
+        WatershedParams {
+            min_prominence: 10,
+            min_separation: 8.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentationMode {
+    // Every connected component is one storm
+    #[default]
+    FloodFill,
+    // Connected components are split at the saddles between reflectivity maxima
+    Watershed(WatershedParams),
+}
+
+struct Basin {
+    parent: usize,
+    peak: u32,
+    // Sums over the pixels at the peak intensity, to locate the peak plateau center
+    peak_x: f64,
+    peak_y: f64,
+    peak_count: f64,
+}
+
+impl Basin {
+    fn peak_position(&self) -> (f64, f64) {
+        (self.peak_x / self.peak_count, self.peak_y / self.peak_count)
+    }
+}
+
+fn find_root(basins: &mut [Basin], basin: usize) -> usize {
+    let mut root = basin;
+    while basins[root].parent != root {
+        root = basins[root].parent;
+    }
+    // Path compression
+    let mut current = basin;
+    while basins[current].parent != root {
+        let next = basins[current].parent;
+        basins[current].parent = root;
+        current = next;
+    }
+    root
+}
+
+// Split one connected component into cells by flooding from its local maxima downwards.
+// Basins meeting at a saddle are merged unless the lower one is prominent and far enough away.
+pub fn split_component(component: &[Pixel], params: &WatershedParams, adjacent_threshold: i32) -> Vec<Vec<Pixel>> {
+    let index: HashMap<(i32, i32), usize> = component
+        .iter()
+        .enumerate()
+        .map(|(i, pixel)| ((pixel.x, pixel.y), i))
+        .collect();
+
+    let mut order: Vec<usize> = (0..component.len()).collect();
+    order.sort_by(|&a, &b| component[b].intensity.cmp(&component[a].intensity));
+
+    let mut labels: Vec<Option<usize>> = vec![None; component.len()];
+    let mut basins: Vec<Basin> = Vec::new();
+
+    for &i in &order {
+        let pixel = component[i];
+        let level = pixel.intensity;
+
+        // Basins already flooded around this pixel
+        let mut neighbor_roots = Vec::new();
+        for dx in -adjacent_threshold..=adjacent_threshold {
+            for dy in -adjacent_threshold..=adjacent_threshold {
+                if let Some(&neighbor) = index.get(&(pixel.x + dx, pixel.y + dy)) {
+                    if let Some(basin) = labels[neighbor] {
+                        let root = find_root(&mut basins, basin);
+                        if !neighbor_roots.contains(&root) {
+                            neighbor_roots.push(root);
+                        }
+                    }
+                }
+            }
+        }
+
+        let basin = if neighbor_roots.is_empty() {
+            // A new local maximum
+            basins.push(Basin {
+                parent: basins.len(),
+                peak: level,
+                peak_x: 0.0,
+                peak_y: 0.0,
+                peak_count: 0.0,
+            });
+            basins.len() - 1
+        } else {
+            // The highest neighboring peak dominates, lower ones either merge into it or stay apart
+            neighbor_roots.sort_by(|&a, &b| basins[b].peak.cmp(&basins[a].peak));
+            let dominant = neighbor_roots[0];
+            for &other in &neighbor_roots[1..] {
+                if should_merge(&basins[dominant], &basins[other], level, params) {
+                    basins[other].parent = dominant;
+                    if basins[other].peak == basins[dominant].peak {
+                        basins[dominant].peak_x += basins[other].peak_x;
+                        basins[dominant].peak_y += basins[other].peak_y;
+                        basins[dominant].peak_count += basins[other].peak_count;
+                    }
+                }
+            }
+            dominant
+        };
+
+        if basins[basin].peak == level {
+            basins[basin].peak_x += pixel.x as f64;
+            basins[basin].peak_y += pixel.y as f64;
+            basins[basin].peak_count += 1.0;
+        }
+        labels[i] = Some(basin);
+    }
+
+    let mut groups: Vec<Vec<Pixel>> = Vec::new();
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    for (i, label) in labels.iter().enumerate() {
+        if let Some(basin) = label {
+            let root = find_root(&mut basins, *basin);
+            let group = *group_of_root.entry(root).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push(component[i]);
+        }
+    }
+
+    groups
+}
+
+fn should_merge(dominant: &Basin, other: &Basin, level: u32, params: &WatershedParams) -> bool {
+    let prominence = other.peak.saturating_sub(level);
+    if prominence == 0 || prominence < params.min_prominence {
+        return true;
+    }
+    let (dominant_x, dominant_y) = dominant.peak_position();
+    let (other_x, other_y) = other.peak_position();
+    let separation = ((dominant_x - other_x).powi(2) + (dominant_y - other_y).powi(2)).sqrt();
+    separation < params.min_separation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    // Two touching cells along x: a 60 dBZ peak at x = 5 and a 55 dBZ peak at x = 24, joined by a 30 dBZ saddle
+    fn two_peaks() -> Vec<Pixel> {
+        (0..30i32)
+            .flat_map(|x| (0..3).map(move |y| (x, y)))
+            .map(|(x, y)| {
+                let intensity = (60 - 5 * (x - 5).abs()).max(55 - 5 * (x - 24).abs()).max(30) as u32;
+                Pixel { x, y, color: Rgba([0, 0, 0, 255]), intensity }
+            })
+            .collect()
+    }
+
+    fn split(params: WatershedParams) -> Vec<Vec<Pixel>> {
+        let component = two_peaks();
+        let groups = split_component(&component, &params, 1);
+        assert_eq!(groups.iter().map(Vec::len).sum::<usize>(), component.len());
+        groups
+    }
+
+    #[test]
+    fn touching_cells_are_split_at_the_saddle() {
+        let groups = split(WatershedParams { min_prominence: 10, min_separation: 8.0 });
+        assert_eq!(groups.len(), 2);
+        for (peak_x, peak) in [(5, 60), (24, 55)] {
+            let group = groups.iter().find(|group| group.iter().any(|pixel| pixel.x == peak_x)).unwrap();
+            assert_eq!(group.iter().map(|pixel| pixel.intensity).max(), Some(peak));
+            assert!(group.iter().all(|pixel| (pixel.x - peak_x).abs() < 15));
+        }
+    }
+
+    #[test]
+    fn peak_below_min_prominence_is_merged() {
+        // The lower peak rises 25 dBZ above the saddle
+        assert_eq!(split(WatershedParams { min_prominence: 25, min_separation: 8.0 }).len(), 2);
+        assert_eq!(split(WatershedParams { min_prominence: 30, min_separation: 8.0 }).len(), 1);
+    }
+
+    #[test]
+    fn peaks_closer_than_min_separation_are_merged() {
+        // The peaks lie 19 pixels apart
+        assert_eq!(split(WatershedParams { min_prominence: 10, min_separation: 19.0 }).len(), 2);
+        assert_eq!(split(WatershedParams { min_prominence: 10, min_separation: 20.0 }).len(), 1);
+    }
+}
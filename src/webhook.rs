@@ -0,0 +1,449 @@
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::config::Config;
+use crate::geo::pixel_to_lat_lon;
+use crate::pipeline::Detection;
+use crate::poi::proximity_report;
+use crate::utils::{azimuth_to_direction, parse_frame_time};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    // First frame of a storm at or above min_dbz
+    NewStorm,
+    // Higher max_intensity than in the previous frame of the storm, at or above min_dbz
+    Intensified,
+    // An echo of the storm came within the radius of a point of interest
+    EnteredPoi,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    // Event kinds posted to this endpoint, all when left out
+    #[serde(default = "all_event_kinds")]
+    pub events: Vec<WebhookEventKind>,
+}
+
+fn all_event_kinds() -> Vec<WebhookEventKind> {
+    vec![WebhookEventKind::NewStorm, WebhookEventKind::Intensified, WebhookEventKind::EnteredPoi]
+}
+
+impl WebhookEndpoint {
+    // Endpoint receiving every event kind
+    pub fn new(url: &str) -> Self {
+        WebhookEndpoint {
+            url: url.to_string(),
+            events: all_event_kinds(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub endpoints: Vec<WebhookEndpoint>,
+    pub min_dbz: u32,
+    // Message text per event kind, {field} is replaced by the field of the event
+    pub new_storm_template: String,
+    pub intensified_template: String,
+    pub entered_poi_template: String,
+    // Posts per endpoint within any minute, further events are dropped
+    pub max_per_minute: u32,
+    // Connection errors, timeouts, 408, 429 and 5xx answers are retried with a doubling delay
+    pub max_retries: u32,
+    pub retry_delay_ms: u64,
+    pub timeout_seconds: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            enabled: false,
+            endpoints: Vec::new(),
+            min_dbz: 50,
+            new_storm_template: String::from("New storm {storm_id} of {max_intensity} dBZ {distance_km} km {compass} of {station}"),
+            intensified_template: String::from("Storm {storm_id} intensified from {previous_intensity} to {max_intensity} dBZ, {distance_km} km {compass} of {station}"),
+            entered_poi_template: String::from("Storm {storm_id} of {max_intensity} dBZ entered the radius of {poi}"),
+            max_per_minute: 20,
+            max_retries: 3,
+            retry_delay_ms: 1000,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookEvent {
+    pub kind: WebhookEventKind,
+    pub station: String,
+    pub source: String,
+    pub frame_time: Option<DateTime<Utc>>,
+    pub storm_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_id: Option<u32>,
+    pub max_intensity: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_intensity: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poi: Option<String>,
+    pub distance_km: f64,
+    pub compass: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeliveryReport {
+    pub delivered: usize,
+    pub rate_limited: usize,
+    pub failed: usize,
+}
+
+// Event state across frames and the outbound posts. Storms are followed by track id, or by storm id in untracked frames.
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    config: WebhookConfig,
+    client: Client,
+    // Max intensity of every storm in the previous frame
+    intensities: BTreeMap<u32, u32>,
+    // Storms inside a point of interest radius in the previous frame
+    inside: BTreeSet<(u32, String)>,
+    // Post times within the rate window, per endpoint
+    posts: Vec<VecDeque<Instant>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Result<Self, Box<dyn Error>> {
+        let client = Client::builder().timeout(Duration::from_secs(config.timeout_seconds)).build()?;
+        Ok(WebhookNotifier {
+            config: config.clone(),
+            client,
+            intensities: BTreeMap::new(),
+            inside: BTreeSet::new(),
+            posts: vec![VecDeque::new(); config.endpoints.len()],
+        })
+    }
+
+    // Events of the next frame, frames must be passed in time order
+    pub fn events(&mut self, detection: &Detection, config: &Config) -> Vec<WebhookEvent> {
+        let station = &config.station;
+        let proximity = proximity_report(&detection.storms, &detection.motion, &config.poi, station);
+        let frame_time = parse_frame_time(&detection.source);
+        let mut events = Vec::new();
+        let mut intensities = BTreeMap::new();
+        let mut inside = BTreeSet::new();
+        for (index, storm) in detection.storms.iter().enumerate() {
+            let track_id = detection.motion.get(index).map(|motion| motion.track_id);
+            let key = track_id.unwrap_or(storm.storm_id);
+            let (latitude, longitude) = pixel_to_lat_lon(storm.intensity_center.x as f64, storm.intensity_center.y as f64, station);
+            let event = |kind: WebhookEventKind, previous_intensity: Option<u32>, poi: Option<String>| WebhookEvent {
+                kind,
+                station: station.name.clone(),
+                source: detection.source.clone(),
+                frame_time,
+                storm_id: storm.storm_id,
+                track_id,
+                max_intensity: storm.max_intensity,
+                previous_intensity,
+                poi,
+                distance_km: storm.distance,
+                compass: azimuth_to_direction(storm.direction).to_string(),
+                latitude,
+                longitude,
+            };
+
+            let previous = self.intensities.get(&key).copied();
+            intensities.insert(key, storm.max_intensity);
+            if storm.max_intensity >= self.config.min_dbz {
+                match previous {
+                    None => events.push(event(WebhookEventKind::NewStorm, None, None)),
+                    Some(previous) if storm.max_intensity > previous => {
+                        events.push(event(WebhookEventKind::Intensified, Some(previous), None))
+                    }
+                    Some(_) => {}
+                }
+            }
+            for entry in proximity.iter().filter(|entry| entry.storm_id == storm.storm_id && entry.inside) {
+                let entered = (key, entry.poi.clone());
+                if !self.inside.contains(&entered) {
+                    events.push(event(WebhookEventKind::EnteredPoi, None, Some(entry.poi.clone())));
+                }
+                inside.insert(entered);
+            }
+        }
+        self.intensities = intensities;
+        self.inside = inside;
+        events
+    }
+
+    // Post every event to the endpoints subscribed to its kind as {"text": <message>, "event": <event>}
+    pub fn notify(&mut self, events: &[WebhookEvent]) -> DeliveryReport {
+        let mut report = DeliveryReport::default();
+        for event in events {
+            let template = match event.kind {
+                WebhookEventKind::NewStorm => &self.config.new_storm_template,
+                WebhookEventKind::Intensified => &self.config.intensified_template,
+                WebhookEventKind::EnteredPoi => &self.config.entered_poi_template,
+            };
+            let payload = json!({ "text": render_template(template, event), "event": event }).to_string();
+            for (endpoint, posts) in self.config.endpoints.iter().zip(&mut self.posts) {
+                if !endpoint.events.contains(&event.kind) {
+                    continue;
+                }
+                let now = Instant::now();
+                while posts.front().is_some_and(|&posted| now.duration_since(posted) >= RATE_WINDOW) {
+                    posts.pop_front();
+                }
+                if posts.len() >= self.config.max_per_minute as usize {
+                    report.rate_limited += 1;
+                    continue;
+                }
+                posts.push_back(now);
+                match post_with_retry(&self.client, &endpoint.url, &payload, &self.config) {
+                    Ok(()) => report.delivered += 1,
+                    Err(e) => {
+                        eprintln!("Webhook {} failed: {}", endpoint.url, e);
+                        report.failed += 1;
+                    }
+                }
+            }
+        }
+        report
+    }
+}
+
+// Replace every {field} of the template by the field of the event, absent fields by nothing
+pub fn render_template(template: &str, event: &WebhookEvent) -> String {
+    let mut text = template.to_string();
+    let Ok(Value::Object(fields)) = serde_json::to_value(event) else {
+        return text;
+    };
+    for (name, value) in &fields {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            Value::Number(number) if number.is_f64() => format!("{:.1}", number.as_f64().unwrap_or(0.0)),
+            other => other.to_string(),
+        };
+        text = text.replace(&format!("{{{}}}", name), &value);
+    }
+    for name in ["track_id", "previous_intensity", "poi", "frame_time"] {
+        text = text.replace(&format!("{{{}}}", name), "");
+    }
+    text
+}
+
+fn post_with_retry(client: &Client, url: &str, payload: &str, config: &WebhookConfig) -> Result<(), String> {
+    let mut delay = Duration::from_millis(config.retry_delay_ms);
+    let mut attempt = 0;
+    loop {
+        let error = match client.post(url).header(CONTENT_TYPE, "application/json").body(payload.to_string()).send() {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if !is_retryable(response.status()) => return Err(format!("answered {}", response.status())),
+            Ok(response) => format!("answered {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= config.max_retries {
+            return Err(format!("{} after {} attempts", error, attempt + 1));
+        }
+        attempt += 1;
+        thread::sleep(delay);
+        delay *= 2;
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use crate::poi::PointOfInterest;
+    use crate::storm::Storm;
+    use crate::test_support::{detection_of, storm_from_pixels};
+    use crate::tracking::StormMotion;
+
+    // Answers one request per status with that status and sends every request body to the returned receiver
+    fn mock_server(statuses: Vec<u16>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                sender.send(String::from_utf8(body).unwrap()).unwrap();
+                let response = format!("HTTP/1.1 {} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn config(urls: &[&str]) -> WebhookConfig {
+        WebhookConfig {
+            enabled: true,
+            endpoints: urls.iter().map(|url| WebhookEndpoint::new(url)).collect(),
+            retry_delay_ms: 1,
+            ..WebhookConfig::default()
+        }
+    }
+
+    fn event(kind: WebhookEventKind) -> WebhookEvent {
+        WebhookEvent {
+            kind,
+            station: String::from("GuangZhou"),
+            source: String::from("frame.png"),
+            frame_time: None,
+            storm_id: 3,
+            track_id: Some(7),
+            max_intensity: 55,
+            previous_intensity: Some(50),
+            poi: None,
+            distance_km: 42.345,
+            compass: String::from("NE"),
+            latitude: 23.0,
+            longitude: 113.0,
+        }
+    }
+
+    fn storm(storm_id: u32, center: (i32, i32), max_intensity: u32) -> Storm {
+        storm_from_pixels(storm_id, &[(center.0, center.1, max_intensity)])
+    }
+
+    fn detection(storms: Vec<Storm>, track_ids: &[u32]) -> Detection {
+        let motion = track_ids.iter().map(|&track_id| StormMotion { track_id, velocity: None }).collect();
+        detection_of("frame.png", storms, motion)
+    }
+
+    #[test]
+    fn template_fills_event_fields() {
+        let text = render_template("{storm_id} {max_intensity} dBZ {distance_km} km {compass} {poi}|", &event(WebhookEventKind::NewStorm));
+        assert_eq!(text, "3 55 dBZ 42.3 km NE |");
+    }
+
+    #[test]
+    fn posts_templated_json() {
+        let (url, requests) = mock_server(vec![200]);
+        let mut notifier = WebhookNotifier::new(&config(&[&url])).unwrap();
+        let report = notifier.notify(&[event(WebhookEventKind::Intensified)]);
+        assert_eq!(report, DeliveryReport { delivered: 1, rate_limited: 0, failed: 0 });
+
+        let body: Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+        assert_eq!(body["text"], "Storm 3 intensified from 50 to 55 dBZ, 42.3 km NE of GuangZhou");
+        assert_eq!(body["event"]["kind"], "intensified");
+        assert_eq!(body["event"]["track_id"], 7);
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let (url, requests) = mock_server(vec![503, 500, 200]);
+        let mut notifier = WebhookNotifier::new(&config(&[&url])).unwrap();
+        let report = notifier.notify(&[event(WebhookEventKind::NewStorm)]);
+        assert_eq!(report.delivered, 1);
+        assert_eq!(requests.iter().take(3).count(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries_and_on_client_errors() {
+        let (url, requests) = mock_server(vec![500, 500, 404]);
+        let mut webhook_config = config(&[&url]);
+        webhook_config.max_retries = 1;
+        let mut notifier = WebhookNotifier::new(&webhook_config).unwrap();
+        let report = notifier.notify(&[event(WebhookEventKind::NewStorm), event(WebhookEventKind::NewStorm)]);
+        assert_eq!(report.failed, 2);
+        // Two attempts for the first event, the 404 of the second is not retried
+        assert_eq!(requests.iter().take(3).count(), 3);
+    }
+
+    #[test]
+    fn rate_limit_drops_excess_posts() {
+        let (url, requests) = mock_server(vec![200, 200]);
+        let mut webhook_config = config(&[&url]);
+        webhook_config.max_per_minute = 2;
+        let mut notifier = WebhookNotifier::new(&webhook_config).unwrap();
+        let events = vec![event(WebhookEventKind::NewStorm); 3];
+        let report = notifier.notify(&events);
+        assert_eq!(report, DeliveryReport { delivered: 2, rate_limited: 1, failed: 0 });
+        assert_eq!(requests.iter().take(2).count(), 2);
+    }
+
+    #[test]
+    fn endpoints_only_receive_subscribed_events() {
+        let (url, requests) = mock_server(vec![200]);
+        let mut webhook_config = config(&[&url]);
+        webhook_config.endpoints[0].events = vec![WebhookEventKind::EnteredPoi];
+        let mut notifier = WebhookNotifier::new(&webhook_config).unwrap();
+        let report = notifier.notify(&[event(WebhookEventKind::NewStorm), event(WebhookEventKind::EnteredPoi)]);
+        assert_eq!(report.delivered, 1);
+        let body: Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+        assert_eq!(body["event"]["kind"], "entered_poi");
+    }
+
+    #[test]
+    fn events_follow_storms_across_frames() {
+        let mut config = Config::default();
+        config.poi.points.push(PointOfInterest {
+            name: String::from("Port"),
+            latitude: None,
+            longitude: None,
+            pixel: Some([100.0, 100.0]),
+            radius_km: 5.0,
+        });
+        let mut notifier = WebhookNotifier::new(&config.webhook).unwrap();
+        let kinds = |events: Vec<WebhookEvent>| events.into_iter().map(|event| (event.kind, event.track_id)).collect::<Vec<_>>();
+
+        // Storm 2 stays below min_dbz
+        let first = detection(vec![storm(1, (10, 10), 50), storm(2, (50, 50), 45)], &[1, 2]);
+        assert_eq!(kinds(notifier.events(&first, &config)), vec![(WebhookEventKind::NewStorm, Some(1))]);
+
+        let second = detection(vec![storm(1, (12, 10), 55), storm(2, (101, 100), 45)], &[1, 2]);
+        assert_eq!(
+            kinds(notifier.events(&second, &config)),
+            vec![(WebhookEventKind::Intensified, Some(1)), (WebhookEventKind::EnteredPoi, Some(2))]
+        );
+
+        // Already inside, weaker and unchanged storms give no events
+        let third = detection(vec![storm(1, (14, 10), 50), storm(2, (100, 101), 45)], &[1, 2]);
+        assert!(notifier.events(&third, &config).is_empty());
+
+        // Only storms of the previous frame are remembered, a track that comes back is new again
+        let fourth = detection(vec![storm(2, (100, 101), 45)], &[2]);
+        assert!(notifier.events(&fourth, &config).is_empty());
+        assert_eq!(notifier.intensities.len(), 1);
+        let fifth = detection(vec![storm(1, (16, 10), 50), storm(2, (100, 101), 45)], &[1, 2]);
+        assert_eq!(kinds(notifier.events(&fifth, &config)), vec![(WebhookEventKind::NewStorm, Some(1))]);
+    }
+}